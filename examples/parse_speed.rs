@@ -0,0 +1,80 @@
+//! Compares parsing speed of the default and `fast` backends on a generated extract.
+//!
+//! ```sh
+//! cargo run --release --features fast --example parse_speed [nodes]
+//! ```
+
+extern crate osm_xml as osm;
+
+use std::env;
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use osm::OSM;
+
+fn generate(nodes: u64) -> String {
+    let mut doc = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    doc.push_str("<osm version=\"0.6\">\n");
+    doc.push_str(" <bounds minlat=\"60.0\" minlon=\"24.0\" maxlat=\"61.0\" maxlon=\"25.0\"/>\n");
+
+    for id in 1..nodes + 1 {
+        write!(
+            doc,
+            " <node id=\"{}\" version=\"2\" changeset=\"{}\" timestamp=\"2018-01-01T00:00:00Z\" \
+             user=\"mapper\" uid=\"{}\" visible=\"true\" lat=\"{:.7}\" lon=\"{:.7}\"",
+            id,
+            1000 + id / 100,
+            id % 50,
+            60.0 + (id % 10000) as f64 * 1e-4,
+            24.0 + (id / 10000) as f64 * 1e-4
+        ).unwrap();
+
+        if id % 10 == 0 {
+            doc.push_str(">\n  <tag k=\"highway\" v=\"crossing\"/>\n </node>\n");
+        } else {
+            doc.push_str("/>\n");
+        }
+    }
+
+    for id in 1..nodes / 10 + 1 {
+        writeln!(doc, " <way id=\"{}\" version=\"1\" changeset=\"1\" user=\"mapper\">", id)
+            .unwrap();
+        for node in 0..10 {
+            writeln!(doc, "  <nd ref=\"{}\"/>", (id - 1) * 10 + node + 1).unwrap();
+        }
+        doc.push_str("  <tag k=\"highway\" v=\"residential\"/>\n");
+        writeln!(doc, "  <tag k=\"name\" v=\"Street {}\"/>\n </way>", id).unwrap();
+    }
+
+    doc.push_str("</osm>\n");
+    doc
+}
+
+fn time<F: Fn() -> OSM>(parse: F) -> (Duration, OSM) {
+    let start = Instant::now();
+    let osm = parse();
+    (start.elapsed(), osm)
+}
+
+fn main() {
+    let nodes = env::args()
+        .nth(1)
+        .map(|nodes| nodes.parse().expect("node count"))
+        .unwrap_or(500_000);
+
+    let doc = generate(nodes);
+    println!("{} nodes, {} MB", nodes, doc.len() / 1_000_000);
+
+    let (xml_time, xml) = time(|| OSM::parse(doc.as_bytes()).unwrap());
+    let (fast_time, fast) = time(|| OSM::parse_fast(doc.as_bytes()).unwrap());
+
+    assert_eq!(xml.nodes, fast.nodes);
+    assert_eq!(xml.ways, fast.ways);
+
+    println!("xml-rs:    {:?}", xml_time);
+    println!("quick-xml: {:?}", fast_time);
+    println!(
+        "speedup:   {:.1}x",
+        xml_time.as_secs_f64() / fast_time.as_secs_f64()
+    );
+}
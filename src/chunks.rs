@@ -0,0 +1,243 @@
+//! Splitting documents at top level elements so that the parts can be read separately, used by
+//! the parallel and async parsers and the element index.
+
+use std::io::Read;
+
+use elements::{Area, Bounds, Count, Element, ElementId, Tag};
+use error::Error;
+use reader::ReaderBuilder;
+use symbols::Symbols;
+
+/// Finds top level elements of a document, ie. the children of `<osm>`.
+///
+/// Tracks element depth, skipping over comments, CDATA, processing instructions and the doctype
+/// declaration, so markup-like content in them doesn't affect the result. Data can be scanned as
+/// it arrives, scanning stops before incomplete markup and continues from it with more data.
+pub(crate) struct Scanner {
+    depth: usize,
+    pos: usize,
+}
+
+impl Scanner {
+    pub(crate) fn new() -> Scanner {
+        Scanner { depth: 0, pos: 0 }
+    }
+
+    /// Offset of the next top level element in `data`, `None` when there are no more complete
+    /// ones. `data` must start with the data scanned so far.
+    pub(crate) fn next_element(&mut self, data: &[u8]) -> Option<usize> {
+        while let Some(offset) = data[self.pos..].iter().position(|&b| b == b'<') {
+            let start = self.pos + offset;
+            let rest = &data[start..];
+            if is_incomplete(rest) {
+                self.pos = start;
+                return None;
+            }
+
+            let mut element = None;
+            let end = if rest.starts_with(b"<?") {
+                find(data, start, b"?>")
+            } else if rest.starts_with(b"<!--") {
+                find(data, start, b"-->")
+            } else if rest.starts_with(b"<![CDATA[") {
+                find(data, start, b"]]>")
+            } else if rest.starts_with(b"<!") {
+                declaration_end(data, start)
+            } else if rest.starts_with(b"</") {
+                let end = find(data, start, b">");
+                if end.is_some() {
+                    self.depth = self.depth.saturating_sub(1);
+                }
+                end
+            } else {
+                let end = tag_end(data, start);
+                if let Some(end) = end {
+                    if self.depth == 1 {
+                        element = Some(start);
+                    }
+                    if data[end - 1] != b'/' {
+                        self.depth += 1;
+                    }
+                }
+                end
+            };
+
+            match end {
+                Some(end) => self.pos = end + 1,
+                None => {
+                    self.pos = start;
+                    return None;
+                }
+            }
+
+            if element.is_some() {
+                return element;
+            }
+        }
+
+        self.pos = data.len();
+        None
+    }
+
+    /// Offset up to which the data has been scanned, earlier data is not needed anymore.
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Adjusts the scanner after `len` bytes have been removed from the start of the data.
+    pub(crate) fn consume(&mut self, len: usize) {
+        self.pos -= len;
+    }
+}
+
+/// Whether there's too little data to tell what kind of markup starts here.
+fn is_incomplete(rest: &[u8]) -> bool {
+    rest.len() < 2 || (rest.len() < 4 && b"<!--".starts_with(rest)) ||
+        (rest.len() < 9 && b"<![CDATA[".starts_with(rest))
+}
+
+/// Offset of the end of `needle` starting at or after `from`.
+fn find(data: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    data[from..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|offset| from + offset + needle.len() - 1)
+}
+
+/// Offset of the closing `>` of a start tag, attribute values may contain `>`.
+pub(crate) fn tag_end(data: &[u8], start: usize) -> Option<usize> {
+    let mut quote = None;
+
+    for (offset, &b) in data[start..].iter().enumerate() {
+        match quote {
+            Some(q) if b == q => quote = None,
+            Some(_) => {}
+            None if b == b'"' || b == b'\'' => quote = Some(b),
+            None if b == b'>' => return Some(start + offset),
+            None => {}
+        }
+    }
+
+    None
+}
+
+/// Offset of the end of `<!DOCTYPE ...>`, which may have an internal subset in brackets.
+fn declaration_end(data: &[u8], start: usize) -> Option<usize> {
+    let mut brackets = 0usize;
+
+    for (offset, &b) in data[start..].iter().enumerate() {
+        match b {
+            b'[' => brackets += 1,
+            b']' => brackets = brackets.saturating_sub(1),
+            b'>' if brackets == 0 => return Some(start + offset),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+/// Elements and other results of a part of the document, in document order.
+pub(crate) struct Chunk {
+    pub(crate) elements: Vec<Element>,
+    /// `Some` when the chunk had `<bounds>`, which may have been invalid
+    pub(crate) bounds: Option<Option<Bounds>>,
+    pub(crate) areas: Vec<Area>,
+    pub(crate) counts: Vec<Count>,
+    pub(crate) duplicate_tags: Vec<(ElementId, Tag)>,
+    /// Symbol table given to `read_chunk`, with the strings of the chunk added. Streams pass
+    /// the table from chunk to chunk, parallel parsing interns when merging.
+    #[cfg(feature = "tokio")]
+    pub(crate) symbols: Option<Symbols>,
+    /// Error which ended reading the chunk, elements before it are read
+    pub(crate) error: Option<Error>,
+}
+
+/// Reads part of the document between top level elements. Parts other than the first and last
+/// are wrapped into `<osm>` root element to make them documents of their own. Strings are
+/// interned to `symbols` when the builder interns.
+pub(crate) fn read_chunk(
+    data: &[u8],
+    first: bool,
+    last: bool,
+    builder: &ReaderBuilder,
+    symbols: Option<Symbols>,
+) -> Chunk {
+    let open: &[u8] = if first { b"" } else { b"<osm>" };
+    let close: &[u8] = if last { b"" } else { b"</osm>" };
+    let mut reader = builder.build_with_symbols(open.chain(data).chain(close), symbols);
+
+    let mut elements = Vec::new();
+    let mut error = None;
+    for element in reader.by_ref() {
+        match element {
+            Ok(element) => elements.push(element),
+            Err(err) => error = Some(err),
+        }
+    }
+
+    Chunk {
+        elements: elements,
+        bounds: if reader.bounds_read() {
+            Some(reader.bounds())
+        } else {
+            None
+        },
+        areas: reader.take_areas(),
+        counts: reader.counts().to_vec(),
+        duplicate_tags: reader.take_duplicate_tags(),
+        #[cfg(feature = "tokio")]
+        symbols: reader.take_symbols(),
+        error: error,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const DOC: &[u8] = b"<?xml version=\"1.0\"?><osm><!-- <node> -->\
+                         <node id=\"1\" v=\"a>b\"/>\
+                         <way id=\"2\"><nd ref=\"1\"/><![CDATA[<x>]]></way>\
+                         <node id=\"3\"></node></osm>";
+
+    fn starts(data: &[u8], offsets: &[usize]) -> Vec<Vec<u8>> {
+        offsets
+            .iter()
+            .map(|&offset| data[offset..offset + 4].to_vec())
+            .collect()
+    }
+
+    #[test]
+    fn finds_top_level_elements() {
+        let mut scanner = Scanner::new();
+        let mut offsets = Vec::new();
+        while let Some(offset) = scanner.next_element(DOC) {
+            offsets.push(offset);
+        }
+
+        assert_eq!(
+            starts(DOC, &offsets),
+            vec![b"<nod".to_vec(), b"<way".to_vec(), b"<nod".to_vec()]
+        );
+    }
+
+    #[test]
+    fn scans_incrementally() {
+        for step in 1..DOC.len() {
+            let mut scanner = Scanner::new();
+            let mut offsets = Vec::new();
+            let mut len = 0;
+
+            while len < DOC.len() {
+                len = (len + step).min(DOC.len());
+                while let Some(offset) = scanner.next_element(&DOC[..len]) {
+                    offsets.push(offset);
+                }
+            }
+
+            assert_eq!(offsets.len(), 3);
+            assert_eq!(starts(DOC, &offsets)[0], b"<nod".to_vec());
+        }
+    }
+}
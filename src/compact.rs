@@ -0,0 +1,664 @@
+use std::borrow::Cow;
+use std::io::{self, Read};
+
+use fnv::FnvHashMap;
+
+use elements::{Bounds, Element, ElementId, ElementKind, Id, Location, Member, Metadata, Node,
+               Relation, Role, Tag, UnresolvedReference, Way};
+use error::Error;
+use locations::{from_fixed, to_fixed};
+use reader::ElementReader;
+use store::ElementStore;
+use tags::Tags;
+use OSM;
+
+/// Memory efficient read-only alternative to `OSM`.
+///
+/// Elements are stored in struct-of-arrays layout sorted by id and looked up with binary search.
+/// Coordinates are 32-bit fixed point values with 1e-7 degree precision, which keeps the exact
+/// values of OSM data. Tag keys, tag values and member roles are deduplicated to one shared
+/// string buffer and elements refer to them by index.
+///
+/// Elements are accessed through lightweight views (`CompactNode`, `CompactWay`,
+/// `CompactRelation`) which can be converted to regular elements. Metadata and Overpass geometry
+/// are not stored. The views don't implement `OSMElement`, which lends out `Tags` that aren't
+/// kept in this layout, they have their own `id` and `tags` accessors instead.
+///
+/// The read API of `OSM` which works on any store (`store::children`, `store::descendants`,
+/// `store::resolve_reference`, `ResolvedOSM` and others) is available through the
+/// `ElementStore` implementation, which returns converted copies of the elements. Inserting
+/// and removing elements fails as the document can't be changed.
+#[derive(Debug, Default)]
+pub struct CompactOSM {
+    bounds: Option<Bounds>,
+    nodes: NodeColumns,
+    ways: WayColumns,
+    relations: RelationColumns,
+    strings: Strings,
+}
+
+#[derive(Debug, Default)]
+struct NodeColumns {
+    ids: Vec<Id>,
+    lats: Vec<i32>,
+    lons: Vec<i32>,
+    tags: Ranges<(u32, u32)>,
+}
+
+#[derive(Debug, Default)]
+struct WayColumns {
+    ids: Vec<Id>,
+    nodes: Ranges<Id>,
+    tags: Ranges<(u32, u32)>,
+}
+
+#[derive(Debug, Default)]
+struct RelationColumns {
+    ids: Vec<Id>,
+    members: Ranges<(ElementId, u32)>,
+    tags: Ranges<(u32, u32)>,
+}
+
+/// Variable length values of all elements in one vector, `offsets` has the start of each
+/// element plus the end of the last one.
+#[derive(Debug)]
+struct Ranges<T> {
+    offsets: Vec<u64>,
+    values: Vec<T>,
+}
+
+#[derive(Debug, Default)]
+struct Strings {
+    buffer: String,
+    offsets: Vec<u64>,
+}
+
+impl CompactOSM {
+    pub fn parse<R: Read>(source: R) -> Result<CompactOSM, Error> {
+        let mut builder = Builder::default();
+        let mut reader = ElementReader::new(source);
+
+        for element in reader.by_ref() {
+            try!(builder.push(&try!(element)));
+        }
+
+        Ok(builder.finish(reader.bounds()))
+    }
+
+    /// Converts the document, fails only if it has more than 2^32 distinct tag keys, tag values
+    /// and roles.
+    pub fn from_osm(osm: &OSM) -> Result<CompactOSM, Error> {
+        let mut builder = Builder::default();
+        for node in osm.nodes.values() {
+            try!(builder.push_node(node));
+        }
+        for way in osm.ways.values() {
+            try!(builder.push_way(way));
+        }
+        for relation in osm.relations.values() {
+            try!(builder.push_relation(relation));
+        }
+        Ok(builder.finish(osm.bounds))
+    }
+
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    pub fn node(&self, id: Id) -> Option<CompactNode<'_>> {
+        self.nodes.ids.binary_search(&id).ok().map(|index| CompactNode {
+            osm: self,
+            index: index,
+        })
+    }
+
+    pub fn way(&self, id: Id) -> Option<CompactWay<'_>> {
+        self.ways.ids.binary_search(&id).ok().map(|index| CompactWay {
+            osm: self,
+            index: index,
+        })
+    }
+
+    pub fn relation(&self, id: Id) -> Option<CompactRelation<'_>> {
+        self.relations.ids.binary_search(&id).ok().map(|index| CompactRelation {
+            osm: self,
+            index: index,
+        })
+    }
+
+    pub fn get(&self, id: ElementId) -> Option<CompactElement<'_>> {
+        match id {
+            ElementId::Node(id) => self.node(id).map(CompactElement::Node),
+            ElementId::Way(id) => self.way(id).map(CompactElement::Way),
+            ElementId::Relation(id) => self.relation(id).map(CompactElement::Relation),
+        }
+    }
+
+    pub fn resolve_reference(&self, reference: &UnresolvedReference) -> Option<CompactElement<'_>> {
+        self.get(ElementId::from(*reference))
+    }
+
+    /// Nodes sorted by id.
+    pub fn nodes(&self) -> impl Iterator<Item = CompactNode<'_>> {
+        (0..self.nodes.ids.len()).map(move |index| CompactNode {
+            osm: self,
+            index: index,
+        })
+    }
+
+    /// Ways sorted by id.
+    pub fn ways(&self) -> impl Iterator<Item = CompactWay<'_>> {
+        (0..self.ways.ids.len()).map(move |index| CompactWay {
+            osm: self,
+            index: index,
+        })
+    }
+
+    /// Relations sorted by id.
+    pub fn relations(&self) -> impl Iterator<Item = CompactRelation<'_>> {
+        (0..self.relations.ids.len()).map(move |index| CompactRelation {
+            osm: self,
+            index: index,
+        })
+    }
+
+    /// Iterates over all elements in file order: nodes first, then ways and relations.
+    pub fn elements(&self) -> impl Iterator<Item = CompactElement<'_>> {
+        self.nodes()
+            .map(CompactElement::Node)
+            .chain(self.ways().map(CompactElement::Way))
+            .chain(self.relations().map(CompactElement::Relation))
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.ids.len()
+    }
+
+    pub fn way_count(&self) -> usize {
+        self.ways.ids.len()
+    }
+
+    pub fn relation_count(&self) -> usize {
+        self.relations.ids.len()
+    }
+
+    /// Converts back to regular `OSM` without metadata.
+    pub fn to_osm(&self) -> OSM {
+        let mut osm = OSM::new();
+        osm.bounds = self.bounds;
+        for element in self.elements() {
+            osm.insert(element.to_element());
+        }
+        osm
+    }
+}
+
+impl ElementStore for CompactOSM {
+    fn node(&self, id: Id) -> Result<Option<Cow<'_, Node>>, Error> {
+        Ok(CompactOSM::node(self, id).map(|node| Cow::Owned(node.to_node())))
+    }
+
+    fn way(&self, id: Id) -> Result<Option<Cow<'_, Way>>, Error> {
+        Ok(CompactOSM::way(self, id).map(|way| Cow::Owned(way.to_way())))
+    }
+
+    fn relation(&self, id: Id) -> Result<Option<Cow<'_, Relation>>, Error> {
+        Ok(CompactOSM::relation(self, id).map(|relation| Cow::Owned(relation.to_relation())))
+    }
+
+    fn ids(&self, kind: ElementKind) -> Box<dyn Iterator<Item = Id> + '_> {
+        let ids = match kind {
+            ElementKind::Node => &self.nodes.ids,
+            ElementKind::Way => &self.ways.ids,
+            ElementKind::Relation => &self.relations.ids,
+        };
+        Box::new(ids.iter().cloned())
+    }
+
+    fn insert(&mut self, _element: Element) -> Result<Option<Element>, Error> {
+        Err(Error::InvalidEdit("CompactOSM is read-only".to_string()))
+    }
+
+    fn remove(&mut self, _id: ElementId) -> Result<Option<Element>, Error> {
+        Err(Error::InvalidEdit("CompactOSM is read-only".to_string()))
+    }
+
+    fn contains(&self, id: ElementId) -> Result<bool, Error> {
+        Ok(self.get(id).is_some())
+    }
+}
+
+/// Panics if the document has more than 2^32 distinct strings, see `CompactOSM::from_osm`.
+impl<'a> From<&'a OSM> for CompactOSM {
+    fn from(osm: &'a OSM) -> CompactOSM {
+        CompactOSM::from_osm(osm).expect("Too many distinct strings for CompactOSM")
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum CompactElement<'a> {
+    Node(CompactNode<'a>),
+    Way(CompactWay<'a>),
+    Relation(CompactRelation<'a>),
+}
+
+impl<'a> CompactElement<'a> {
+    pub fn element_id(&self) -> ElementId {
+        match *self {
+            CompactElement::Node(node) => ElementId::Node(node.id()),
+            CompactElement::Way(way) => ElementId::Way(way.id()),
+            CompactElement::Relation(relation) => ElementId::Relation(relation.id()),
+        }
+    }
+
+    pub fn tags(&self) -> CompactTags<'a> {
+        match *self {
+            CompactElement::Node(node) => node.tags(),
+            CompactElement::Way(way) => way.tags(),
+            CompactElement::Relation(relation) => relation.tags(),
+        }
+    }
+
+    pub fn to_element(&self) -> Element {
+        match *self {
+            CompactElement::Node(node) => Element::Node(node.to_node()),
+            CompactElement::Way(way) => Element::Way(way.to_way()),
+            CompactElement::Relation(relation) => Element::Relation(relation.to_relation()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct CompactNode<'a> {
+    osm: &'a CompactOSM,
+    index: usize,
+}
+
+impl<'a> CompactNode<'a> {
+    pub fn id(&self) -> Id {
+        self.osm.nodes.ids[self.index]
+    }
+
+    pub fn lat(&self) -> f64 {
+        self.location().lat
+    }
+
+    pub fn lon(&self) -> f64 {
+        self.location().lon
+    }
+
+    pub fn location(&self) -> Location {
+        let nodes = &self.osm.nodes;
+        from_fixed((nodes.lats[self.index], nodes.lons[self.index]))
+    }
+
+    pub fn tags(&self) -> CompactTags<'a> {
+        CompactTags {
+            strings: &self.osm.strings,
+            tags: self.osm.nodes.tags.get(self.index),
+        }
+    }
+
+    pub fn to_node(&self) -> Node {
+        Node {
+            id: self.id(),
+            lat: self.lat(),
+            lon: self.lon(),
+            tags: self.tags().to_tags(),
+            meta: Metadata::default(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct CompactWay<'a> {
+    osm: &'a CompactOSM,
+    index: usize,
+}
+
+impl<'a> CompactWay<'a> {
+    pub fn id(&self) -> Id {
+        self.osm.ways.ids[self.index]
+    }
+
+    /// Node ids of the way in order.
+    pub fn nodes(&self) -> &'a [Id] {
+        self.osm.ways.nodes.get(self.index)
+    }
+
+    pub fn tags(&self) -> CompactTags<'a> {
+        CompactTags {
+            strings: &self.osm.strings,
+            tags: self.osm.ways.tags.get(self.index),
+        }
+    }
+
+    pub fn to_way(&self) -> Way {
+        Way {
+            id: self.id(),
+            tags: self.tags().to_tags(),
+            nodes: self.nodes()
+                .iter()
+                .map(|&id| UnresolvedReference::Node(id))
+                .collect(),
+            meta: Metadata::default(),
+            geometry: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct CompactRelation<'a> {
+    osm: &'a CompactOSM,
+    index: usize,
+}
+
+impl<'a> CompactRelation<'a> {
+    pub fn id(&self) -> Id {
+        self.osm.relations.ids[self.index]
+    }
+
+    /// Members of the relation in order with their roles.
+    pub fn members(&self) -> impl Iterator<Item = (ElementId, &'a str)> + 'a {
+        let strings = &self.osm.strings;
+        self.osm
+            .relations
+            .members
+            .get(self.index)
+            .iter()
+            .map(move |&(id, role)| (id, strings.get(role)))
+    }
+
+    pub fn tags(&self) -> CompactTags<'a> {
+        CompactTags {
+            strings: &self.osm.strings,
+            tags: self.osm.relations.tags.get(self.index),
+        }
+    }
+
+    pub fn to_relation(&self) -> Relation {
+        Relation {
+            id: self.id(),
+            members: self.members()
+                .map(|(id, role)| {
+                    let role = Role::from(role);
+                    match UnresolvedReference::from(id) {
+                        r @ UnresolvedReference::Node(_) => Member::Node(r, role),
+                        r @ UnresolvedReference::Way(_) => Member::Way(r, role),
+                        r @ UnresolvedReference::Relation(_) => Member::Relation(r, role),
+                    }
+                })
+                .collect(),
+            tags: self.tags().to_tags(),
+            meta: Metadata::default(),
+            geometry: Default::default(),
+        }
+    }
+}
+
+/// Tags of a compact element, with the same lookups as `Tags`.
+#[derive(Debug, Copy, Clone)]
+pub struct CompactTags<'a> {
+    strings: &'a Strings,
+    tags: &'a [(u32, u32)],
+}
+
+impl<'a> CompactTags<'a> {
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.iter().find(|&(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tags.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tags.is_empty()
+    }
+
+    /// Iterates over `(key, value)` pairs in original order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        let strings = self.strings;
+        self.tags
+            .iter()
+            .map(move |&(k, v)| (strings.get(k), strings.get(v)))
+    }
+
+    pub fn to_tags(&self) -> Tags {
+        self.iter().map(|(k, v)| Tag::new(k, v)).collect()
+    }
+}
+
+impl<T> Default for Ranges<T> {
+    fn default() -> Ranges<T> {
+        Ranges {
+            offsets: vec![0],
+            values: Vec::new(),
+        }
+    }
+}
+
+impl<T: Clone> Ranges<T> {
+    fn push<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        self.values.extend(values);
+        self.offsets.push(self.values.len() as u64);
+    }
+
+    fn get(&self, index: usize) -> &[T] {
+        &self.values[self.offsets[index] as usize..self.offsets[index + 1] as usize]
+    }
+
+    fn reorder(&self, order: &[usize]) -> Ranges<T> {
+        let mut ranges = Ranges::default();
+        for &index in order {
+            ranges.push(self.get(index).iter().cloned());
+        }
+        ranges
+    }
+}
+
+impl Strings {
+    fn get(&self, index: u32) -> &str {
+        let index = index as usize;
+        let end = self.offsets
+            .get(index + 1)
+            .map(|&end| end as usize)
+            .unwrap_or(self.buffer.len());
+        &self.buffer[self.offsets[index] as usize..end]
+    }
+}
+
+
+#[derive(Default)]
+struct Builder {
+    osm: CompactOSM,
+    string_index: FnvHashMap<String, u32>,
+}
+
+impl Builder {
+    fn push(&mut self, element: &Element) -> Result<(), Error> {
+        match *element {
+            Element::Node(ref node) => self.push_node(node),
+            Element::Way(ref way) => self.push_way(way),
+            Element::Relation(ref relation) => self.push_relation(relation),
+        }
+    }
+
+    fn push_node(&mut self, node: &Node) -> Result<(), Error> {
+        let (lat, lon) = to_fixed(Location {
+            lat: node.lat,
+            lon: node.lon,
+        });
+        let tags = try!(self.tags(&node.tags));
+
+        let nodes = &mut self.osm.nodes;
+        nodes.ids.push(node.id);
+        nodes.lats.push(lat);
+        nodes.lons.push(lon);
+        nodes.tags.push(tags);
+        Ok(())
+    }
+
+    fn push_way(&mut self, way: &Way) -> Result<(), Error> {
+        let tags = try!(self.tags(&way.tags));
+
+        let ways = &mut self.osm.ways;
+        ways.ids.push(way.id);
+        ways.nodes
+            .push(way.nodes.iter().map(|node| ElementId::from(*node).id()));
+        ways.tags.push(tags);
+        Ok(())
+    }
+
+    fn push_relation(&mut self, relation: &Relation) -> Result<(), Error> {
+        let tags = try!(self.tags(&relation.tags));
+        let mut members = Vec::with_capacity(relation.members.len());
+        for member in &relation.members {
+            match *member {
+                Member::Node(r, ref role) |
+                Member::Way(r, ref role) |
+                Member::Relation(r, ref role) => {
+                    members.push((ElementId::from(r), try!(self.string(role))))
+                }
+            }
+        }
+
+        let relations = &mut self.osm.relations;
+        relations.ids.push(relation.id);
+        relations.members.push(members);
+        relations.tags.push(tags);
+        Ok(())
+    }
+
+    fn tags(&mut self, tags: &Tags) -> Result<Vec<(u32, u32)>, Error> {
+        tags.iter()
+            .map(|tag| Ok((try!(self.string(&tag.key)), try!(self.string(&tag.val)))))
+            .collect()
+    }
+
+    fn string(&mut self, value: &str) -> Result<u32, Error> {
+        if let Some(&index) = self.string_index.get(value) {
+            return Ok(index);
+        }
+
+        let strings = &mut self.osm.strings;
+        if strings.offsets.len() > u32::MAX as usize {
+            return Err(Error::Io(io::Error::other(
+                "more than 2^32 distinct strings for CompactOSM",
+            )));
+        }
+        let index = strings.offsets.len() as u32;
+        strings.offsets.push(strings.buffer.len() as u64);
+        strings.buffer.push_str(value);
+        self.string_index.insert(value.to_string(), index);
+        Ok(index)
+    }
+
+    fn finish(self, bounds: Option<Bounds>) -> CompactOSM {
+        let mut osm = self.osm;
+        osm.bounds = bounds;
+
+        if let Some(order) = sort_order(&osm.nodes.ids) {
+            let nodes = &osm.nodes;
+            osm.nodes = NodeColumns {
+                ids: order.iter().map(|&i| nodes.ids[i]).collect(),
+                lats: order.iter().map(|&i| nodes.lats[i]).collect(),
+                lons: order.iter().map(|&i| nodes.lons[i]).collect(),
+                tags: nodes.tags.reorder(&order),
+            };
+        }
+
+        if let Some(order) = sort_order(&osm.ways.ids) {
+            let ways = &osm.ways;
+            osm.ways = WayColumns {
+                ids: order.iter().map(|&i| ways.ids[i]).collect(),
+                nodes: ways.nodes.reorder(&order),
+                tags: ways.tags.reorder(&order),
+            };
+        }
+
+        if let Some(order) = sort_order(&osm.relations.ids) {
+            let relations = &osm.relations;
+            osm.relations = RelationColumns {
+                ids: order.iter().map(|&i| relations.ids[i]).collect(),
+                members: relations.members.reorder(&order),
+                tags: relations.tags.reorder(&order),
+            };
+        }
+
+        osm
+    }
+}
+
+/// Order of rows sorted by id, or `None` when ids are already strictly increasing like in
+/// regular OSM files. Duplicate ids keep the last row, like `OSM::insert` does.
+fn sort_order(ids: &[Id]) -> Option<Vec<usize>> {
+    if ids.windows(2).all(|pair| pair[0] < pair[1]) {
+        return None;
+    }
+
+    let mut order: Vec<usize> = (0..ids.len()).collect();
+    order.sort_by_key(|&i| (ids[i], i));
+
+    let mut deduped: Vec<usize> = Vec::with_capacity(order.len());
+    for i in order {
+        match deduped.last_mut() {
+            Some(last) if ids[*last] == ids[i] => *last = i,
+            _ => deduped.push(i),
+        }
+    }
+
+    Some(deduped)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unsorted_and_duplicate_ids() {
+        assert_eq!(sort_order(&[1, 2, 5]), None);
+        assert_eq!(sort_order(&[5, 1, 2]), Some(vec![1, 2, 0]));
+        assert_eq!(sort_order(&[2, 1, 2]), Some(vec![1, 2]));
+    }
+
+    #[test]
+    fn strings_are_shared() {
+        let mut builder = Builder::default();
+        for id in 0..3 {
+            builder.push_node(&Node {
+                id: id,
+                lat: 0.0,
+                lon: 0.0,
+                tags: vec![Tag::new("amenity", "bench")].into(),
+                meta: Metadata::default(),
+            }).unwrap();
+        }
+
+        let osm = builder.finish(None);
+        assert_eq!(osm.strings.buffer, "amenitybench");
+        assert_eq!(osm.node(2).unwrap().tags().get("amenity"), Some("bench"));
+    }
+
+    #[test]
+    fn empty_strings() {
+        let mut builder = Builder::default();
+        builder.push_relation(&Relation {
+            id: 1,
+            members: vec![Member::Node(UnresolvedReference::Node(2), Role::default())],
+            tags: vec![Tag::new("note", ""), Tag::new("type", "site")].into(),
+            meta: Metadata::default(),
+            geometry: Default::default(),
+        }).unwrap();
+
+        let osm = builder.finish(None);
+        let relation = osm.relation(1).unwrap();
+        assert_eq!(relation.members().collect::<Vec<_>>(), vec![(ElementId::Node(2), "")]);
+        assert_eq!(relation.tags().get("note"), Some(""));
+        assert_eq!(relation.tags().get("type"), Some("site"));
+    }
+}
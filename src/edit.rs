@@ -0,0 +1,287 @@
+//! Editing methods which keep references between elements valid.
+//!
+//! New elements get negative placeholder ids like in editors, so they can't be mixed up with
+//! elements already in the database. The ids are shared by all element kinds and count down from
+//! -1, skipping ids already in the document. Ways and relations can only refer to elements in
+//! the document and elements still in use can't be deleted unless `DeleteMode::Cascade` is
+//! given.
+
+use elements::{Coordinate, Element, ElementId, ElementKind, Geometry, Id, Member, Metadata, Node,
+               Relation, UnresolvedReference, Way};
+use error::Error;
+use recursion::member_id;
+use symbols::Str;
+use tags::Tags;
+use OSM;
+
+/// What to do with the references to an element when it's deleted.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum DeleteMode {
+    /// Refuse to delete elements which ways or relations refer to.
+    Refuse,
+    /// Remove the element from the ways and relations which refer to it too. Closed ways are
+    /// closed again when their first node is removed. Ways left without two different nodes, or
+    /// closed ways without three, are deleted the same way, relations are kept even when they
+    /// are left without members.
+    Cascade,
+}
+
+impl OSM {
+    /// Adds node with a new placeholder id.
+    pub fn add_node(&mut self, lat: Coordinate, lon: Coordinate, tags: Tags) -> Id {
+        let id = self.placeholder_id(ElementKind::Node);
+        self.nodes.insert(
+            id,
+            Node {
+                id: id,
+                lat: lat,
+                lon: lon,
+                tags: tags,
+                meta: Metadata::default(),
+            },
+        );
+        id
+    }
+
+    /// Adds way with a new placeholder id. The way needs at least two distinct nodes and all of
+    /// them have to be in the document.
+    pub fn add_way<I>(&mut self, nodes: I, tags: Tags) -> Result<Id, Error>
+    where
+        I: IntoIterator<Item = Id>,
+    {
+        let nodes: Vec<UnresolvedReference> =
+            nodes.into_iter().map(UnresolvedReference::Node).collect();
+        let distinct = nodes
+            .first()
+            .is_some_and(|first| nodes.iter().any(|node| node != first));
+        if !distinct {
+            return Err(Error::InvalidEdit("way needs at least two distinct nodes".to_string()));
+        }
+        for node in &nodes {
+            try!(self.check_exists(ElementId::from(*node)));
+        }
+
+        let id = self.placeholder_id(ElementKind::Way);
+        self.ways.insert(
+            id,
+            Way {
+                id: id,
+                tags: tags,
+                nodes: nodes,
+                meta: Metadata::default(),
+                geometry: Geometry::default(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Adds relation with a new placeholder id. All members have to be in the document.
+    pub fn add_relation(&mut self, members: Vec<Member>, tags: Tags) -> Result<Id, Error> {
+        for member in &members {
+            try!(self.check_exists(member_id(member)));
+        }
+
+        let id = self.placeholder_id(ElementKind::Relation);
+        self.relations.insert(
+            id,
+            Relation {
+                id: id,
+                members: members,
+                tags: tags,
+                meta: Metadata::default(),
+                geometry: Geometry::default(),
+            },
+        );
+        Ok(id)
+    }
+
+    /// Sets tag of the element and returns the old value.
+    pub fn set_tag<K, V>(&mut self, id: ElementId, key: K, val: V) -> Result<Option<String>, Error>
+    where
+        K: Into<Str>,
+        V: Into<Str>,
+    {
+        Ok(try!(self.tags_mut(id)).insert(key, val))
+    }
+
+    /// Removes tag of the element and returns its value.
+    pub fn remove_tag(&mut self, id: ElementId, key: &str) -> Result<Option<String>, Error> {
+        Ok(try!(self.tags_mut(id)).remove(key))
+    }
+
+    /// Moves node to a new location. Inline Overpass geometry of the ways is not updated.
+    pub fn move_node(&mut self, id: Id, lat: Coordinate, lon: Coordinate) -> Result<(), Error> {
+        match self.nodes.get_mut(&id) {
+            Some(node) => {
+                node.lat = lat;
+                node.lon = lon;
+                Ok(())
+            }
+            None => Err(Error::ElementNotFound(ElementId::Node(id))),
+        }
+    }
+
+    /// Deletes node, see `DeleteMode`. Returns the deleted elements, the node first.
+    pub fn delete_node(&mut self, id: Id, mode: DeleteMode) -> Result<Vec<Element>, Error> {
+        self.delete(ElementId::Node(id), mode)
+    }
+
+    /// Deletes way, see `DeleteMode`. Returns the deleted elements, the way first. Nodes of the
+    /// way are kept.
+    pub fn delete_way(&mut self, id: Id, mode: DeleteMode) -> Result<Vec<Element>, Error> {
+        self.delete(ElementId::Way(id), mode)
+    }
+
+    /// Deletes relation, see `DeleteMode`. Returns the deleted elements, the relation first.
+    /// Members of the relation are kept.
+    pub fn delete_relation(&mut self, id: Id, mode: DeleteMode) -> Result<Vec<Element>, Error> {
+        self.delete(ElementId::Relation(id), mode)
+    }
+
+    pub(crate) fn delete(
+        &mut self,
+        id: ElementId,
+        mode: DeleteMode,
+    ) -> Result<Vec<Element>, Error> {
+        try!(self.check_exists(id));
+        if mode == DeleteMode::Refuse {
+            let parents = self.referrers(id);
+            if !parents.is_empty() {
+                return Err(Error::ElementInUse(id, parents));
+            }
+        }
+
+        let mut deleted = Vec::new();
+        let mut pending = vec![id];
+        while let Some(id) = pending.pop() {
+            let element = match self.remove(id) {
+                Some(element) => element,
+                None => continue,
+            };
+
+            for parent in self.referrers(id) {
+                if let ElementId::Way(way_id) = parent {
+                    if let Some(way) = self.ways.get_mut(&way_id) {
+                        let closed = is_closed(way);
+                        retain_with_geometry(&mut way.nodes, &mut way.geometry.nodes, |node| {
+                            ElementId::from(*node) != id
+                        });
+                        if !clean_way(way, closed) {
+                            pending.push(parent);
+                        }
+                    }
+                } else if let ElementId::Relation(relation_id) = parent {
+                    if let Some(relation) = self.relations.get_mut(&relation_id) {
+                        retain_with_geometry(
+                            &mut relation.members,
+                            &mut relation.geometry.members,
+                            |member| member_id(member) != id,
+                        );
+                    }
+                }
+            }
+
+            deleted.push(element);
+        }
+
+        Ok(deleted)
+    }
+
+    /// Ways and relations which refer to the element, other than the element itself, in file
+    /// order.
+    pub(crate) fn referrers(&self, id: ElementId) -> Vec<ElementId> {
+        let mut referrers = Vec::new();
+
+        if let ElementId::Node(_) = id {
+            for way in self.ways.values() {
+                if way.nodes.iter().any(|node| ElementId::from(*node) == id) {
+                    referrers.push(ElementId::Way(way.id));
+                }
+            }
+        }
+
+        for relation in self.relations.values() {
+            if ElementId::Relation(relation.id) != id &&
+                relation.members.iter().any(|member| member_id(member) == id)
+            {
+                referrers.push(ElementId::Relation(relation.id));
+            }
+        }
+
+        referrers.sort();
+        referrers
+    }
+
+    pub(crate) fn placeholder_id(&mut self, kind: ElementKind) -> Id {
+        loop {
+            self.placeholder -= 1;
+            if self.get(ElementId::new(kind, self.placeholder)).is_none() {
+                return self.placeholder;
+            }
+        }
+    }
+
+    pub(crate) fn check_exists(&self, id: ElementId) -> Result<(), Error> {
+        match self.get(id) {
+            Some(_) => Ok(()),
+            None => Err(Error::ElementNotFound(id)),
+        }
+    }
+
+    fn tags_mut(&mut self, id: ElementId) -> Result<&mut Tags, Error> {
+        let tags = match id {
+            ElementId::Node(id) => self.nodes.get_mut(&id).map(|node| &mut node.tags),
+            ElementId::Way(id) => self.ways.get_mut(&id).map(|way| &mut way.tags),
+            ElementId::Relation(id) => self.relations
+                .get_mut(&id)
+                .map(|relation| &mut relation.tags),
+        };
+        tags.ok_or(Error::ElementNotFound(id))
+    }
+}
+
+/// Whether the way ends where it starts.
+pub(crate) fn is_closed(way: &Way) -> bool {
+    way.nodes.len() > 1 && way.nodes.first() == way.nodes.last()
+}
+
+/// Removes repeated nodes next to each other after nodes of the way were removed or replaced,
+/// and closes the way again if it was `closed` before. Returns whether the way is still valid:
+/// two different nodes, three for closed ways.
+pub(crate) fn clean_way(way: &mut Way, closed: bool) -> bool {
+    let mut previous = None;
+    retain_with_geometry(&mut way.nodes, &mut way.geometry.nodes, |reference| {
+        let keep = previous != Some(*reference);
+        previous = Some(*reference);
+        keep
+    });
+
+    if closed && !way.nodes.is_empty() && !is_closed(way) {
+        if way.geometry.nodes.len() == way.nodes.len() {
+            let first = way.geometry.nodes[0];
+            way.geometry.nodes.push(first);
+        }
+        let first = way.nodes[0];
+        way.nodes.push(first);
+    }
+
+    let mut distinct = way.nodes.clone();
+    distinct.sort_by_key(|reference| ElementId::from(*reference));
+    distinct.dedup();
+    distinct.len() >= if is_closed(way) { 3 } else { 2 }
+}
+
+/// Removes items from `items` and the matching items from inline geometry, when there is one.
+pub(crate) fn retain_with_geometry<T, G, F>(items: &mut Vec<T>, geometry: &mut Vec<G>, keep: F)
+where
+    F: FnMut(&T) -> bool,
+{
+    let kept: Vec<bool> = items.iter().map(keep).collect();
+    if geometry.len() == items.len() {
+        let mut kept = kept.iter();
+        geometry.retain(|_| kept.next() == Some(&true));
+    }
+
+    let mut kept = kept.into_iter();
+    items.retain(|_| kept.next() == Some(true));
+}
@@ -1,13 +1,24 @@
-use polygon;
+use polygon::{self, GeometryType, PolygonRules};
+use symbols::Str;
+use tags::Tags;
 
 pub type Coordinate = f64;
 pub type Id = i64;
-pub type Role = String;
+pub type Role = Str;
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Tag {
-    pub key: String,
-    pub val: String,
+    pub key: Str,
+    pub val: Str,
+}
+
+impl Tag {
+    pub fn new<K: Into<Str>, V: Into<Str>>(key: K, val: V) -> Tag {
+        Tag {
+            key: key.into(),
+            val: val.into(),
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -18,32 +29,117 @@ pub struct Bounds {
     pub maxlon: Coordinate,
 }
 
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Location {
+    pub lat: Coordinate,
+    pub lon: Coordinate,
+}
+
+/// Geometry included inline by Overpass API with `out geom`, `out bb` and `out center`. Empty
+/// for regular OSM XML.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Geometry {
+    pub bounds: Option<Bounds>,
+    pub center: Option<Location>,
+    /// Locations of way nodes in the same order as `Way::nodes`. Nodes outside of the query bbox
+    /// have no location.
+    pub nodes: Vec<Option<Location>>,
+    /// Locations of relation members in the same order as `Relation::members`: single location
+    /// for node members, node locations for way members and nothing for relation members.
+    pub members: Vec<Vec<Option<Location>>>,
+}
+
+impl Geometry {
+    pub fn is_empty(&self) -> bool {
+        self.bounds.is_none() && self.center.is_none() && self.nodes.is_empty() &&
+            self.members.is_empty()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Metadata {
+    pub version: Option<u32>,
+    pub timestamp: Option<String>,
+    pub changeset: Option<Id>,
+    pub uid: Option<Id>,
+    pub user: Option<String>,
+    pub visible: Option<bool>,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Node {
     pub id: Id,
     pub lat: Coordinate,
     pub lon: Coordinate,
-    pub tags: Vec<Tag>,
+    pub tags: Tags,
+    pub meta: Metadata,
+}
+
+impl Node {
+    pub fn geometry_type(&self) -> GeometryType {
+        polygon::classify_node(self)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Way {
     pub id: Id,
-    pub tags: Vec<Tag>,
+    pub tags: Tags,
     pub nodes: Vec<UnresolvedReference>,
+    pub meta: Metadata,
+    pub geometry: Geometry,
 }
 
 impl Way {
     pub fn is_polygon(&self) -> bool {
         polygon::is_polygon(self)
     }
+
+    pub fn is_polygon_with(&self, rules: &PolygonRules) -> bool {
+        polygon::is_polygon_with(self, rules)
+    }
+
+    pub fn geometry_type(&self) -> GeometryType {
+        polygon::classify_way(self)
+    }
+
+    pub fn geometry_type_with(&self, rules: &PolygonRules) -> GeometryType {
+        polygon::classify_way_with(self, rules)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct Relation {
     pub id: Id,
     pub members: Vec<Member>,
-    pub tags: Vec<Tag>,
+    pub tags: Tags,
+    pub meta: Metadata,
+    pub geometry: Geometry,
+}
+
+impl Relation {
+    pub fn geometry_type(&self) -> Option<GeometryType> {
+        polygon::classify_relation(self)
+    }
+}
+
+/// Overpass API area, derived from a closed way or multipolygon relation. Way areas have the way
+/// id plus 2400000000 and relation areas the relation id plus 3600000000.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Area {
+    pub id: Id,
+    pub tags: Tags,
+    pub meta: Metadata,
+}
+
+/// Result of Overpass `out count`.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct Count {
+    pub nodes: u64,
+    pub ways: u64,
+    pub relations: u64,
+    pub areas: u64,
+    pub total: u64,
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -67,3 +163,223 @@ pub enum Reference<'a> {
     Relation(&'a Relation),
     Unresolved,
 }
+
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Copy, Clone)]
+pub enum ElementKind {
+    Node,
+    Way,
+    Relation,
+}
+
+/// Identifies single element in the document. Ordering follows the usual OSM file order: all
+/// nodes first, then ways and relations, each sorted by id.
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Copy, Clone)]
+pub enum ElementId {
+    Node(Id),
+    Way(Id),
+    Relation(Id),
+}
+
+impl ElementId {
+    pub fn new(kind: ElementKind, id: Id) -> ElementId {
+        match kind {
+            ElementKind::Node => ElementId::Node(id),
+            ElementKind::Way => ElementId::Way(id),
+            ElementKind::Relation => ElementId::Relation(id),
+        }
+    }
+
+    pub fn id(&self) -> Id {
+        match *self {
+            ElementId::Node(id) | ElementId::Way(id) | ElementId::Relation(id) => id,
+        }
+    }
+
+    pub fn kind(&self) -> ElementKind {
+        match *self {
+            ElementId::Node(_) => ElementKind::Node,
+            ElementId::Way(_) => ElementKind::Way,
+            ElementId::Relation(_) => ElementKind::Relation,
+        }
+    }
+}
+
+impl From<UnresolvedReference> for ElementId {
+    fn from(reference: UnresolvedReference) -> ElementId {
+        match reference {
+            UnresolvedReference::Node(id) => ElementId::Node(id),
+            UnresolvedReference::Way(id) => ElementId::Way(id),
+            UnresolvedReference::Relation(id) => ElementId::Relation(id),
+        }
+    }
+}
+
+impl From<ElementId> for UnresolvedReference {
+    fn from(id: ElementId) -> UnresolvedReference {
+        match id {
+            ElementId::Node(id) => UnresolvedReference::Node(id),
+            ElementId::Way(id) => UnresolvedReference::Way(id),
+            ElementId::Relation(id) => UnresolvedReference::Relation(id),
+        }
+    }
+}
+
+/// Common accessors shared by all element kinds, so that code handling tags or metadata can be
+/// written once for nodes, ways and relations.
+pub trait OSMElement {
+    fn id(&self) -> Id;
+    fn kind(&self) -> ElementKind;
+    fn tags(&self) -> &Tags;
+    fn meta(&self) -> &Metadata;
+
+    fn element_id(&self) -> ElementId {
+        ElementId::new(self.kind(), self.id())
+    }
+}
+
+impl OSMElement for Node {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn kind(&self) -> ElementKind {
+        ElementKind::Node
+    }
+
+    fn tags(&self) -> &Tags {
+        &self.tags
+    }
+
+    fn meta(&self) -> &Metadata {
+        &self.meta
+    }
+}
+
+impl OSMElement for Way {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn kind(&self) -> ElementKind {
+        ElementKind::Way
+    }
+
+    fn tags(&self) -> &Tags {
+        &self.tags
+    }
+
+    fn meta(&self) -> &Metadata {
+        &self.meta
+    }
+}
+
+impl OSMElement for Relation {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn kind(&self) -> ElementKind {
+        ElementKind::Relation
+    }
+
+    fn tags(&self) -> &Tags {
+        &self.tags
+    }
+
+    fn meta(&self) -> &Metadata {
+        &self.meta
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Element {
+    Node(Node),
+    Way(Way),
+    Relation(Relation),
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ElementRef<'a> {
+    Node(&'a Node),
+    Way(&'a Way),
+    Relation(&'a Relation),
+}
+
+impl<'a> ElementRef<'a> {
+    pub fn to_element(&self) -> Element {
+        match *self {
+            ElementRef::Node(node) => Element::Node(node.clone()),
+            ElementRef::Way(way) => Element::Way(way.clone()),
+            ElementRef::Relation(relation) => Element::Relation(relation.clone()),
+        }
+    }
+
+    /// Geometry type of the element, `None` for relations without geometry of their own.
+    pub fn geometry_type(&self) -> Option<GeometryType> {
+        polygon::classify(*self)
+    }
+
+    fn as_element(&self) -> &'a dyn OSMElement {
+        match *self {
+            ElementRef::Node(node) => node,
+            ElementRef::Way(way) => way,
+            ElementRef::Relation(relation) => relation,
+        }
+    }
+}
+
+impl<'a> From<&'a Element> for ElementRef<'a> {
+    fn from(element: &'a Element) -> ElementRef<'a> {
+        match *element {
+            Element::Node(ref node) => ElementRef::Node(node),
+            Element::Way(ref way) => ElementRef::Way(way),
+            Element::Relation(ref relation) => ElementRef::Relation(relation),
+        }
+    }
+}
+
+impl Element {
+    fn as_element(&self) -> &dyn OSMElement {
+        match *self {
+            Element::Node(ref node) => node,
+            Element::Way(ref way) => way,
+            Element::Relation(ref relation) => relation,
+        }
+    }
+}
+
+impl OSMElement for Element {
+    fn id(&self) -> Id {
+        self.as_element().id()
+    }
+
+    fn kind(&self) -> ElementKind {
+        self.as_element().kind()
+    }
+
+    fn tags(&self) -> &Tags {
+        self.as_element().tags()
+    }
+
+    fn meta(&self) -> &Metadata {
+        self.as_element().meta()
+    }
+}
+
+impl<'a> OSMElement for ElementRef<'a> {
+    fn id(&self) -> Id {
+        self.as_element().id()
+    }
+
+    fn kind(&self) -> ElementKind {
+        self.as_element().kind()
+    }
+
+    fn tags(&self) -> &Tags {
+        self.as_element().tags()
+    }
+
+    fn meta(&self) -> &Metadata {
+        self.as_element().meta()
+    }
+}
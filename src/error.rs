@@ -1,7 +1,12 @@
+extern crate regex;
+extern crate serde_json;
 extern crate xml;
 
+use std::io;
 use std::num::{ParseFloatError, ParseIntError};
 
+use elements::ElementId;
+
 #[derive(Debug)]
 pub enum Error {
     BoundsMissing(ErrorReason),
@@ -9,8 +14,24 @@ pub enum Error {
     MalformedNode(ErrorReason),
     MalformedWay(ErrorReason),
     MalformedRelation(ErrorReason),
+    MalformedPolygonRule(ErrorReason),
+    MalformedFilter(ErrorReason),
+    MalformedQuery(ErrorReason),
+    MalformedSnapshot(ErrorReason),
     UnknownElement,
+    /// Edited or referenced element does not exist in the document
+    ElementNotFound(ElementId),
+    /// Element can't be deleted because the listed ways and relations refer to it
+    ElementInUse(ElementId, Vec<ElementId>),
+    /// Editing operation can't be done to the given elements
+    InvalidEdit(String),
+    /// Overpass API `runtime error` remark, the document is incomplete
+    RuntimeError(String),
     XmlParseError(xml::reader::Error),
+    #[cfg(feature = "fast")]
+    FastXmlParseError(::quick_xml::Error),
+    Io(io::Error),
+    JsonParseError(serde_json::Error),
 }
 
 use std::fmt;
@@ -19,8 +40,16 @@ use std::fmt;
 pub enum ErrorReason {
     ParseFloat(ParseFloatError),
     ParseInt(ParseIntError),
+    Regex(regex::Error),
     IllegalNesting,
+    DuplicateKey,
+    InvalidValue,
     Missing,
+    /// Unexpected input at the given byte offset
+    Syntax(usize),
+    /// Data written by a different format version
+    UnsupportedVersion(u32),
+    ChecksumMismatch,
 }
 
 impl From<ParseFloatError> for ErrorReason {
@@ -35,12 +64,37 @@ impl From<ParseIntError> for ErrorReason {
     }
 }
 
+impl From<regex::Error> for ErrorReason {
+    fn from(err: regex::Error) -> ErrorReason {
+        ErrorReason::Regex(err)
+    }
+}
+
 impl From<xml::reader::Error> for Error {
     fn from(err: xml::reader::Error) -> Error {
         Error::XmlParseError(err)
     }
 }
 
+#[cfg(feature = "fast")]
+impl From<::quick_xml::Error> for Error {
+    fn from(err: ::quick_xml::Error) -> Error {
+        Error::FastXmlParseError(err)
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::JsonParseError(err)
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         use Error::*;
@@ -50,8 +104,22 @@ impl fmt::Display for Error {
             MalformedNode(ref reason) => write!(f, "OSM XML error: Malformed node: {:?}", reason),
             MalformedWay(ref reason) => write!(f, "OSM XML error: Malformed way: {:?}", reason),
             MalformedRelation(ref reason) => write!(f, "OSM XML error: Malformed relation: {:?}", reason),
+            MalformedPolygonRule(ref reason) => write!(f, "Malformed polygon rule: {:?}", reason),
+            MalformedFilter(ref reason) => write!(f, "Malformed filter expression: {:?}", reason),
+            MalformedQuery(ref reason) => write!(f, "Malformed Overpass query: {:?}", reason),
+            MalformedSnapshot(ref reason) => write!(f, "Malformed snapshot: {:?}", reason),
             UnknownElement => write!(f, "OSM XML error: Unknown XML element"),
+            ElementNotFound(ref id) => write!(f, "Element not found: {:?}", id),
+            ElementInUse(ref id, ref parents) => {
+                write!(f, "Element {:?} is used by {:?}", id, parents)
+            }
+            InvalidEdit(ref message) => write!(f, "Invalid edit: {}", message),
+            RuntimeError(ref message) => write!(f, "Overpass API error: {}", message),
             XmlParseError(ref reason) => write!(f, "OSM XML parse error: {}", reason),
+            #[cfg(feature = "fast")]
+            FastXmlParseError(ref reason) => write!(f, "OSM XML parse error: {}", reason),
+            Io(ref reason) => write!(f, "I/O error: {}", reason),
+            JsonParseError(ref reason) => write!(f, "JSON parse error: {}", reason),
         }
         
     }
@@ -0,0 +1,170 @@
+use std::borrow::Cow;
+use std::io::BufRead;
+use std::ops::Range;
+use std::str;
+
+use quick_xml::escape;
+use quick_xml::events::{BytesStart, Event as XmlEvent};
+use quick_xml::Reader;
+
+use error::Error;
+use reader::{Attributes, ElementType, Event, Events};
+
+/// `quick-xml` reading backend. Events are read to a buffer reused between events, names and
+/// attribute values are borrowed from it.
+pub(crate) struct FastEvents<R: BufRead> {
+    reader: Reader<R>,
+    buf: Vec<u8>,
+    attributes: Vec<Span>,
+    /// End of the empty element returned last
+    pending_end: Option<Option<ElementType>>,
+}
+
+/// Attributes of a start element, scanned once so that looking up an attribute doesn't parse
+/// the ones before it again.
+pub(crate) struct FastAttributes<'a> {
+    start: BytesStart<'a>,
+    spans: &'a [Span],
+}
+
+/// Attribute key and value positions in `BytesStart::attributes_raw`.
+struct Span {
+    key: Range<usize>,
+    value: Range<usize>,
+}
+
+impl<R: BufRead> FastEvents<R> {
+    pub(crate) fn new(source: R) -> FastEvents<R> {
+        FastEvents {
+            reader: Reader::from_reader(source),
+            buf: Vec::new(),
+            attributes: Vec::new(),
+            pending_end: None,
+        }
+    }
+}
+
+impl<R: BufRead> Events for FastEvents<R> {
+    type Attributes<'a> = FastAttributes<'a> where Self: 'a;
+
+    fn next_event(&mut self) -> Result<Event<'_, FastAttributes<'_>>, Error> {
+        // `<node/>` is reported as start and end like in xml-rs
+        if let Some(element_type) = self.pending_end.take() {
+            return Ok(Event::End(element_type));
+        }
+        self.buf.clear();
+
+        match try!(self.reader.read_event_into(&mut self.buf)) {
+            XmlEvent::Start(start) => {
+                let element_type = ElementType::from_name(start.local_name().as_ref());
+                scan_attributes(start.attributes_raw(), &mut self.attributes);
+
+                Ok(Event::Start(
+                    element_type,
+                    FastAttributes {
+                        start: start,
+                        spans: &self.attributes,
+                    },
+                    false,
+                ))
+            }
+            XmlEvent::Empty(start) => {
+                let element_type = ElementType::from_name(start.local_name().as_ref());
+                scan_attributes(start.attributes_raw(), &mut self.attributes);
+                self.pending_end = Some(element_type);
+
+                Ok(Event::Start(
+                    element_type,
+                    FastAttributes {
+                        start: start,
+                        spans: &self.attributes,
+                    },
+                    true,
+                ))
+            }
+            XmlEvent::End(end) => Ok(Event::End(ElementType::from_name(end.local_name().as_ref()))),
+            XmlEvent::Text(text) => {
+                let text = try!(text.unescape());
+                // xml-rs reports whitespace separately from characters
+                if text.bytes().all(is_whitespace) {
+                    Ok(Event::Other)
+                } else {
+                    Ok(Event::Text(text))
+                }
+            }
+            XmlEvent::CData(cdata) => Ok(Event::Text(
+                try!(cdata.decode().map_err(::quick_xml::Error::from)),
+            )),
+            XmlEvent::Eof => Ok(Event::EndDocument),
+            _ => Ok(Event::Other),
+        }
+    }
+}
+
+impl<'b> Attributes for FastAttributes<'b> {
+    fn value(&self, name: &str) -> Option<Cow<'_, str>> {
+        let raw = self.start.attributes_raw();
+        let span = self.spans.iter().find(|span| {
+            let key = &raw[span.key.clone()];
+            let local_name = match key.iter().position(|&b| b == b':') {
+                Some(colon) => &key[colon + 1..],
+                None => key,
+            };
+            local_name == name.as_bytes()
+        })?;
+
+        let value = str::from_utf8(&raw[span.value.clone()]).ok()?;
+        if value.as_bytes().contains(&b'&') {
+            escape::unescape(value).ok()
+        } else {
+            Some(Cow::Borrowed(value))
+        }
+    }
+}
+
+/// Finds the `key="value"` pairs of `raw`, stops at the first malformed attribute.
+fn scan_attributes(raw: &[u8], spans: &mut Vec<Span>) {
+    spans.clear();
+    let mut pos = 0;
+
+    loop {
+        while pos < raw.len() && is_whitespace(raw[pos]) {
+            pos += 1;
+        }
+        let key_start = pos;
+        while pos < raw.len() && raw[pos] != b'=' && !is_whitespace(raw[pos]) {
+            pos += 1;
+        }
+        let key_end = pos;
+        while pos < raw.len() && is_whitespace(raw[pos]) {
+            pos += 1;
+        }
+        if key_start == key_end || pos >= raw.len() || raw[pos] != b'=' {
+            return;
+        }
+        pos += 1;
+        while pos < raw.len() && is_whitespace(raw[pos]) {
+            pos += 1;
+        }
+
+        let quote = match raw.get(pos) {
+            Some(&quote) if quote == b'"' || quote == b'\'' => quote,
+            _ => return,
+        };
+        let value_start = pos + 1;
+        let value_end = match raw[value_start..].iter().position(|&b| b == quote) {
+            Some(len) => value_start + len,
+            None => return,
+        };
+
+        spans.push(Span {
+            key: key_start..key_end,
+            value: value_start..value_end,
+        });
+        pos = value_end + 1;
+    }
+}
+
+fn is_whitespace(b: u8) -> bool {
+    b == b' ' || b == b'\t' || b == b'\n' || b == b'\r'
+}
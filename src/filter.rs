@@ -0,0 +1,368 @@
+//! Tag filter expressions, eg. `nwr/highway=primary,secondary`.
+//!
+//! Expression is an optional element type prefix (any combination of `n`, `w` and `r` followed
+//! by `/`, default is all types) and a single tag condition:
+//!
+//! - `key` tag exists, `!key` tag does not exist
+//! - `key=a,b` value is one of the listed, `key!=a,b` tag is missing or value is not listed
+//! - `key~regex` value matches the regex, `key!~regex` tag is missing or value does not match
+
+use std::borrow::Cow;
+use std::str::FromStr;
+
+use regex::Regex;
+
+use elements::{Element, ElementId, ElementKind, OSMElement};
+use error::{Error, ErrorReason};
+use store::{self, ElementStore};
+use tags::Tags;
+use OSM;
+
+#[derive(Debug, Clone)]
+pub struct Filter {
+    kinds: Vec<ElementKind>,
+    condition: Condition,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Condition {
+    Exists(String),
+    Missing(String),
+    ValueIn(String, Vec<String>),
+    ValueNotIn(String, Vec<String>),
+    Matches(String, Regex),
+    NotMatches(String, Regex),
+}
+
+impl Filter {
+    pub fn matches<E: OSMElement + ?Sized>(&self, element: &E) -> bool {
+        if !self.kinds.contains(&element.kind()) {
+            return false;
+        }
+
+        self.condition.matches(element.tags())
+    }
+
+    /// Copies matching elements to a new document.
+    pub fn apply(&self, osm: &OSM) -> OSM {
+        let ids: Vec<ElementId> = self.matching_ids(osm);
+        osm.subset(&ids)
+    }
+
+    /// Copies matching elements to a new document along with everything they refer to: nodes
+    /// of matched ways and members of matched relations, recursively.
+    pub fn apply_with_references(&self, osm: &OSM) -> OSM {
+        let matched = self.matching_ids(osm);
+        let mut ids = osm.descendants(matched.iter().cloned());
+        ids.extend(matched);
+        osm.subset(&ids)
+    }
+
+    /// `apply` for any element store, eg. `store::FileStore`.
+    pub fn extract<S: ElementStore + ?Sized>(&self, store: &S) -> Result<OSM, Error> {
+        let ids = try!(self.stored_matching_ids(store));
+        store::extract(store, &ids)
+    }
+
+    /// `apply_with_references` for any element store.
+    pub fn extract_with_references<S>(&self, store: &S) -> Result<OSM, Error>
+    where
+        S: ElementStore + ?Sized,
+    {
+        let matched = try!(self.stored_matching_ids(store));
+        let mut ids = try!(store::descendants(store, matched.iter().cloned()));
+        ids.extend(matched);
+        store::extract(store, &ids)
+    }
+
+    /// Filters element stream, eg. `ElementReader`. Errors are passed through.
+    pub fn filter_stream<I>(&self, elements: I) -> FilteredElements<'_, I>
+    where
+        I: Iterator<Item = Result<Element, Error>>,
+    {
+        FilteredElements {
+            filter: self,
+            elements: elements,
+        }
+    }
+
+    fn matching_ids(&self, osm: &OSM) -> Vec<ElementId> {
+        osm.elements()
+            .filter(|element| self.matches(element))
+            .map(|element| element.element_id())
+            .collect()
+    }
+
+    fn stored_matching_ids<S>(&self, store: &S) -> Result<Vec<ElementId>, Error>
+    where
+        S: ElementStore + ?Sized,
+    {
+        let mut ids = Vec::new();
+        // Stores without the elements in memory are slow to iterate, skip kinds not asked for
+        for kind in &self.kinds {
+            match *kind {
+                ElementKind::Node => try!(self.add_matching(store.nodes(), &mut ids)),
+                ElementKind::Way => try!(self.add_matching(store.ways(), &mut ids)),
+                ElementKind::Relation => try!(self.add_matching(store.relations(), &mut ids)),
+            }
+        }
+        Ok(ids)
+    }
+
+    fn add_matching<'a, T, I>(&self, elements: I, ids: &mut Vec<ElementId>) -> Result<(), Error>
+    where
+        T: OSMElement + Clone + 'a,
+        I: Iterator<Item = Result<Cow<'a, T>, Error>>,
+    {
+        for element in elements {
+            let element = try!(element);
+            if self.condition.matches(element.tags()) {
+                ids.push(element.element_id());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Filter, Error> {
+        let (kinds, expression) = match s.find('/') {
+            Some(pos) if is_kind_prefix(&s[..pos]) => {
+                (try!(parse_kinds(&s[..pos])), &s[pos + 1..])
+            }
+            _ => (
+                vec![ElementKind::Node, ElementKind::Way, ElementKind::Relation],
+                s,
+            ),
+        };
+
+        Ok(Filter {
+            kinds: kinds,
+            condition: try!(parse_condition(expression.trim())),
+        })
+    }
+}
+
+impl Condition {
+    pub(crate) fn matches(&self, tags: &Tags) -> bool {
+        match *self {
+            Condition::Exists(ref key) => tags.contains(key),
+            Condition::Missing(ref key) => !tags.contains(key),
+            Condition::ValueIn(ref key, ref values) => tags.get(key)
+                .map(|val| values.iter().any(|v| v == val))
+                .unwrap_or(false),
+            Condition::ValueNotIn(ref key, ref values) => tags.get(key)
+                .map(|val| !values.iter().any(|v| v == val))
+                .unwrap_or(true),
+            Condition::Matches(ref key, ref regex) => tags.get(key)
+                .map(|val| regex.is_match(val))
+                .unwrap_or(false),
+            Condition::NotMatches(ref key, ref regex) => tags.get(key)
+                .map(|val| !regex.is_match(val))
+                .unwrap_or(true),
+        }
+    }
+}
+
+pub struct FilteredElements<'a, I> {
+    filter: &'a Filter,
+    elements: I,
+}
+
+impl<'a, I> Iterator for FilteredElements<'a, I>
+where
+    I: Iterator<Item = Result<Element, Error>>,
+{
+    type Item = Result<Element, Error>;
+
+    fn next(&mut self) -> Option<Result<Element, Error>> {
+        let filter = self.filter;
+        self.elements.find(|element| match *element {
+            Ok(ref element) => filter.matches(element),
+            Err(_) => true,
+        })
+    }
+}
+
+fn is_kind_prefix(prefix: &str) -> bool {
+    !prefix.is_empty() && prefix.chars().all(|c| c == 'n' || c == 'w' || c == 'r')
+}
+
+fn parse_kinds(prefix: &str) -> Result<Vec<ElementKind>, Error> {
+    let mut kinds = Vec::new();
+    for c in prefix.chars() {
+        let kind = match c {
+            'n' => ElementKind::Node,
+            'w' => ElementKind::Way,
+            'r' => ElementKind::Relation,
+            _ => return Err(Error::MalformedFilter(ErrorReason::InvalidValue)),
+        };
+        if !kinds.contains(&kind) {
+            kinds.push(kind);
+        }
+    }
+
+    Ok(kinds)
+}
+
+fn parse_condition(expression: &str) -> Result<Condition, Error> {
+    if let Some(key) = expression.strip_prefix('!') {
+        return Ok(Condition::Missing(try!(parse_key(key))));
+    }
+
+    let operator = expression.find(['=', '~']);
+    let pos = match operator {
+        Some(pos) => pos,
+        None => return Ok(Condition::Exists(try!(parse_key(expression)))),
+    };
+
+    let negated = pos > 0 && expression.as_bytes()[pos - 1] == b'!';
+    let key_end = if negated { pos - 1 } else { pos };
+    let key = try!(parse_key(&expression[..key_end]));
+    let value = &expression[pos + 1..];
+
+    if expression.as_bytes()[pos] == b'~' {
+        let regex = try!(
+            Regex::new(value).map_err(|err| Error::MalformedFilter(ErrorReason::from(err)))
+        );
+        return Ok(if negated {
+            Condition::NotMatches(key, regex)
+        } else {
+            Condition::Matches(key, regex)
+        });
+    }
+
+    if value == "*" {
+        return Ok(if negated {
+            Condition::Missing(key)
+        } else {
+            Condition::Exists(key)
+        });
+    }
+
+    let values: Vec<String> = value.split(',').map(|val| val.trim().to_string()).collect();
+    if values.iter().any(|val| val.is_empty()) {
+        return Err(Error::MalformedFilter(ErrorReason::Missing));
+    }
+
+    Ok(if negated {
+        Condition::ValueNotIn(key, values)
+    } else {
+        Condition::ValueIn(key, values)
+    })
+}
+
+fn parse_key(key: &str) -> Result<String, Error> {
+    let key = key.trim();
+    if key.is_empty() {
+        return Err(Error::MalformedFilter(ErrorReason::Missing));
+    }
+
+    Ok(key.to_string())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use elements::{Geometry, Metadata, Node, Relation, Tag, Way};
+
+    fn node(tags: &[(&str, &str)]) -> Node {
+        Node {
+            id: 1,
+            lat: 0.0,
+            lon: 0.0,
+            tags: tags.iter().map(|&(k, v)| Tag::new(k, v)).collect(),
+            meta: Metadata::default(),
+        }
+    }
+
+    fn way(tags: &[(&str, &str)]) -> Way {
+        Way {
+            id: 1,
+            tags: tags.iter().map(|&(k, v)| Tag::new(k, v)).collect(),
+            nodes: Vec::new(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
+        }
+    }
+
+    fn filter(expression: &str) -> Filter {
+        expression.parse().unwrap()
+    }
+
+    #[test]
+    fn key_existence() {
+        assert!(filter("w/building").matches(&way(&[("building", "yes")])));
+        assert!(!filter("w/building").matches(&node(&[("building", "yes")])));
+        assert!(!filter("building").matches(&way(&[("highway", "primary")])));
+        assert!(filter("!building").matches(&way(&[("highway", "primary")])));
+        assert!(filter("building=*").matches(&node(&[("building", "no")])));
+    }
+
+    #[test]
+    fn value_lists() {
+        let f = filter("nwr/highway=primary,secondary");
+        assert!(f.matches(&way(&[("highway", "secondary")])));
+        assert!(f.matches(&node(&[("highway", "primary")])));
+        assert!(!f.matches(&way(&[("highway", "tertiary")])));
+        assert!(!f.matches(&way(&[])));
+    }
+
+    #[test]
+    fn negated_values() {
+        let f = filter("n/amenity!=bench");
+        assert!(f.matches(&node(&[("amenity", "cafe")])));
+        assert!(f.matches(&node(&[])));
+        assert!(!f.matches(&node(&[("amenity", "bench")])));
+        assert!(!f.matches(&way(&[("amenity", "cafe")])));
+    }
+
+    #[test]
+    fn regex_values() {
+        let f = filter("name~^Helsinki");
+        assert!(f.matches(&node(&[("name", "Helsinki-Vantaa")])));
+        assert!(!f.matches(&node(&[("name", "Vantaa")])));
+        assert!(!f.matches(&node(&[])));
+
+        let f = filter("name!~^Helsinki");
+        assert!(f.matches(&node(&[("name", "Vantaa")])));
+        assert!(f.matches(&node(&[])));
+    }
+
+    #[test]
+    fn relation_type() {
+        let relation = Relation {
+            id: 1,
+            members: Vec::new(),
+            tags: vec![Tag::new("type", "multipolygon")].into(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
+        };
+        assert!(filter("r/type=multipolygon").matches(&relation));
+        assert!(!filter("nw/type=multipolygon").matches(&relation));
+    }
+
+    #[test]
+    fn slash_in_key_is_not_type_prefix() {
+        let f = filter("note/fixme");
+        assert!(f.matches(&node(&[("note/fixme", "x")])));
+    }
+
+    #[test]
+    fn malformed_expressions() {
+        match "w/".parse::<Filter>() {
+            Err(Error::MalformedFilter(ErrorReason::Missing)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match "highway=primary,".parse::<Filter>() {
+            Err(Error::MalformedFilter(ErrorReason::Missing)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match "name~(".parse::<Filter>() {
+            Err(Error::MalformedFilter(ErrorReason::Regex(_))) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+}
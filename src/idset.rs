@@ -0,0 +1,663 @@
+use std::collections::btree_map::{self, BTreeMap};
+use std::fmt;
+use std::iter::FromIterator;
+
+use elements::{ElementId, ElementKind, Id};
+
+const CHUNK_BITS: u32 = 16;
+const CHUNK_SIZE: usize = 1 << CHUNK_BITS;
+const WORDS: usize = CHUNK_SIZE / 64;
+// Above this a bitmap takes less memory than the sorted ids
+const SPARSE_MAX: usize = CHUNK_SIZE / 16;
+
+/// Set of element ids for collecting large numbers of ids, eg. all nodes of the selected ways.
+///
+/// Ids are split to chunks of 65536 consecutive ids. Chunks with few ids keep them in a sorted
+/// array and full ones in a bitmap, so dense id ranges like those of regular OSM files take
+/// about one bit per id. Negative ids are supported and iteration is in ascending order.
+#[derive(Clone, Default, PartialEq, Eq)]
+pub struct IdSet {
+    chunks: BTreeMap<i64, Chunk>,
+    len: usize,
+}
+
+/// Ids of one chunk, bitmap exactly when there are more than `SPARSE_MAX` of them so that
+/// equal sets have equal chunks.
+#[derive(Clone, PartialEq, Eq)]
+enum Chunk {
+    Sparse(Vec<u16>),
+    Dense(Box<[u64; WORDS]>, usize),
+}
+
+impl IdSet {
+    pub fn new() -> IdSet {
+        IdSet::default()
+    }
+
+    /// Adds the id, returns whether it was not in the set.
+    pub fn insert(&mut self, id: Id) -> bool {
+        let (high, low) = split(id);
+        let inserted = self.chunks
+            .entry(high)
+            .or_insert_with(|| Chunk::Sparse(Vec::new()))
+            .insert(low);
+        if inserted {
+            self.len += 1;
+        }
+        inserted
+    }
+
+    /// Removes the id, returns whether it was in the set.
+    pub fn remove(&mut self, id: Id) -> bool {
+        let (high, low) = split(id);
+        let (removed, empty) = match self.chunks.get_mut(&high) {
+            Some(chunk) => (chunk.remove(low), chunk.len() == 0),
+            None => return false,
+        };
+        if empty {
+            self.chunks.remove(&high);
+        }
+        if removed {
+            self.len -= 1;
+        }
+        removed
+    }
+
+    pub fn contains(&self, id: Id) -> bool {
+        let (high, low) = split(id);
+        self.chunks
+            .get(&high)
+            .is_some_and(|chunk| chunk.contains(low))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.chunks.clear();
+        self.len = 0;
+    }
+
+    /// Iterates over the ids in ascending order.
+    pub fn iter(&self) -> Ids<'_> {
+        Ids {
+            chunks: self.chunks.iter(),
+            current: None,
+            position: 0,
+        }
+    }
+
+    /// Adds all ids of `other` to the set.
+    pub fn union_with(&mut self, other: &IdSet) {
+        for (&high, chunk) in &other.chunks {
+            let union = match self.chunks.get(&high) {
+                Some(own) => own.union(chunk),
+                None => chunk.clone(),
+            };
+            self.replace(high, Some(union));
+        }
+    }
+
+    pub fn union(&self, other: &IdSet) -> IdSet {
+        let mut union = self.clone();
+        union.union_with(other);
+        union
+    }
+
+    pub fn intersection(&self, other: &IdSet) -> IdSet {
+        let mut intersection = IdSet::new();
+        for (&high, chunk) in &self.chunks {
+            if let Some(other_chunk) = other.chunks.get(&high) {
+                intersection.replace(high, chunk.intersection(other_chunk));
+            }
+        }
+        intersection
+    }
+
+    /// Ids of the set which are not in `other`.
+    pub fn difference(&self, other: &IdSet) -> IdSet {
+        let mut difference = self.clone();
+        for (&high, chunk) in &self.chunks {
+            if let Some(other_chunk) = other.chunks.get(&high) {
+                difference.replace(high, chunk.difference(other_chunk));
+            }
+        }
+        difference
+    }
+
+    fn replace(&mut self, high: i64, chunk: Option<Chunk>) {
+        if let Some(old) = self.chunks.remove(&high) {
+            self.len -= old.len();
+        }
+        if let Some(chunk) = chunk {
+            self.len += chunk.len();
+            self.chunks.insert(high, chunk);
+        }
+    }
+}
+
+impl fmt::Debug for IdSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.iter()).finish()
+    }
+}
+
+impl Extend<Id> for IdSet {
+    fn extend<I: IntoIterator<Item = Id>>(&mut self, ids: I) {
+        for id in ids {
+            self.insert(id);
+        }
+    }
+}
+
+impl FromIterator<Id> for IdSet {
+    fn from_iter<I: IntoIterator<Item = Id>>(ids: I) -> IdSet {
+        let mut set = IdSet::new();
+        set.extend(ids);
+        set
+    }
+}
+
+impl<'a> IntoIterator for &'a IdSet {
+    type Item = Id;
+    type IntoIter = Ids<'a>;
+
+    fn into_iter(self) -> Ids<'a> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for IdSet {
+    type Item = Id;
+    type IntoIter = IntoIds;
+
+    fn into_iter(self) -> IntoIds {
+        IntoIds {
+            chunks: self.chunks.into_iter(),
+            current: None,
+            position: 0,
+        }
+    }
+}
+
+/// Iterator over the ids of `IdSet` in ascending order.
+pub struct Ids<'a> {
+    chunks: btree_map::Iter<'a, i64, Chunk>,
+    current: Option<(i64, &'a Chunk)>,
+    position: usize,
+}
+
+impl<'a> Iterator for Ids<'a> {
+    type Item = Id;
+
+    fn next(&mut self) -> Option<Id> {
+        loop {
+            if let Some((high, chunk)) = self.current {
+                if let Some(low) = chunk.next_from(&mut self.position) {
+                    return Some(join(high, low));
+                }
+            }
+
+            let (&high, chunk) = self.chunks.next()?;
+            self.current = Some((high, chunk));
+            self.position = 0;
+        }
+    }
+}
+
+/// Owning iterator over the ids of `IdSet` in ascending order.
+pub struct IntoIds {
+    chunks: btree_map::IntoIter<i64, Chunk>,
+    current: Option<(i64, Chunk)>,
+    position: usize,
+}
+
+impl Iterator for IntoIds {
+    type Item = Id;
+
+    fn next(&mut self) -> Option<Id> {
+        loop {
+            if let Some((high, ref chunk)) = self.current {
+                if let Some(low) = chunk.next_from(&mut self.position) {
+                    return Some(join(high, low));
+                }
+            }
+
+            self.current = Some(self.chunks.next()?);
+            self.position = 0;
+        }
+    }
+}
+
+impl Chunk {
+    fn len(&self) -> usize {
+        match *self {
+            Chunk::Sparse(ref ids) => ids.len(),
+            Chunk::Dense(_, len) => len,
+        }
+    }
+
+    fn contains(&self, low: u16) -> bool {
+        match *self {
+            Chunk::Sparse(ref ids) => ids.binary_search(&low).is_ok(),
+            Chunk::Dense(ref words, _) => words[low as usize / 64] & bit(low) != 0,
+        }
+    }
+
+    fn insert(&mut self, low: u16) -> bool {
+        let full = match *self {
+            Chunk::Sparse(ref mut ids) => match ids.binary_search(&low) {
+                Ok(_) => return false,
+                Err(index) => {
+                    ids.insert(index, low);
+                    ids.len() > SPARSE_MAX
+                }
+            },
+            Chunk::Dense(ref mut words, ref mut len) => {
+                let word = &mut words[low as usize / 64];
+                if *word & bit(low) != 0 {
+                    return false;
+                }
+                *word |= bit(low);
+                *len += 1;
+                false
+            }
+        };
+
+        if full {
+            *self = Chunk::from_words(self.words());
+        }
+        true
+    }
+
+    fn remove(&mut self, low: u16) -> bool {
+        let few = match *self {
+            Chunk::Sparse(ref mut ids) => match ids.binary_search(&low) {
+                Ok(index) => {
+                    ids.remove(index);
+                    return true;
+                }
+                Err(_) => return false,
+            },
+            Chunk::Dense(ref mut words, ref mut len) => {
+                let word = &mut words[low as usize / 64];
+                if *word & bit(low) == 0 {
+                    return false;
+                }
+                *word &= !bit(low);
+                *len -= 1;
+                *len <= SPARSE_MAX
+            }
+        };
+
+        if few {
+            *self = Chunk::from_words(self.words());
+        }
+        true
+    }
+
+    /// Next id at or after `position`, advancing the position past it.
+    fn next_from(&self, position: &mut usize) -> Option<u16> {
+        match *self {
+            Chunk::Sparse(ref ids) => {
+                let low = ids.get(*position).cloned();
+                *position += 1;
+                low
+            }
+            Chunk::Dense(ref words, _) => {
+                while *position < CHUNK_SIZE {
+                    let word = words[*position / 64] >> (*position % 64);
+                    if word == 0 {
+                        *position = (*position / 64 + 1) * 64;
+                        continue;
+                    }
+                    let low = *position + word.trailing_zeros() as usize;
+                    *position = low + 1;
+                    return Some(low as u16);
+                }
+                None
+            }
+        }
+    }
+
+    fn words(&self) -> Box<[u64; WORDS]> {
+        match *self {
+            Chunk::Sparse(ref ids) => {
+                let mut words = Box::new([0; WORDS]);
+                for &low in ids {
+                    words[low as usize / 64] |= bit(low);
+                }
+                words
+            }
+            Chunk::Dense(ref words, _) => words.clone(),
+        }
+    }
+
+    /// Chunk of the ids in the bitmap.
+    fn from_words(words: Box<[u64; WORDS]>) -> Chunk {
+        let len = words.iter().map(|word| word.count_ones() as usize).sum();
+        if len > SPARSE_MAX {
+            return Chunk::Dense(words, len);
+        }
+
+        let mut ids = Vec::with_capacity(len);
+        for (index, &word) in words.iter().enumerate() {
+            let mut word = word;
+            while word != 0 {
+                ids.push((index * 64) as u16 + word.trailing_zeros() as u16);
+                word &= word - 1;
+            }
+        }
+        Chunk::Sparse(ids)
+    }
+
+    fn union(&self, other: &Chunk) -> Chunk {
+        match (self, other) {
+            (Chunk::Sparse(a), Chunk::Sparse(b)) if a.len() + b.len() <= SPARSE_MAX => {
+                let mut ids = Vec::with_capacity(a.len() + b.len());
+                let (mut i, mut j) = (0, 0);
+                while i < a.len() && j < b.len() {
+                    if a[i] < b[j] {
+                        ids.push(a[i]);
+                        i += 1;
+                    } else if a[i] > b[j] {
+                        ids.push(b[j]);
+                        j += 1;
+                    } else {
+                        ids.push(a[i]);
+                        i += 1;
+                        j += 1;
+                    }
+                }
+                ids.extend_from_slice(&a[i..]);
+                ids.extend_from_slice(&b[j..]);
+                Chunk::Sparse(ids)
+            }
+            _ => {
+                let mut words = self.words();
+                for (word, other) in words.iter_mut().zip(other.words().iter()) {
+                    *word |= *other;
+                }
+                Chunk::from_words(words)
+            }
+        }
+    }
+
+    fn intersection(&self, other: &Chunk) -> Option<Chunk> {
+        let chunk = match (self, other) {
+            (Chunk::Sparse(ids), _) => {
+                Chunk::Sparse(ids.iter().cloned().filter(|&low| other.contains(low)).collect())
+            }
+            (_, Chunk::Sparse(ids)) => {
+                Chunk::Sparse(ids.iter().cloned().filter(|&low| self.contains(low)).collect())
+            }
+            (Chunk::Dense(a, _), Chunk::Dense(b, _)) => {
+                let mut words = a.clone();
+                for (word, other) in words.iter_mut().zip(b.iter()) {
+                    *word &= *other;
+                }
+                Chunk::from_words(words)
+            }
+        };
+        non_empty(chunk)
+    }
+
+    fn difference(&self, other: &Chunk) -> Option<Chunk> {
+        let chunk = match *self {
+            Chunk::Sparse(ref ids) => {
+                Chunk::Sparse(ids.iter().cloned().filter(|&low| !other.contains(low)).collect())
+            }
+            Chunk::Dense(ref words, _) => {
+                let mut words = words.clone();
+                for (word, other) in words.iter_mut().zip(other.words().iter()) {
+                    *word &= !*other;
+                }
+                Chunk::from_words(words)
+            }
+        };
+        non_empty(chunk)
+    }
+}
+
+fn non_empty(chunk: Chunk) -> Option<Chunk> {
+    if chunk.len() > 0 {
+        Some(chunk)
+    } else {
+        None
+    }
+}
+
+fn split(id: Id) -> (i64, u16) {
+    (id >> CHUNK_BITS, (id & 0xffff) as u16)
+}
+
+fn join(high: i64, low: u16) -> Id {
+    (high << CHUNK_BITS) | Id::from(low)
+}
+
+fn bit(low: u16) -> u64 {
+    1 << (low % 64)
+}
+
+/// Set of node, way and relation ids, see `IdSet`. Iteration is in file order: nodes first, then
+/// ways and relations, each in ascending id order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ElementIdSet {
+    pub nodes: IdSet,
+    pub ways: IdSet,
+    pub relations: IdSet,
+}
+
+impl ElementIdSet {
+    pub fn new() -> ElementIdSet {
+        ElementIdSet::default()
+    }
+
+    /// Ids of the given kind.
+    pub fn ids(&self, kind: ElementKind) -> &IdSet {
+        match kind {
+            ElementKind::Node => &self.nodes,
+            ElementKind::Way => &self.ways,
+            ElementKind::Relation => &self.relations,
+        }
+    }
+
+    pub fn ids_mut(&mut self, kind: ElementKind) -> &mut IdSet {
+        match kind {
+            ElementKind::Node => &mut self.nodes,
+            ElementKind::Way => &mut self.ways,
+            ElementKind::Relation => &mut self.relations,
+        }
+    }
+
+    /// Adds the id, returns whether it was not in the set.
+    pub fn insert(&mut self, id: ElementId) -> bool {
+        self.ids_mut(id.kind()).insert(id.id())
+    }
+
+    /// Removes the id, returns whether it was in the set.
+    pub fn remove(&mut self, id: ElementId) -> bool {
+        self.ids_mut(id.kind()).remove(id.id())
+    }
+
+    pub fn contains(&self, id: ElementId) -> bool {
+        self.ids(id.kind()).contains(id.id())
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len() + self.ways.len() + self.relations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn iter(&self) -> ElementIds<Ids<'_>> {
+        ElementIds {
+            nodes: self.nodes.iter(),
+            ways: self.ways.iter(),
+            relations: self.relations.iter(),
+        }
+    }
+
+    pub fn union_with(&mut self, other: &ElementIdSet) {
+        self.nodes.union_with(&other.nodes);
+        self.ways.union_with(&other.ways);
+        self.relations.union_with(&other.relations);
+    }
+
+    pub fn union(&self, other: &ElementIdSet) -> ElementIdSet {
+        ElementIdSet {
+            nodes: self.nodes.union(&other.nodes),
+            ways: self.ways.union(&other.ways),
+            relations: self.relations.union(&other.relations),
+        }
+    }
+
+    pub fn intersection(&self, other: &ElementIdSet) -> ElementIdSet {
+        ElementIdSet {
+            nodes: self.nodes.intersection(&other.nodes),
+            ways: self.ways.intersection(&other.ways),
+            relations: self.relations.intersection(&other.relations),
+        }
+    }
+
+    pub fn difference(&self, other: &ElementIdSet) -> ElementIdSet {
+        ElementIdSet {
+            nodes: self.nodes.difference(&other.nodes),
+            ways: self.ways.difference(&other.ways),
+            relations: self.relations.difference(&other.relations),
+        }
+    }
+}
+
+impl Extend<ElementId> for ElementIdSet {
+    fn extend<I: IntoIterator<Item = ElementId>>(&mut self, ids: I) {
+        for id in ids {
+            self.insert(id);
+        }
+    }
+}
+
+impl FromIterator<ElementId> for ElementIdSet {
+    fn from_iter<I: IntoIterator<Item = ElementId>>(ids: I) -> ElementIdSet {
+        let mut set = ElementIdSet::new();
+        set.extend(ids);
+        set
+    }
+}
+
+impl<'a> IntoIterator for &'a ElementIdSet {
+    type Item = ElementId;
+    type IntoIter = ElementIds<Ids<'a>>;
+
+    fn into_iter(self) -> ElementIds<Ids<'a>> {
+        self.iter()
+    }
+}
+
+impl IntoIterator for ElementIdSet {
+    type Item = ElementId;
+    type IntoIter = ElementIds<IntoIds>;
+
+    fn into_iter(self) -> ElementIds<IntoIds> {
+        ElementIds {
+            nodes: self.nodes.into_iter(),
+            ways: self.ways.into_iter(),
+            relations: self.relations.into_iter(),
+        }
+    }
+}
+
+/// Iterator over the ids of `ElementIdSet`.
+pub struct ElementIds<I> {
+    nodes: I,
+    ways: I,
+    relations: I,
+}
+
+impl<I: Iterator<Item = Id>> Iterator for ElementIds<I> {
+    type Item = ElementId;
+
+    fn next(&mut self) -> Option<ElementId> {
+        if let Some(id) = self.nodes.next() {
+            return Some(ElementId::Node(id));
+        }
+        if let Some(id) = self.ways.next() {
+            return Some(ElementId::Way(id));
+        }
+        self.relations.next().map(ElementId::Relation)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn check_against_sorted(set: &IdSet, ids: &[Id]) {
+        let mut expected = ids.to_vec();
+        expected.sort();
+        expected.dedup();
+        assert_eq!(set.iter().collect::<Vec<_>>(), expected);
+        assert_eq!(set.clone().into_iter().collect::<Vec<_>>(), expected);
+        assert_eq!(set.len(), expected.len());
+    }
+
+    #[test]
+    fn negative_and_extreme_ids() {
+        let ids = [0, -1, 1, -65536, -65537, 65535, 65536, Id::MIN, Id::MAX, -5, -5];
+        let set: IdSet = ids.iter().cloned().collect();
+        check_against_sorted(&set, &ids);
+        assert!(set.contains(Id::MIN));
+        assert!(!set.contains(2));
+    }
+
+    #[test]
+    fn chunks_switch_between_sparse_and_dense() {
+        let mut set = IdSet::new();
+        let ids: Vec<Id> = (0..10_000).map(|i| i * 3).collect();
+        set.extend(ids.iter().cloned());
+        check_against_sorted(&set, &ids);
+        match set.chunks[&0] {
+            Chunk::Dense(..) => (),
+            _ => panic!("expected bitmap chunk"),
+        }
+
+        for &id in &ids[1000..] {
+            assert!(set.remove(id));
+        }
+        assert!(!set.remove(ids[1000]));
+        check_against_sorted(&set, &ids[..1000]);
+        match set.chunks[&0] {
+            Chunk::Sparse(..) => (),
+            _ => panic!("expected sparse chunk"),
+        }
+
+        // Same contents compare equal however they were built
+        let rebuilt: IdSet = ids[..1000].iter().cloned().collect();
+        assert_eq!(set, rebuilt);
+    }
+
+    #[test]
+    fn set_operations() {
+        let a: IdSet = (0..6000).chain(100_000..100_010).chain(-20..-10).collect();
+        let b: IdSet = (3000..9000).chain(-15..-5).collect();
+
+        let union: Vec<Id> = (-20..-5).chain(0..9000).chain(100_000..100_010).collect();
+        check_against_sorted(&a.union(&b), &union);
+
+        let intersection: Vec<Id> = (-15..-10).chain(3000..6000).collect();
+        check_against_sorted(&a.intersection(&b), &intersection);
+
+        let difference: Vec<Id> = (-20..-15).chain(0..3000).chain(100_000..100_010).collect();
+        check_against_sorted(&a.difference(&b), &difference);
+
+        assert!(a.intersection(&IdSet::new()).is_empty());
+        assert_eq!(a.difference(&IdSet::new()), a);
+    }
+}
@@ -0,0 +1,419 @@
+//! Byte offset index of the elements of a document, for loading single elements from large files
+//! without parsing all of them.
+//!
+//! ```no_run
+//! use osm_xml::ElementId;
+//! use osm_xml::index::IndexedFile;
+//!
+//! // Builds `planet.osm.idx` next to the file on first use
+//! let file = IndexedFile::open("planet.osm").unwrap();
+//! let way = file.way_with_nodes(4_253_164).unwrap();
+//! let node = file.element(ElementId::Node(1)).unwrap();
+//! ```
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use chunks::{self, Scanner};
+use elements::{Element, ElementId, Id, Node, OSMElement, Relation, UnresolvedReference, Way};
+use error::Error;
+use positioned::PositionedReader;
+use reader::{ElementReader, ElementType};
+use OSM;
+
+const READ_SIZE: usize = 64 * 1024;
+const MAGIC: &[u8; 8] = b"OSMIDX\0\x01";
+
+/// Offsets of the start tags of the nodes, ways and relations of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ElementIndex {
+    // Sorted by element id
+    entries: Vec<(ElementId, u64)>,
+    source_len: u64,
+}
+
+impl ElementIndex {
+    /// Indexes the document with one pass over it, without parsing the elements.
+    ///
+    /// When an element occurs more than once, the last one is indexed like `OSM::parse` would
+    /// keep it.
+    pub fn build<R: Read>(source: R) -> Result<ElementIndex, Error> {
+        let mut entries = Vec::new();
+        let source_len = try!(scan_elements(source, |id, offset| {
+            entries.push((id, offset));
+            true
+        }));
+
+        // Stable sort of the reversed entries keeps the last occurrence first
+        entries.reverse();
+        entries.sort_by_key(|&(id, _)| id);
+        entries.dedup_by_key(|&mut (id, _)| id);
+
+        Ok(ElementIndex {
+            entries: entries,
+            source_len: source_len,
+        })
+    }
+
+    /// Offset of the start tag of the element.
+    pub fn offset(&self, id: ElementId) -> Option<u64> {
+        self.entries
+            .binary_search_by_key(&id, |&(id, _)| id)
+            .ok()
+            .map(|index| self.entries[index].1)
+    }
+
+    pub fn contains(&self, id: ElementId) -> bool {
+        self.offset(id).is_some()
+    }
+
+    /// Iterates over the indexed elements and their offsets in element id order.
+    pub fn iter(&self) -> impl Iterator<Item = (ElementId, u64)> + '_ {
+        self.entries.iter().cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Length of the indexed document in bytes.
+    pub fn source_len(&self) -> u64 {
+        self.source_len
+    }
+
+    /// Writes the index in a compact binary format.
+    pub fn write_to<W: Write>(&self, mut out: W) -> Result<(), Error> {
+        try!(out.write_all(MAGIC));
+        try!(out.write_all(&self.source_len.to_le_bytes()));
+        try!(out.write_all(&(self.entries.len() as u64).to_le_bytes()));
+
+        for &(id, offset) in &self.entries {
+            let kind: u8 = match id {
+                ElementId::Node(_) => 0,
+                ElementId::Way(_) => 1,
+                ElementId::Relation(_) => 2,
+            };
+            try!(out.write_all(&[kind]));
+            try!(out.write_all(&id.id().to_le_bytes()));
+            try!(out.write_all(&offset.to_le_bytes()));
+        }
+
+        try!(out.flush());
+        Ok(())
+    }
+
+    /// Reads an index written with `write_to`.
+    pub fn read_from<R: Read>(mut source: R) -> Result<ElementIndex, Error> {
+        let mut magic = [0; 8];
+        try!(source.read_exact(&mut magic));
+        if &magic != MAGIC {
+            return Err(invalid_data("not an element index"));
+        }
+
+        let source_len = try!(read_u64(&mut source));
+        let len = try!(read_u64(&mut source));
+
+        let mut entries = Vec::new();
+        let mut previous = None;
+        for _ in 0..len {
+            let mut kind = [0; 1];
+            try!(source.read_exact(&mut kind));
+            let id = try!(read_u64(&mut source)) as Id;
+            let offset = try!(read_u64(&mut source));
+
+            let id = match kind[0] {
+                0 => ElementId::Node(id),
+                1 => ElementId::Way(id),
+                2 => ElementId::Relation(id),
+                _ => return Err(invalid_data("unknown element kind in index")),
+            };
+            // Lookups rely on the order
+            if let Some(previous) = previous {
+                if previous >= id {
+                    return Err(invalid_data("element index is not sorted"));
+                }
+            }
+            previous = Some(id);
+            entries.push((id, offset));
+        }
+
+        Ok(ElementIndex {
+            entries: entries,
+            source_len: source_len,
+        })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let file = try!(File::create(path));
+        self.write_to(BufWriter::new(file))
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ElementIndex, Error> {
+        let file = try!(File::open(path));
+        ElementIndex::read_from(BufReader::new(file))
+    }
+}
+
+/// Document file with an index, loads elements by seeking to them and parsing only them.
+#[derive(Debug)]
+pub struct IndexedFile {
+    file: File,
+    index: ElementIndex,
+}
+
+impl IndexedFile {
+    /// Opens the file with the index in the sidecar file `<path>.idx`. The index is built and
+    /// saved when the sidecar is missing, unreadable or older than the file. Saving is best
+    /// effort, the index is rebuilt on every open when the sidecar can't be written, eg. in a
+    /// read-only directory.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<IndexedFile, Error> {
+        let path = path.as_ref();
+        let file = try!(File::open(path));
+        let sidecar = sidecar_path(path);
+
+        let index = match current_index(&file, &sidecar) {
+            Some(index) => index,
+            None => {
+                let index = try!(ElementIndex::build(&file));
+                index.save(&sidecar).ok();
+                index
+            }
+        };
+
+        Ok(IndexedFile::with_index(file, index))
+    }
+
+    /// Uses the index built earlier, which must have been built from the same file.
+    pub fn with_index(file: File, index: ElementIndex) -> IndexedFile {
+        IndexedFile {
+            file: file,
+            index: index,
+        }
+    }
+
+    pub fn index(&self) -> &ElementIndex {
+        &self.index
+    }
+
+    /// Loads the element, `None` when it's not in the index. An error is returned when the
+    /// indexed element is malformed or the file has changed since it was indexed.
+    pub fn element(&self, id: ElementId) -> Result<Option<Element>, Error> {
+        let offset = match self.index.offset(id) {
+            Some(offset) => offset,
+            None => return Ok(None),
+        };
+
+        // Only the element at the offset is parsed, reading stops at its end
+        let open: &[u8] = b"<osm>";
+        let source = open.chain(BufReader::new(PositionedReader::new(&self.file, offset)));
+
+        match try!(ElementReader::new(source).first_element()) {
+            Some(element) => if element.element_id() == id {
+                Ok(Some(element))
+            } else {
+                Err(invalid_data("indexed element not found at its offset"))
+            },
+            None => Err(invalid_data("indexed element not found at its offset")),
+        }
+    }
+
+    pub fn node(&self, id: Id) -> Result<Option<Node>, Error> {
+        match try!(self.element(ElementId::Node(id))) {
+            Some(Element::Node(node)) => Ok(Some(node)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn way(&self, id: Id) -> Result<Option<Way>, Error> {
+        match try!(self.element(ElementId::Way(id))) {
+            Some(Element::Way(way)) => Ok(Some(way)),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn relation(&self, id: Id) -> Result<Option<Relation>, Error> {
+        match try!(self.element(ElementId::Relation(id))) {
+            Some(Element::Relation(relation)) => Ok(Some(relation)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Loads the way and its nodes into a document of their own, so that the node references
+    /// of the way can be resolved with `OSM::resolve_reference`. Nodes missing from the file are
+    /// left out.
+    pub fn way_with_nodes(&self, id: Id) -> Result<Option<OSM>, Error> {
+        let way = match try!(self.way(id)) {
+            Some(way) => way,
+            None => return Ok(None),
+        };
+
+        let mut osm = OSM::new();
+        for reference in &way.nodes {
+            if let UnresolvedReference::Node(node_id) = *reference {
+                if osm.nodes.contains_key(&node_id) {
+                    continue;
+                }
+                if let Some(node) = try!(self.node(node_id)) {
+                    osm.nodes.insert(node_id, node);
+                }
+            }
+        }
+        osm.ways.insert(way.id, way);
+
+        Ok(Some(osm))
+    }
+}
+
+/// Calls `found` with the id and the start tag offset of each top level node, way and relation
+/// in document order, without parsing them, until it returns `false`. Returns the number of
+/// bytes read.
+pub(crate) fn scan_elements<R, F>(mut source: R, mut found: F) -> Result<u64, Error>
+where
+    R: Read,
+    F: FnMut(ElementId, u64) -> bool,
+{
+    let mut scanner = Scanner::new();
+    let mut read_buf = vec![0; READ_SIZE];
+    let mut buf = Vec::new();
+    // Offset of `buf` in the document
+    let mut base = 0u64;
+
+    loop {
+        let len = match source.read(&mut read_buf) {
+            Ok(0) => break,
+            Ok(len) => len,
+            Err(ref err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(Error::Io(err)),
+        };
+        buf.extend_from_slice(&read_buf[..len]);
+
+        while let Some(start) = scanner.next_element(&buf) {
+            // The scanner only returns elements with a complete start tag
+            if let Some(end) = chunks::tag_end(&buf, start) {
+                if let Some(id) = start_tag_id(&buf[start..end]) {
+                    if !found(id, base + start as u64) {
+                        return Ok(base + buf.len() as u64);
+                    }
+                }
+            }
+        }
+
+        let scanned = scanner.position();
+        buf.drain(..scanned);
+        scanner.consume(scanned);
+        base += scanned as u64;
+    }
+
+    Ok(base + buf.len() as u64)
+}
+
+/// Id of a node, way or relation from its start tag, `None` for other elements.
+fn start_tag_id(tag: &[u8]) -> Option<ElementId> {
+    let tag = &tag[1..];
+    let name_len = tag
+        .iter()
+        .position(|&b| b.is_ascii_whitespace() || b == b'/' || b == b'>')
+        .unwrap_or(tag.len());
+
+    let make_id: fn(Id) -> ElementId = match ElementType::from_name(&tag[..name_len]) {
+        Some(ElementType::Node) => ElementId::Node,
+        Some(ElementType::Way) => ElementId::Way,
+        Some(ElementType::Relation) => ElementId::Relation,
+        _ => return None,
+    };
+
+    let mut rest = &tag[name_len..];
+    loop {
+        rest = trim_start(rest);
+        let name_len = rest
+            .iter()
+            .position(|&b| b == b'=' || b.is_ascii_whitespace())?;
+        let name = &rest[..name_len];
+
+        rest = trim_start(&rest[name_len..]);
+        if rest.first() != Some(&b'=') {
+            return None;
+        }
+        rest = trim_start(&rest[1..]);
+
+        let quote = *rest.first()?;
+        if quote != b'"' && quote != b'\'' {
+            return None;
+        }
+        let value_len = rest[1..].iter().position(|&b| b == quote)?;
+        let value = &rest[1..1 + value_len];
+        rest = &rest[value_len + 2..];
+
+        if name.eq_ignore_ascii_case(b"id") {
+            let value = ::std::str::from_utf8(value).ok()?;
+            return value.trim().parse().ok().map(make_id);
+        }
+    }
+}
+
+fn trim_start(data: &[u8]) -> &[u8] {
+    let start = data
+        .iter()
+        .position(|b| !b.is_ascii_whitespace())
+        .unwrap_or(data.len());
+    &data[start..]
+}
+
+fn read_u64<R: Read>(source: &mut R) -> Result<u64, Error> {
+    let mut bytes = [0; 8];
+    try!(source.read_exact(&mut bytes));
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
+
+fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = OsString::from(path.as_os_str());
+    sidecar.push(".idx");
+    PathBuf::from(sidecar)
+}
+
+/// Index from the sidecar when it's up to date with the file.
+fn current_index(file: &File, sidecar: &Path) -> Option<ElementIndex> {
+    let file_meta = file.metadata().ok()?;
+    let sidecar_meta = fs::metadata(sidecar).ok()?;
+    if sidecar_meta.modified().ok()? < file_meta.modified().ok()? {
+        return None;
+    }
+
+    let index = ElementIndex::load(sidecar).ok()?;
+    if index.source_len() == file_meta.len() {
+        Some(index)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn ids_from_start_tags() {
+        assert_eq!(
+            start_tag_id(b"<node lat=\"1.0\" id=\"12\" lon=\"1.0\">"),
+            Some(ElementId::Node(12))
+        );
+        assert_eq!(start_tag_id(b"<way id = '-3'/>"), Some(ElementId::Way(-3)));
+        assert_eq!(
+            start_tag_id(b"<relation\n  uid=\"5\" id=\"7\">"),
+            Some(ElementId::Relation(7))
+        );
+        assert_eq!(start_tag_id(b"<node uid=\"5\">"), None);
+        assert_eq!(start_tag_id(b"<bounds id=\"5\"/>"), None);
+        assert_eq!(start_tag_id(b"<node id=\"x\"/>"), None);
+    }
+}
@@ -2,22 +2,67 @@
        unused_import_braces, unused_qualifications)]
 
 extern crate fnv;
+#[cfg(feature = "tokio")]
+extern crate futures_core;
+extern crate memmap2;
+#[cfg(feature = "fast")]
+extern crate quick_xml;
+extern crate regex;
+extern crate serde_json;
+#[cfg(feature = "tokio")]
+extern crate tokio;
 extern crate xml;
 
 use std::io::prelude::*;
-use std::str::FromStr;
-
-use xml::reader::{EventReader, XmlEvent};
-use xml::attribute::OwnedAttribute;
 
 pub mod error;
-use error::{Error, ErrorReason};
+use error::Error;
 use fnv::FnvHashMap;
 
+mod chunks;
+mod compact;
+pub use compact::{CompactElement, CompactNode, CompactOSM, CompactRelation, CompactTags,
+                  CompactWay};
+mod edit;
+pub use edit::DeleteMode;
 mod elements;
-pub use elements::{Bounds, Coordinate, Id, Member, Node, Reference, Relation, Role, Tag,
-                   UnresolvedReference, Way};
+#[cfg(feature = "fast")]
+mod fast;
+pub use elements::{Area, Bounds, Coordinate, Count, Element, ElementId, ElementKind, ElementRef,
+                   Geometry, Id, Location, Member, Metadata, Node, OSMElement, Reference,
+                   Relation, Role, Tag, UnresolvedReference, Way};
+pub mod filter;
+mod idset;
+pub use idset::{ElementIdSet, ElementIds, IdSet, IntoIds, Ids};
+pub mod index;
+pub mod locations;
+pub mod multipolygon;
+mod operations;
+mod options;
+pub use options::ParseOptions;
+pub mod overpass;
+mod parallel;
 mod polygon;
+mod positioned;
+mod reader;
+pub use reader::{ElementReader, ReaderBuilder};
+mod recursion;
+mod resolved;
+pub use resolved::{MissingReference, RecursiveMembers, ResolvedMember, ResolvedOSM,
+                   ResolvedRelation, ResolvedWay};
+pub use polygon::{GeometryType, PolygonRule, PolygonRules, RuleType};
+pub mod snapshot;
+pub mod sort;
+pub mod store;
+#[cfg(feature = "tokio")]
+mod stream;
+#[cfg(feature = "tokio")]
+pub use stream::ElementStream;
+mod symbols;
+pub use symbols::{Str, Symbols};
+mod tags;
+pub use tags::Tags;
+pub mod values;
 
 #[derive(Debug)]
 pub struct OSM {
@@ -25,73 +70,78 @@ pub struct OSM {
     pub nodes: FnvHashMap<Id, Node>,
     pub ways: FnvHashMap<Id, Way>,
     pub relations: FnvHashMap<Id, Relation>,
+    /// Overpass API areas, not part of the element graph.
+    pub areas: FnvHashMap<Id, Area>,
+    /// Overpass API `out count` results.
+    pub counts: Vec<Count>,
+    /// Tag keys, tag values and roles of the document when parsed with `parse_interned`.
+    pub symbols: Symbols,
+    /// Tags dropped while parsing because the element already had a tag with the same key, the
+    /// first value is kept.
+    pub duplicate_tags: Vec<(ElementId, Tag)>,
+    /// Last id given to a new element by the editing methods.
+    placeholder: Id,
 }
 
 impl OSM {
-    fn empty() -> OSM {
+    /// Empty document, eg. for building data with `add_node`, `add_way` and `add_relation`.
+    pub fn new() -> OSM {
         OSM {
             bounds: None,
             nodes: FnvHashMap::default(),
             ways: FnvHashMap::default(),
             relations: FnvHashMap::default(),
+            areas: FnvHashMap::default(),
+            counts: Vec::new(),
+            symbols: Symbols::new(),
+            duplicate_tags: Vec::new(),
+            placeholder: 0,
         }
     }
 
     pub fn parse<R: Read>(source: R) -> Result<OSM, Error> {
-        let mut osm = OSM::empty();
+        OSM::read(ElementReader::new(source))
+    }
+
+    /// Parses the document with the faster `quick-xml` backend, see `ReaderBuilder::fast`.
+    #[cfg(feature = "fast")]
+    pub fn parse_fast<R: Read>(source: R) -> Result<OSM, Error> {
+        ReaderBuilder::new().fast().parse(source)
+    }
+
+    /// Parses the document keeping only the data selected by `options`. `ReaderBuilder`
+    /// combines the options with the other backends and interning.
+    pub fn parse_with_options<R: Read>(source: R, options: ParseOptions) -> Result<OSM, Error> {
+        ReaderBuilder::new().options(options).parse(source)
+    }
 
-        let mut parser = EventReader::new(source);
+    /// Parses the document interning tag keys, tag values and member roles to `OSM::symbols`.
+    /// Repeated strings then share one allocation and compare by pointer.
+    pub fn parse_interned<R: Read>(source: R) -> Result<OSM, Error> {
+        ReaderBuilder::new().symbols(Symbols::new()).parse(source)
+    }
+
+    pub(crate) fn read<R: Read>(mut reader: ElementReader<R>) -> Result<OSM, Error> {
+        let mut osm = OSM::new();
 
-        loop {
-            match parse_element_data(&mut parser) {
-                Err(Error::XmlParseError(err)) => return Err(Error::XmlParseError(err)),
-                Err(Error::BoundsMissing(_)) => osm.bounds = None,
-                Err(Error::MalformedTag(_)) |
-                Err(Error::MalformedNode(_)) |
-                Err(Error::MalformedWay(_)) |
-                Err(Error::MalformedRelation(_)) |
-                Err(Error::UnknownElement) => continue,
-                Ok(data) => match data {
-                    ElementData::EndOfDocument => return Ok(osm),
-                    ElementData::Ignored => continue,
-                    ElementData::Bounds(minlat, minlon, maxlat, maxlon) => {
-                        osm.bounds = Some(Bounds {
-                            minlat: minlat,
-                            minlon: minlon,
-                            maxlat: maxlat,
-                            maxlon: maxlon,
-                        });
-                    }
-                    ElementData::Node(id, lat, lon, tags) => {
-                        osm.nodes.insert(
-                            id,
-                            Node {
-                                id: id,
-                                lat: lat,
-                                lon: lon,
-                                tags: tags,
-                            },
-                        );
-                    }
-                    ElementData::Way(id, node_refs, tags) => {
-                        osm.ways.insert(
-                            id,
-                            Way {
-                                id: id,
-                                nodes: node_refs,
-                                tags: tags,
-                            },
-                        );
-                    }
-                    ElementData::Relation(relation) => {
-                        osm.relations.insert(relation.id, relation);
-                    }
-                },
-            }
+        for element in reader.by_ref() {
+            osm.insert(try!(element));
         }
+
+        osm.bounds = reader.bounds();
+        osm.areas = reader
+            .take_areas()
+            .into_iter()
+            .map(|area| (area.id, area))
+            .collect();
+        osm.counts = reader.counts().to_vec();
+        osm.duplicate_tags = reader.take_duplicate_tags();
+        osm.symbols = reader.take_symbols().unwrap_or_default();
+        Ok(osm)
     }
 
-    pub fn resolve_reference<'a>(&self, reference: &UnresolvedReference) -> Reference {
+    /// Element the reference points to, see `store::resolve_reference` for other stores.
+    pub fn resolve_reference(&self, reference: &UnresolvedReference) -> Reference<'_> {
         match *reference {
             UnresolvedReference::Node(id) => self.nodes
                 .get(&id)
@@ -107,260 +157,47 @@ impl OSM {
                 .unwrap_or(Reference::Unresolved),
         }
     }
-}
-
-enum ElementType {
-    Bounds,
-    Node,
-    Way,
-    Relation,
-    Tag,
-    NodeRef,
-    Member,
-}
 
-enum ElementData {
-    Bounds(Coordinate, Coordinate, Coordinate, Coordinate),
-    Node(Id, Coordinate, Coordinate, Vec<Tag>),
-    Way(Id, Vec<UnresolvedReference>, Vec<Tag>),
-    Relation(Relation),
-    // These two are here so we can terminate and skip uninteresting data without
-    // using error handling.
-    EndOfDocument,
-    Ignored,
-}
-
-
-impl FromStr for ElementType {
-    type Err = Error;
-
-    fn from_str(s: &str) -> Result<ElementType, Error> {
-        match s.to_lowercase().as_ref() {
-            "bounds" => Ok(ElementType::Bounds),
-            "node" => Ok(ElementType::Node),
-            "way" => Ok(ElementType::Way),
-            "relation" => Ok(ElementType::Relation),
-            "tag" => Ok(ElementType::Tag),
-            "nd" => Ok(ElementType::NodeRef),
-            "member" => Ok(ElementType::Member),
-            _ => Err(Error::UnknownElement),
-        }
+    /// Iterates over all elements in the document: nodes first, then ways and relations.
+    pub fn elements(&self) -> impl Iterator<Item = ElementRef<'_>> {
+        self.nodes
+            .values()
+            .map(ElementRef::Node)
+            .chain(self.ways.values().map(ElementRef::Way))
+            .chain(self.relations.values().map(ElementRef::Relation))
     }
-}
-
-fn parse_element_data<R: Read>(parser: &mut EventReader<R>) -> Result<ElementData, Error> {
-    let element = try!(parser.next());
-    match element {
-        XmlEvent::EndDocument => Ok(ElementData::EndOfDocument),
-        XmlEvent::StartElement {
-            name, attributes, ..
-        } => {
-            let element_type = try!(ElementType::from_str(&name.local_name));
-
-            match element_type {
-                ElementType::Bounds => parse_bounds(&attributes),
-                ElementType::Node => parse_node(parser, &attributes),
-                ElementType::Way => parse_way(parser, &attributes),
-                ElementType::Relation => parse_relation(parser, &attributes),
-                _ => Err(Error::UnknownElement),
-            }
-        }
-        _ => Ok(ElementData::Ignored),
-    }
-}
 
-fn parse_relation<R: Read>(
-    parser: &mut EventReader<R>,
-    attrs: &Vec<OwnedAttribute>,
-) -> Result<ElementData, Error> {
-    let id = try!(find_attribute("id", attrs).map_err(Error::MalformedRelation));
-
-    let mut members = Vec::new();
-    let mut tags = Vec::new();
-
-    loop {
-        match try!(parser.next()) {
-            XmlEvent::EndElement { name } => {
-                let element_type = try!(ElementType::from_str(&name.local_name));
-
-                match element_type {
-                    ElementType::Relation => {
-                        return Ok(ElementData::Relation(Relation {
-                            id: id,
-                            members: members,
-                            tags: tags,
-                        }))
-                    }
-                    _ => continue,
-                }
-            }
-            XmlEvent::StartElement {
-                name, attributes, ..
-            } => {
-                let element_type = try!(ElementType::from_str(&name.local_name));
-
-                match element_type {
-                    ElementType::Tag => if let Ok(tag) = parse_tag(&attributes) {
-                        tags.push(tag);
-                    },
-                    ElementType::Member => {
-                        let el_type = try!(
-                            find_attribute_uncasted("type", &attributes)
-                                .map_err(Error::MalformedRelation)
-                        );
-                        let el_ref = try!(
-                            find_attribute("ref", &attributes).map_err(Error::MalformedRelation)
-                        );
-                        let el_role = try!(
-                            find_attribute_uncasted("role", &attributes)
-                                .map_err(Error::MalformedRelation)
-                        );
-
-                        let el = match el_type.to_lowercase().as_ref() {
-                            "node" => Member::Node(UnresolvedReference::Node(el_ref), el_role),
-                            "way" => Member::Way(UnresolvedReference::Way(el_ref), el_role),
-                            "relation" => {
-                                Member::Relation(UnresolvedReference::Relation(el_ref), el_role)
-                            }
-                            _ => return Err(Error::MalformedRelation(ErrorReason::Missing)),
-                        };
-
-                        members.push(el);
-                    }
-                    ElementType::Bounds |
-                    ElementType::Node |
-                    ElementType::Relation |
-                    ElementType::Way |
-                    ElementType::NodeRef => {
-                        return Err(Error::MalformedRelation(ErrorReason::IllegalNesting))
-                    }
-                }
-            }
-            _ => continue,
+    pub fn get(&self, id: ElementId) -> Option<ElementRef<'_>> {
+        match id {
+            ElementId::Node(id) => self.nodes.get(&id).map(ElementRef::Node),
+            ElementId::Way(id) => self.ways.get(&id).map(ElementRef::Way),
+            ElementId::Relation(id) => self.relations.get(&id).map(ElementRef::Relation),
         }
     }
-}
-
-fn parse_way<R: Read>(
-    parser: &mut EventReader<R>,
-    attrs: &Vec<OwnedAttribute>,
-) -> Result<ElementData, Error> {
-    let id = try!(find_attribute("id", attrs).map_err(Error::MalformedWay));
-
-    let mut node_refs = Vec::new();
-    let mut tags = Vec::new();
 
-    loop {
-        match try!(parser.next()) {
-            XmlEvent::EndElement { name } => {
-                let element_type = try!(ElementType::from_str(&name.local_name));
-
-                match element_type {
-                    ElementType::Way => return Ok(ElementData::Way(id, node_refs, tags)),
-                    _ => continue,
-                }
-            }
-            XmlEvent::StartElement {
-                name, attributes, ..
-            } => {
-                let element_type = try!(ElementType::from_str(&name.local_name));
-
-                match element_type {
-                    ElementType::Tag => if let Ok(tag) = parse_tag(&attributes) {
-                        tags.push(tag);
-                    },
-                    ElementType::NodeRef => {
-                        let node_ref =
-                            try!(find_attribute("ref", &attributes).map_err(Error::MalformedWay));
-                        node_refs.push(UnresolvedReference::Node(node_ref));
-                    }
-                    ElementType::Bounds |
-                    ElementType::Node |
-                    ElementType::Relation |
-                    ElementType::Way |
-                    ElementType::Member => {
-                        return Err(Error::MalformedWay(ErrorReason::IllegalNesting))
-                    }
-                }
-            }
-            _ => continue,
+    /// Inserts element to the document, returning the previous element with the same id if there
+    /// was one.
+    pub fn insert(&mut self, element: Element) -> Option<Element> {
+        match element {
+            Element::Node(node) => self.nodes.insert(node.id, node).map(Element::Node),
+            Element::Way(way) => self.ways.insert(way.id, way).map(Element::Way),
+            Element::Relation(relation) => self.relations
+                .insert(relation.id, relation)
+                .map(Element::Relation),
         }
     }
-}
 
-fn parse_node<R: Read>(
-    parser: &mut EventReader<R>,
-    attrs: &Vec<OwnedAttribute>,
-) -> Result<ElementData, Error> {
-    let id = try!(find_attribute("id", attrs).map_err(Error::MalformedNode));
-    let lat = try!(find_attribute("lat", attrs).map_err(Error::MalformedNode));
-    let lon = try!(find_attribute("lon", attrs).map_err(Error::MalformedNode));
-
-    let mut tags = Vec::new();
-
-    loop {
-        match try!(parser.next()) {
-            XmlEvent::EndElement { name } => {
-                let element_type = try!(ElementType::from_str(&name.local_name));
-
-                match element_type {
-                    ElementType::Node => return Ok(ElementData::Node(id, lat, lon, tags)),
-                    _ => continue,
-                }
-            }
-            XmlEvent::StartElement {
-                name, attributes, ..
-            } => {
-                let element_type = try!(ElementType::from_str(&name.local_name));
-
-                match element_type {
-                    ElementType::Tag => if let Ok(tag) = parse_tag(&attributes) {
-                        tags.push(tag);
-                    },
-                    ElementType::Bounds |
-                    ElementType::Node |
-                    ElementType::Relation |
-                    ElementType::Way |
-                    ElementType::NodeRef |
-                    ElementType::Member => {
-                        return Err(Error::MalformedNode(ErrorReason::IllegalNesting))
-                    }
-                }
-            }
-            _ => continue,
+    pub fn remove(&mut self, id: ElementId) -> Option<Element> {
+        match id {
+            ElementId::Node(id) => self.nodes.remove(&id).map(Element::Node),
+            ElementId::Way(id) => self.ways.remove(&id).map(Element::Way),
+            ElementId::Relation(id) => self.relations.remove(&id).map(Element::Relation),
         }
     }
 }
 
-fn parse_tag(attributes: &Vec<OwnedAttribute>) -> Result<Tag, Error> {
-    let key = try!(find_attribute_uncasted("k", attributes).map_err(Error::MalformedTag));
-    let val = try!(find_attribute_uncasted("v", attributes).map_err(Error::MalformedTag));
-    Ok(Tag { key: key, val: val })
-}
-
-fn parse_bounds(attrs: &Vec<OwnedAttribute>) -> Result<ElementData, Error> {
-    let minlat = try!(find_attribute("minlat", attrs).map_err(Error::BoundsMissing));
-    let minlon = try!(find_attribute("minlon", attrs).map_err(Error::BoundsMissing));
-    let maxlat = try!(find_attribute("maxlat", attrs).map_err(Error::BoundsMissing));
-    let maxlon = try!(find_attribute("maxlon", attrs).map_err(Error::BoundsMissing));
-
-    Ok(ElementData::Bounds(minlat, minlon, maxlat, maxlon))
-}
-
-fn find_attribute<T>(name: &str, attrs: &Vec<OwnedAttribute>) -> Result<T, ErrorReason>
-where
-    ErrorReason: From<<T as std::str::FromStr>::Err>,
-    T: FromStr,
-{
-    let val_raw = try!(find_attribute_uncasted(name, attrs));
-    let val = try!(val_raw.parse::<T>());
-    Ok(val)
-}
-
-fn find_attribute_uncasted(name: &str, attrs: &Vec<OwnedAttribute>) -> Result<String, ErrorReason> {
-    let attr = attrs.iter().find(|attr| attr.name.local_name == name);
-    match attr {
-        Some(a) => Ok(a.value.clone()),
-        None => Err(ErrorReason::Missing),
+impl Default for OSM {
+    fn default() -> OSM {
+        OSM::new()
     }
 }
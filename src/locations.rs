@@ -0,0 +1,434 @@
+//! Node location stores for attaching coordinates to ways while streaming, without keeping
+//! whole nodes in memory.
+//!
+//! Locations are stored as 32-bit fixed point values with 1e-7 degree precision, the same
+//! precision OSM database uses. Stores are filled as nodes stream by, and `LocationAdder` fills
+//! `Way::geometry` from the store for ways that follow the nodes:
+//!
+//! ```no_run
+//! # extern crate osm_xml as osm;
+//! # use std::fs::File;
+//! use osm::locations::{LocationAdder, SparseLocations};
+//!
+//! # fn main() {
+//! let f = File::open("extract.osm").unwrap();
+//! let elements = osm::ElementReader::new(f);
+//!
+//! for element in LocationAdder::new(elements, SparseLocations::new()) {
+//!     if let Ok(osm::Element::Way(way)) = element {
+//!         println!("{} {:?}", way.id, way.geometry.nodes);
+//!     }
+//! }
+//! # }
+//! ```
+//!
+//! Available stores:
+//!
+//! - `SparseLocations`: hash map, good for extracts with few nodes compared to the id range
+//! - `DenseLocations`: anonymous memory map indexed by node id, good for large extracts and
+//!   planet files, memory is reserved up to the largest id seen
+//! - `FileLocations`: array indexed by node id in a file, for when locations do not fit to
+//!   memory or need to be kept for later runs
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use fnv::FnvHashMap;
+#[cfg(target_os = "linux")]
+use memmap2::RemapOptions;
+use memmap2::{MmapMut, MmapOptions};
+
+use elements::{Element, ElementId, Id, Location};
+use error::{Error, ErrorReason};
+use positioned::PositionedReader;
+
+const PRECISION: f64 = 10_000_000.0;
+const ENTRY_SIZE: usize = 8;
+const INITIAL_DENSE_CAPACITY: usize = 1 << 20;
+
+/// Storage for node locations by node id.
+pub trait LocationStore {
+    fn set(&mut self, id: Id, location: Location) -> Result<(), Error>;
+    fn get(&self, id: Id) -> Option<Location>;
+}
+
+impl<S: LocationStore + ?Sized> LocationStore for &mut S {
+    fn set(&mut self, id: Id, location: Location) -> Result<(), Error> {
+        (**self).set(id, location)
+    }
+
+    fn get(&self, id: Id) -> Option<Location> {
+        (**self).get(id)
+    }
+}
+
+/// Location store backed by a hash map.
+#[derive(Debug, Default)]
+pub struct SparseLocations {
+    locations: FnvHashMap<Id, (i32, i32)>,
+}
+
+impl SparseLocations {
+    pub fn new() -> SparseLocations {
+        SparseLocations::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.locations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.locations.is_empty()
+    }
+}
+
+impl LocationStore for SparseLocations {
+    fn set(&mut self, id: Id, location: Location) -> Result<(), Error> {
+        self.locations.insert(id, to_fixed(location));
+        Ok(())
+    }
+
+    fn get(&self, id: Id) -> Option<Location> {
+        self.locations.get(&id).map(|&fixed| from_fixed(fixed))
+    }
+}
+
+/// Location store backed by an anonymous memory map indexed by node id. Pages are allocated by
+/// the operating system when they are first written to, so unused id ranges cost only address
+/// space, also after the map has grown. Negative ids are not supported.
+pub struct DenseLocations {
+    map: MmapMut,
+}
+
+impl DenseLocations {
+    pub fn new() -> Result<DenseLocations, Error> {
+        DenseLocations::with_capacity(INITIAL_DENSE_CAPACITY)
+    }
+
+    /// Reserves space for node ids up to `max_id` up front to avoid remapping when the store
+    /// grows.
+    pub fn with_capacity(max_id: usize) -> Result<DenseLocations, Error> {
+        Ok(DenseLocations {
+            map: try!(anonymous_map((max_id + 1) * ENTRY_SIZE)),
+        })
+    }
+
+    fn grow(&mut self, index: usize) -> Result<(), Error> {
+        let len = (index + 1).next_power_of_two() * ENTRY_SIZE;
+        self.resize(len)
+    }
+
+    /// Moves the pages to the larger map without touching the unused ones.
+    #[cfg(target_os = "linux")]
+    fn resize(&mut self, len: usize) -> Result<(), Error> {
+        // No references to the map can exist while it's borrowed mutably
+        try!(unsafe { self.map.remap(len, RemapOptions::new().may_move(true)) });
+        Ok(())
+    }
+
+    /// Copies the pages which have been written to, zero pages are skipped so that they are not
+    /// allocated in the new map.
+    #[cfg(not(target_os = "linux"))]
+    fn resize(&mut self, len: usize) -> Result<(), Error> {
+        const PAGE_SIZE: usize = 4096;
+
+        let mut map = try!(anonymous_map(len));
+        for (offset, page) in self.map.chunks(PAGE_SIZE).enumerate() {
+            if page.iter().any(|&byte| byte != 0) {
+                let start = offset * PAGE_SIZE;
+                map[start..start + page.len()].copy_from_slice(page);
+            }
+        }
+        self.map = map;
+        Ok(())
+    }
+}
+
+impl LocationStore for DenseLocations {
+    fn set(&mut self, id: Id, location: Location) -> Result<(), Error> {
+        let index = try!(dense_index(id));
+        if (index + 1) * ENTRY_SIZE > self.map.len() {
+            try!(self.grow(index));
+        }
+
+        let offset = index * ENTRY_SIZE;
+        self.map[offset..offset + ENTRY_SIZE].copy_from_slice(&encode(location));
+        Ok(())
+    }
+
+    fn get(&self, id: Id) -> Option<Location> {
+        let offset = dense_index(id).ok()? * ENTRY_SIZE;
+        self.map.get(offset..offset + ENTRY_SIZE).and_then(decode)
+    }
+}
+
+/// Location store backed by a file with the same layout as `DenseLocations`. On most file
+/// systems unused id ranges are left as holes and take no disk space. Read errors are reported
+/// as missing locations.
+pub struct FileLocations {
+    file: File,
+}
+
+impl FileLocations {
+    /// Creates new store, truncating the file if it exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<FileLocations, Error> {
+        let file = try!(
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path)
+        );
+        Ok(FileLocations { file: file })
+    }
+
+    /// Opens store written earlier.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileLocations, Error> {
+        let file = try!(OpenOptions::new().read(true).write(true).open(path));
+        Ok(FileLocations { file: file })
+    }
+
+    fn read(&self, offset: u64) -> io::Result<[u8; ENTRY_SIZE]> {
+        let mut entry = [0; ENTRY_SIZE];
+        try!(PositionedReader::new(&self.file, offset).read_exact(&mut entry));
+        Ok(entry)
+    }
+}
+
+impl LocationStore for FileLocations {
+    fn set(&mut self, id: Id, location: Location) -> Result<(), Error> {
+        let offset = (try!(dense_index(id)) * ENTRY_SIZE) as u64;
+        try!(self.file.seek(SeekFrom::Start(offset)));
+        try!(self.file.write_all(&encode(location)));
+        Ok(())
+    }
+
+    fn get(&self, id: Id) -> Option<Location> {
+        let offset = (dense_index(id).ok()? * ENTRY_SIZE) as u64;
+        self.read(offset).ok().as_ref().and_then(|entry| decode(entry))
+    }
+}
+
+/// Adds locations to ways of an element stream ("add locations to ways").
+///
+/// Node locations are stored as nodes pass by and each way gets `geometry.nodes` filled from the
+/// store, so nodes have to come before the ways like they do in regular OSM files. All elements
+/// and errors are passed through.
+pub struct LocationAdder<I, S> {
+    elements: I,
+    store: S,
+}
+
+impl<I, S> LocationAdder<I, S>
+where
+    I: Iterator<Item = Result<Element, Error>>,
+    S: LocationStore,
+{
+    pub fn new(elements: I, store: S) -> LocationAdder<I, S> {
+        LocationAdder {
+            elements: elements,
+            store: store,
+        }
+    }
+
+    pub fn store(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_store(self) -> S {
+        self.store
+    }
+}
+
+impl<I, S> Iterator for LocationAdder<I, S>
+where
+    I: Iterator<Item = Result<Element, Error>>,
+    S: LocationStore,
+{
+    type Item = Result<Element, Error>;
+
+    fn next(&mut self) -> Option<Result<Element, Error>> {
+        let element = match self.elements.next()? {
+            Ok(element) => element,
+            Err(err) => return Some(Err(err)),
+        };
+
+        match element {
+            Element::Node(node) => {
+                let location = Location {
+                    lat: node.lat,
+                    lon: node.lon,
+                };
+                if let Err(err) = self.store.set(node.id, location) {
+                    return Some(Err(err));
+                }
+                Some(Ok(Element::Node(node)))
+            }
+            Element::Way(mut way) => {
+                let store = &self.store;
+                let locations: Vec<_> = way.nodes
+                    .iter()
+                    .map(|node| store.get(ElementId::from(*node).id()))
+                    .collect();
+
+                if locations.iter().any(|location| location.is_some()) {
+                    way.geometry.nodes = locations;
+                }
+                Some(Ok(Element::Way(way)))
+            }
+            relation => Some(Ok(relation)),
+        }
+    }
+}
+
+/// Zeroed map which doesn't reserve swap for its whole length, most of it is never used.
+fn anonymous_map(len: usize) -> io::Result<MmapMut> {
+    MmapOptions::new().len(len).no_reserve_swap().map_anon()
+}
+
+fn dense_index(id: Id) -> Result<usize, Error> {
+    if id < 0 {
+        return Err(Error::MalformedNode(ErrorReason::InvalidValue));
+    }
+
+    Ok(id as usize)
+}
+
+pub(crate) fn to_fixed(location: Location) -> (i32, i32) {
+    (
+        (location.lat * PRECISION).round() as i32,
+        (location.lon * PRECISION).round() as i32,
+    )
+}
+
+pub(crate) fn from_fixed((lat, lon): (i32, i32)) -> Location {
+    Location {
+        lat: f64::from(lat) / PRECISION,
+        lon: f64::from(lon) / PRECISION,
+    }
+}
+
+/// Dense stores flip the sign bit so that zeroed memory and file holes, which decode to
+/// `i32::MIN` that is out of coordinate range, mean missing location.
+fn encode(location: Location) -> [u8; ENTRY_SIZE] {
+    let (lat, lon) = to_fixed(location);
+    let mut entry = [0; ENTRY_SIZE];
+    entry[..4].copy_from_slice(&(lat ^ i32::MIN).to_le_bytes());
+    entry[4..].copy_from_slice(&(lon ^ i32::MIN).to_le_bytes());
+    entry
+}
+
+fn decode(entry: &[u8]) -> Option<Location> {
+    if entry.iter().all(|&b| b == 0) {
+        return None;
+    }
+
+    let lat = i32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) ^ i32::MIN;
+    let lon = i32::from_le_bytes([entry[4], entry[5], entry[6], entry[7]]) ^ i32::MIN;
+    Some(from_fixed((lat, lon)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+
+    fn location(lat: f64, lon: f64) -> Location {
+        Location { lat: lat, lon: lon }
+    }
+
+    fn check_store<S: LocationStore>(store: &mut S) {
+        assert_eq!(store.get(1), None);
+
+        store.set(1, location(60.1757315, 24.9595858)).unwrap();
+        store.set(0, location(0.0, 0.0)).unwrap();
+        store.set(5_000_000, location(-89.9999999, -179.9999999)).unwrap();
+
+        assert_eq!(store.get(1), Some(location(60.1757315, 24.9595858)));
+        assert_eq!(store.get(0), Some(location(0.0, 0.0)));
+        assert_eq!(store.get(5_000_000), Some(location(-89.9999999, -179.9999999)));
+        assert_eq!(store.get(2), None);
+        assert_eq!(store.get(10_000_000), None);
+
+        store.set(1, location(1.0, 2.0)).unwrap();
+        assert_eq!(store.get(1), Some(location(1.0, 2.0)));
+    }
+
+    #[test]
+    fn fixed_point_precision() {
+        let loc = location(60.17573154, -24.95958576);
+        assert_eq!(from_fixed(to_fixed(loc)), location(60.1757315, -24.9595858));
+        assert_eq!(decode(&encode(loc)), Some(location(60.1757315, -24.9595858)));
+        assert_eq!(decode(&encode(location(180.0, -180.0))), Some(location(180.0, -180.0)));
+    }
+
+    #[test]
+    fn sparse_store() {
+        let mut store = SparseLocations::new();
+        check_store(&mut store);
+        assert_eq!(store.len(), 3);
+
+        store.set(-1, location(1.0, 1.0)).unwrap();
+        assert_eq!(store.get(-1), Some(location(1.0, 1.0)));
+    }
+
+    #[test]
+    fn dense_store_grows() {
+        let mut store = DenseLocations::with_capacity(16).unwrap();
+        check_store(&mut store);
+
+        match store.set(-1, location(1.0, 1.0)) {
+            Err(Error::MalformedNode(ErrorReason::InvalidValue)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        assert_eq!(store.get(-1), None);
+    }
+
+    /// Resident size of the mapping starting at `ptr` in kB.
+    #[cfg(target_os = "linux")]
+    fn resident_kb(ptr: *const u8) -> usize {
+        let smaps = fs::read_to_string("/proc/self/smaps").unwrap();
+        let start = format!("{:x}-", ptr as usize);
+        let mut lines = smaps.lines().skip_while(|line| !line.starts_with(&start));
+        lines
+            .find(|line| line.starts_with("Rss:"))
+            .and_then(|line| line.split_whitespace().nth(1))
+            .and_then(|kb| kb.parse().ok())
+            .unwrap()
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn dense_store_grows_sparsely() {
+        let mut store = DenseLocations::with_capacity(16).unwrap();
+        store.set(0, location(1.0, 1.0)).unwrap();
+        store.set(1 << 24, location(2.0, 2.0)).unwrap();
+        store.set(1 << 27, location(3.0, 3.0)).unwrap();
+
+        assert_eq!(store.get(0), Some(location(1.0, 1.0)));
+        assert_eq!(store.get(1 << 24), Some(location(2.0, 2.0)));
+        assert_eq!(store.get(1 << 27), Some(location(3.0, 3.0)));
+        // The map is 1 GiB, copying it would make 128 MiB resident
+        assert!(resident_kb(store.map.as_ptr()) < 16 * 1024);
+    }
+
+    #[test]
+    fn file_store_persists() {
+        let path = env::temp_dir().join(format!("osm-xml-locations-{}", ::std::process::id()));
+
+        {
+            let mut store = FileLocations::create(&path).unwrap();
+            check_store(&mut store);
+        }
+
+        let store = FileLocations::open(&path).unwrap();
+        assert_eq!(store.get(1), Some(location(1.0, 2.0)));
+        assert_eq!(store.get(5_000_000), Some(location(-89.9999999, -179.9999999)));
+        assert_eq!(store.get(3), None);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
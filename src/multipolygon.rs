@@ -0,0 +1,471 @@
+//! Two-pass streaming assembly of multipolygon and boundary relations.
+//!
+//! First pass collects area relations and the ids of their member ways. Second pass stores node
+//! locations to a `LocationStore`, keeps only the member ways and emits each relation as soon as
+//! all of its member ways have been seen. Ways are dropped once no pending relation needs them,
+//! so memory use is bounded by the relations in progress instead of the whole input:
+//!
+//! ```no_run
+//! # extern crate osm_xml as osm;
+//! # use std::fs::File;
+//! use osm::locations::DenseLocations;
+//! use osm::multipolygon::MultipolygonCollector;
+//!
+//! # fn main() {
+//! let mut collector = MultipolygonCollector::new();
+//! let f = File::open("extract.osm").unwrap();
+//! collector.read_relations(osm::ElementReader::new(f)).unwrap();
+//!
+//! let f = File::open("extract.osm").unwrap();
+//! let store = DenseLocations::new().unwrap();
+//! for multipolygon in collector.assemble(osm::ElementReader::new(f), store) {
+//!     let multipolygon = multipolygon.unwrap();
+//!     println!("{}: {} polygons", multipolygon.id, multipolygon.polygons.len());
+//! }
+//! # }
+//! ```
+//!
+//! Rings are joined from member ways by shared end nodes, member roles are not trusted. Rings
+//! nested in an odd number of other rings are inner rings of the smallest ring containing them.
+//! Outer rings are counterclockwise and inner rings clockwise. Rings that can not be closed are
+//! dropped.
+
+use std::collections::VecDeque;
+
+use fnv::FnvHashMap;
+
+use elements::{Element, ElementId, Id, Location, Member, Relation};
+use error::Error;
+use locations::LocationStore;
+use polygon::GeometryType;
+use tags::Tags;
+
+/// Assembled multipolygon or boundary relation.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Multipolygon {
+    pub id: Id,
+    pub tags: Tags,
+    pub polygons: Vec<Polygon>,
+    /// Member ways that were not in the input or had nodes without location. Relations with
+    /// missing ways are emitted at the end of the input with the rings that could be assembled.
+    pub missing_ways: Vec<Id>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Polygon {
+    pub outer: Vec<Location>,
+    pub inners: Vec<Vec<Location>>,
+}
+
+/// Collects area relations in the first pass, see module documentation.
+#[derive(Debug, Default)]
+pub struct MultipolygonCollector {
+    relations: FnvHashMap<Id, PendingRelation>,
+    way_relations: FnvHashMap<Id, Vec<Id>>,
+}
+
+#[derive(Debug)]
+struct PendingRelation {
+    tags: Tags,
+    ways: Vec<Id>,
+    remaining: usize,
+}
+
+type WayNodes = Vec<(Id, Location)>;
+
+impl MultipolygonCollector {
+    pub fn new() -> MultipolygonCollector {
+        MultipolygonCollector::default()
+    }
+
+    /// Records relation if it is a multipolygon or boundary. Returns whether the relation was
+    /// recorded.
+    pub fn add_relation(&mut self, relation: &Relation) -> bool {
+        if relation.geometry_type() != Some(GeometryType::Area) {
+            return false;
+        }
+
+        let mut ways = Vec::new();
+        for member in &relation.members {
+            if let Member::Way(way, _) = *member {
+                let id = ElementId::from(way).id();
+                if !ways.contains(&id) {
+                    ways.push(id);
+                }
+            }
+        }
+
+        if self.relations.contains_key(&relation.id) {
+            return true;
+        }
+
+        for &way in &ways {
+            self.way_relations
+                .entry(way)
+                .or_default()
+                .push(relation.id);
+        }
+        self.relations.insert(
+            relation.id,
+            PendingRelation {
+                tags: relation.tags.clone(),
+                remaining: ways.len(),
+                ways: ways,
+            },
+        );
+
+        true
+    }
+
+    /// First pass over the input, stops at the first error.
+    pub fn read_relations<I>(&mut self, elements: I) -> Result<(), Error>
+    where
+        I: Iterator<Item = Result<Element, Error>>,
+    {
+        for element in elements {
+            if let Element::Relation(relation) = try!(element) {
+                self.add_relation(&relation);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.relations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.relations.is_empty()
+    }
+
+    /// Second pass over the input. Nodes have to come before ways like they do in regular OSM
+    /// files, errors are passed through.
+    pub fn assemble<I, S>(self, elements: I, store: S) -> AssembledMultipolygons<I, S>
+    where
+        I: Iterator<Item = Result<Element, Error>>,
+        S: LocationStore,
+    {
+        AssembledMultipolygons {
+            collector: self,
+            elements: elements,
+            store: store,
+            ways: FnvHashMap::default(),
+            ready: VecDeque::new(),
+            finished: false,
+        }
+    }
+}
+
+pub struct AssembledMultipolygons<I, S> {
+    collector: MultipolygonCollector,
+    elements: I,
+    store: S,
+    /// Member ways seen so far, `None` when some of the locations were missing
+    ways: FnvHashMap<Id, Option<WayNodes>>,
+    ready: VecDeque<Multipolygon>,
+    finished: bool,
+}
+
+impl<I, S> AssembledMultipolygons<I, S>
+where
+    I: Iterator<Item = Result<Element, Error>>,
+    S: LocationStore,
+{
+    /// Number of member ways currently kept in memory.
+    pub fn pending_ways(&self) -> usize {
+        self.ways.len()
+    }
+
+    /// Ways with missing locations count as seen but end up in `missing_ways`.
+    fn add_way(&mut self, id: Id, nodes: Option<WayNodes>) {
+        let relations = match self.collector.way_relations.get(&id) {
+            Some(relations) => relations.clone(),
+            None => return,
+        };
+
+        // A way repeated in the input counts only once, the first copy is kept
+        if self.ways.contains_key(&id) {
+            return;
+        }
+        self.ways.insert(id, nodes);
+
+        for relation in relations {
+            let complete = match self.collector.relations.get_mut(&relation) {
+                Some(pending) => {
+                    pending.remaining -= 1;
+                    pending.remaining == 0
+                }
+                None => false,
+            };
+
+            if complete {
+                let multipolygon = self.finish(relation);
+                self.ready.push_back(multipolygon);
+            }
+        }
+    }
+
+    fn finish(&mut self, id: Id) -> Multipolygon {
+        let pending = self.collector
+            .relations
+            .remove(&id)
+            .expect("Finished relation should be pending");
+
+        let mut ways = Vec::new();
+        let mut missing_ways = Vec::new();
+        for way in &pending.ways {
+            match self.ways.get(way) {
+                Some(Some(nodes)) => ways.push(nodes.clone()),
+                _ => missing_ways.push(*way),
+            }
+
+            // Release ways no other pending relation needs
+            let unused = match self.collector.way_relations.get_mut(way) {
+                Some(relations) => {
+                    relations.retain(|relation| *relation != id);
+                    relations.is_empty()
+                }
+                None => true,
+            };
+            if unused {
+                self.collector.way_relations.remove(way);
+                self.ways.remove(way);
+            }
+        }
+
+        Multipolygon {
+            id: id,
+            tags: pending.tags,
+            polygons: build_polygons(ways),
+            missing_ways: missing_ways,
+        }
+    }
+}
+
+impl<I, S> Iterator for AssembledMultipolygons<I, S>
+where
+    I: Iterator<Item = Result<Element, Error>>,
+    S: LocationStore,
+{
+    type Item = Result<Multipolygon, Error>;
+
+    fn next(&mut self) -> Option<Result<Multipolygon, Error>> {
+        loop {
+            if let Some(multipolygon) = self.ready.pop_front() {
+                return Some(Ok(multipolygon));
+            }
+
+            if self.finished {
+                return None;
+            }
+
+            match self.elements.next() {
+                Some(Ok(Element::Node(node))) => {
+                    let location = Location {
+                        lat: node.lat,
+                        lon: node.lon,
+                    };
+                    if let Err(err) = self.store.set(node.id, location) {
+                        return Some(Err(err));
+                    }
+                }
+                Some(Ok(Element::Way(way))) => {
+                    if !self.collector.way_relations.contains_key(&way.id) {
+                        continue;
+                    }
+
+                    let store = &self.store;
+                    let nodes: Option<WayNodes> = way.nodes
+                        .iter()
+                        .map(|node| {
+                            let id = ElementId::from(*node).id();
+                            store.get(id).map(|location| (id, location))
+                        })
+                        .collect();
+                    self.add_way(way.id, nodes);
+                }
+                Some(Ok(Element::Relation(_))) => continue,
+                Some(Err(err)) => return Some(Err(err)),
+                None => {
+                    self.finished = true;
+                    let mut incomplete: Vec<Id> =
+                        self.collector.relations.keys().cloned().collect();
+                    incomplete.sort();
+                    for id in incomplete {
+                        let multipolygon = self.finish(id);
+                        self.ready.push_back(multipolygon);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn build_polygons(ways: Vec<WayNodes>) -> Vec<Polygon> {
+    let rings: Vec<Vec<Location>> = join_rings(ways)
+        .into_iter()
+        .map(|ring| ring.into_iter().map(|(_, location)| location).collect())
+        .collect();
+
+    let depths: Vec<usize> = (0..rings.len())
+        .map(|i| {
+            (0..rings.len())
+                .filter(|&j| j != i && ring_contains(&rings[j], &rings[i]))
+                .count()
+        })
+        .collect();
+
+    let mut polygons = Vec::new();
+    let mut outer_index = FnvHashMap::default();
+    for (i, ring) in rings.iter().enumerate() {
+        if depths[i].is_multiple_of(2) {
+            outer_index.insert(i, polygons.len());
+            polygons.push(Polygon {
+                outer: oriented(ring.clone(), true),
+                inners: Vec::new(),
+            });
+        }
+    }
+
+    for (i, ring) in rings.iter().enumerate() {
+        if depths[i].is_multiple_of(2) {
+            continue;
+        }
+
+        // Parent is the smallest containing outer ring one level up
+        let parent = (0..rings.len())
+            .filter(|&j| depths[j] + 1 == depths[i] && ring_contains(&rings[j], ring))
+            .min_by(|&a, &b| {
+                signed_area(&rings[a])
+                    .abs()
+                    .partial_cmp(&signed_area(&rings[b]).abs())
+                    .unwrap_or(::std::cmp::Ordering::Equal)
+            });
+
+        if let Some(index) = parent.and_then(|parent| outer_index.get(&parent)) {
+            polygons[*index].inners.push(oriented(ring.clone(), false));
+        }
+    }
+
+    polygons
+}
+
+/// Joins ways to closed rings by matching end node ids. Open rings are dropped.
+fn join_rings(ways: Vec<WayNodes>) -> Vec<WayNodes> {
+    let mut pool: Vec<WayNodes> = ways.into_iter().filter(|way| way.len() >= 2).collect();
+    let mut rings = Vec::new();
+
+    while let Some(mut ring) = pool.pop() {
+        while ring.first().map(|n| n.0) != ring.last().map(|n| n.0) {
+            let end = ring.last().map(|n| n.0);
+            let next = pool.iter().position(|way| {
+                way.first().map(|n| n.0) == end || way.last().map(|n| n.0) == end
+            });
+
+            match next {
+                Some(index) => {
+                    let mut way = pool.swap_remove(index);
+                    if way.first().map(|n| n.0) != end {
+                        way.reverse();
+                    }
+                    ring.extend(way.into_iter().skip(1));
+                }
+                None => break,
+            }
+        }
+
+        if ring.len() >= 4 && ring.first().map(|n| n.0) == ring.last().map(|n| n.0) {
+            rings.push(ring);
+        }
+    }
+
+    rings
+}
+
+/// Shoelace formula with longitude as x, positive for counterclockwise rings.
+fn signed_area(ring: &[Location]) -> f64 {
+    ring.windows(2)
+        .map(|pair| pair[0].lon * pair[1].lat - pair[1].lon * pair[0].lat)
+        .sum::<f64>() / 2.0
+}
+
+fn oriented(mut ring: Vec<Location>, counterclockwise: bool) -> Vec<Location> {
+    if (signed_area(&ring) > 0.0) != counterclockwise {
+        ring.reverse();
+    }
+    ring
+}
+
+/// Tests the midpoint of the first segment of `inner`, so rings touching at a node are handled.
+fn ring_contains(outer: &[Location], inner: &[Location]) -> bool {
+    let point = Location {
+        lat: (inner[0].lat + inner[1].lat) / 2.0,
+        lon: (inner[0].lon + inner[1].lon) / 2.0,
+    };
+
+    let mut inside = false;
+    for pair in outer.windows(2) {
+        let (a, b) = (pair[0], pair[1]);
+        if (a.lat > point.lat) != (b.lat > point.lat) {
+            let lon = a.lon + (point.lat - a.lat) / (b.lat - a.lat) * (b.lon - a.lon);
+            if point.lon < lon {
+                inside = !inside;
+            }
+        }
+    }
+
+    inside
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn way(nodes: &[(Id, f64, f64)]) -> WayNodes {
+        nodes
+            .iter()
+            .map(|&(id, lat, lon)| (id, Location { lat: lat, lon: lon }))
+            .collect()
+    }
+
+    #[test]
+    fn join_split_ring() {
+        let rings = join_rings(vec![
+            way(&[(1, 0.0, 0.0), (2, 0.0, 1.0), (3, 1.0, 1.0)]),
+            way(&[(1, 0.0, 0.0), (4, 1.0, 0.0), (3, 1.0, 1.0)]),
+        ]);
+        assert_eq!(rings.len(), 1);
+        assert_eq!(rings[0].len(), 5);
+        assert_eq!(rings[0][0].0, rings[0][4].0);
+    }
+
+    #[test]
+    fn open_rings_are_dropped() {
+        let rings = join_rings(vec![way(&[(1, 0.0, 0.0), (2, 0.0, 1.0), (3, 1.0, 1.0)])]);
+        assert!(rings.is_empty());
+    }
+
+    #[test]
+    fn nested_rings() {
+        let outer = way(&[(1, 0.0, 0.0), (2, 0.0, 10.0), (3, 10.0, 10.0), (4, 10.0, 0.0), (1, 0.0, 0.0)]);
+        let hole = way(&[(5, 2.0, 2.0), (6, 8.0, 2.0), (7, 8.0, 8.0), (8, 2.0, 8.0), (5, 2.0, 2.0)]);
+        let island = way(&[(9, 4.0, 4.0), (10, 4.0, 6.0), (11, 6.0, 6.0), (9, 4.0, 4.0)]);
+        let separate = way(&[(12, 20.0, 20.0), (13, 20.0, 21.0), (14, 21.0, 21.0), (12, 20.0, 20.0)]);
+
+        let mut polygons = build_polygons(vec![outer, hole, island, separate]);
+        polygons.sort_by(|a, b| {
+            signed_area(&b.outer)
+                .partial_cmp(&signed_area(&a.outer))
+                .unwrap()
+        });
+
+        assert_eq!(polygons.len(), 3);
+        assert_eq!(polygons[0].inners.len(), 1);
+        assert!(polygons[1].inners.is_empty());
+        assert!(polygons[2].inners.is_empty());
+
+        assert!(signed_area(&polygons[0].outer) > 0.0);
+        assert!(signed_area(&polygons[0].inners[0]) < 0.0);
+    }
+}
@@ -0,0 +1,507 @@
+//! Editor style operations on ways and nodes: split, merge, reverse and merging duplicate
+//! nodes. Relations are updated along with the ways and nodes they have as members.
+
+use fnv::FnvHashMap;
+
+use edit::{clean_way, retain_with_geometry, DeleteMode};
+use elements::{ElementId, ElementKind, Id, Member, UnresolvedReference, Way};
+use error::Error;
+use idset::IdSet;
+use symbols::Str;
+use tags::Tags;
+use OSM;
+
+const COMPASS: [&str; 16] = [
+    "N", "NNE", "NE", "ENE", "E", "ESE", "SE", "SSE", "S", "SSW", "SW", "WSW", "W", "WNW", "NW",
+    "NNW",
+];
+
+impl OSM {
+    /// Splits the way at the node. The way keeps the nodes up to the split node and a new way
+    /// with the same tags gets the nodes from the split node on, so both have the split node.
+    /// Returns id of the new way.
+    ///
+    /// The new way is added next to the way in every relation that has it as a member, with
+    /// the same role. It goes after the way unless the previous member connects to the far end
+    /// of the new way, so routes stay in order.
+    pub fn split_way(&mut self, way_id: Id, node_id: Id) -> Result<Id, Error> {
+        let (mut new_way, position, len, start) = {
+            let way = try!(self.existing_way(way_id));
+            let len = way.nodes.len();
+            let node = UnresolvedReference::Node(node_id);
+            let mut inner = way.nodes.iter().take(len.saturating_sub(1)).skip(1);
+            let position = match inner.position(|reference| *reference == node) {
+                Some(position) => position + 1,
+                None => {
+                    return Err(Error::InvalidEdit(format!(
+                        "node {} is not an inner node of way {}",
+                        node_id,
+                        way_id
+                    )))
+                }
+            };
+
+            let mut new_way = Way {
+                id: 0,
+                tags: way.tags.clone(),
+                nodes: way.nodes[position..].to_vec(),
+                meta: Default::default(),
+                geometry: Default::default(),
+            };
+            if way.geometry.nodes.len() == len {
+                new_way.geometry.nodes = way.geometry.nodes[position..].to_vec();
+            }
+            (new_way, position, len, way.nodes[0])
+        };
+
+        let new_id = self.placeholder_id(ElementKind::Way);
+        new_way.id = new_id;
+        let end = new_way.nodes[new_way.nodes.len() - 1];
+        if let Some(way) = self.ways.get_mut(&way_id) {
+            way.nodes.truncate(position + 1);
+            way.geometry.nodes.truncate(position + 1);
+            // Bounds and center of the whole way are stale now
+            way.geometry.bounds = None;
+            way.geometry.center = None;
+        }
+        self.ways.insert(new_id, new_way);
+
+        let ways = &self.ways;
+        for relation in self.relations.values_mut() {
+            let mut i = 0;
+            while i < relation.members.len() {
+                let role = match relation.members[i] {
+                    Member::Way(UnresolvedReference::Way(id), ref role) if id == way_id => {
+                        role.clone()
+                    }
+                    _ => {
+                        i += 1;
+                        continue;
+                    }
+                };
+
+                let previous = i.checked_sub(1)
+                    .and_then(|previous| member_endpoints(ways, &relation.members[previous]));
+                let next = relation
+                    .members
+                    .get(i + 1)
+                    .and_then(|next| member_endpoints(ways, next));
+                let backwards = match previous {
+                    Some(ends) if touches(ends, start) || touches(ends, end) => {
+                        touches(ends, end) && !touches(ends, start)
+                    }
+                    _ => next.is_some_and(|ends| touches(ends, start) && !touches(ends, end)),
+                };
+
+                let at = if backwards { i } else { i + 1 };
+                let member = Member::Way(UnresolvedReference::Way(new_id), role);
+                relation.members.insert(at, member);
+                if relation.geometry.members.len() + 1 == relation.members.len() {
+                    let geometry = &mut relation.geometry.members;
+                    let mut split = Vec::new();
+                    if geometry[i].len() == len {
+                        split = geometry[i][position..].to_vec();
+                        geometry[i].truncate(position + 1);
+                    }
+                    geometry.insert(at, split);
+                }
+                i += 2;
+            }
+        }
+
+        Ok(new_id)
+    }
+
+    /// Merges `other` to the end or start of the way they share. The way keeps its direction,
+    /// `other` is reversed with `reverse_way` when needed. Tags of `other` are added to the way
+    /// and conflicting values are refused.
+    ///
+    /// `other` is deleted. Relations which have it as a member get the way instead, or just
+    /// lose the member when they have the way too.
+    pub fn merge_ways(&mut self, way_id: Id, other_id: Id) -> Result<(), Error> {
+        if way_id == other_id {
+            return Err(Error::InvalidEdit(format!("way {} merged with itself", way_id)));
+        }
+
+        let (append, reverse, tags) = {
+            let way = try!(self.existing_way(way_id));
+            let other = try!(self.existing_way(other_id));
+            let (start, end) = try!(open_endpoints(way));
+            let (other_start, other_end) = try!(open_endpoints(other));
+
+            let (append, reverse) = if end == other_start {
+                (true, false)
+            } else if end == other_end {
+                (true, true)
+            } else if start == other_end {
+                (false, false)
+            } else if start == other_start {
+                (false, true)
+            } else {
+                return Err(Error::InvalidEdit(format!(
+                    "ways {} and {} do not share an end node",
+                    way_id,
+                    other_id
+                )));
+            };
+
+            let other_tags = if reverse {
+                reverse_tags(&other.tags)
+            } else {
+                other.tags.clone()
+            };
+            let mut tags = way.tags.clone();
+            for tag in other_tags.iter() {
+                if let Some(val) = tags.get(&tag.key) {
+                    if val != tag.val {
+                        return Err(Error::InvalidEdit(format!(
+                            "ways {} and {} have different values for {}",
+                            way_id,
+                            other_id,
+                            tag.key
+                        )));
+                    }
+                }
+                tags.insert(tag.key.clone(), tag.val.clone());
+            }
+            (append, reverse, tags)
+        };
+
+        if reverse {
+            try!(self.reverse_way(other_id));
+        }
+
+        if let Some(other) = self.ways.remove(&other_id) {
+            if let Some(way) = self.ways.get_mut(&way_id) {
+                let aligned = way.geometry.nodes.len() == way.nodes.len() &&
+                    other.geometry.nodes.len() == other.nodes.len();
+                if append {
+                    way.nodes.extend_from_slice(&other.nodes[1..]);
+                    way.geometry.nodes
+                        .extend_from_slice(other.geometry.nodes.get(1..).unwrap_or(&[]));
+                } else {
+                    let mut nodes = other.nodes;
+                    nodes.extend_from_slice(&way.nodes[1..]);
+                    way.nodes = nodes;
+                    let mut locations = other.geometry.nodes;
+                    locations.extend_from_slice(way.geometry.nodes.get(1..).unwrap_or(&[]));
+                    way.geometry.nodes = locations;
+                }
+                if !aligned {
+                    way.geometry.nodes.clear();
+                }
+                way.geometry.bounds = None;
+                way.geometry.center = None;
+                way.tags = tags;
+            }
+        }
+
+        let replaced = UnresolvedReference::Way(other_id);
+        let merged = UnresolvedReference::Way(way_id);
+        for relation in self.relations.values_mut() {
+            let has_way = relation.members.iter().any(|member| match *member {
+                Member::Way(reference, _) => reference == merged,
+                _ => false,
+            });
+
+            if has_way {
+                retain_with_geometry(
+                    &mut relation.members,
+                    &mut relation.geometry.members,
+                    |member| match *member {
+                        Member::Way(reference, _) => reference != replaced,
+                        _ => true,
+                    },
+                );
+            } else {
+                for member in &mut relation.members {
+                    if let Member::Way(ref mut reference, _) = *member {
+                        if *reference == replaced {
+                            *reference = merged;
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reverses direction of the way. Direction dependent tags are changed to keep their
+    /// meaning: `oneway`, `:left`/`:right` and `:forward`/`:backward` key parts, `left`/`right`
+    /// and `forward`/`backward` values, `direction` and `incline`. `forward` and `backward`
+    /// roles of the way in relations are swapped too.
+    pub fn reverse_way(&mut self, id: Id) -> Result<(), Error> {
+        match self.ways.get_mut(&id) {
+            Some(way) => {
+                way.nodes.reverse();
+                way.geometry.nodes.reverse();
+                way.tags = reverse_tags(&way.tags);
+            }
+            None => return Err(Error::ElementNotFound(ElementId::Way(id))),
+        }
+
+        for relation in self.relations.values_mut() {
+            let aligned = relation.geometry.members.len() == relation.members.len();
+            for (i, member) in relation.members.iter_mut().enumerate() {
+                if let Member::Way(UnresolvedReference::Way(way_id), ref mut role) = *member {
+                    if way_id != id {
+                        continue;
+                    }
+
+                    if let Some(reversed) = swap_word(role) {
+                        *role = Str::from(reversed);
+                    }
+                    if aligned {
+                        relation.geometry.members[i].reverse();
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Merges duplicate nodes to the node. Ways and relations which have the duplicates refer
+    /// to the node instead and the duplicates are deleted. The node keeps its location, tags of
+    /// the duplicates are added to it and conflicting values are refused.
+    ///
+    /// Repeated nodes left next to each other in ways are removed. Ways which are left without
+    /// two different nodes, or closed ways without three, are deleted.
+    pub fn merge_nodes<I>(&mut self, node_id: Id, duplicates: I) -> Result<(), Error>
+    where
+        I: IntoIterator<Item = Id>,
+    {
+        try!(self.check_exists(ElementId::Node(node_id)));
+
+        let mut merged = IdSet::new();
+        let mut tags = self.nodes
+            .get(&node_id)
+            .map(|node| node.tags.clone())
+            .unwrap_or_default();
+        for id in duplicates {
+            if id == node_id || !merged.insert(id) {
+                continue;
+            }
+
+            let duplicate = match self.nodes.get(&id) {
+                Some(duplicate) => duplicate,
+                None => return Err(Error::ElementNotFound(ElementId::Node(id))),
+            };
+            for tag in duplicate.tags.iter() {
+                if let Some(val) = tags.get(&tag.key) {
+                    if val != tag.val {
+                        return Err(Error::InvalidEdit(format!(
+                            "nodes {} and {} have different values for {}",
+                            node_id,
+                            id,
+                            tag.key
+                        )));
+                    }
+                }
+                tags.insert(tag.key.clone(), tag.val.clone());
+            }
+        }
+
+        if let Some(node) = self.nodes.get_mut(&node_id) {
+            node.tags = tags;
+        }
+        for id in &merged {
+            self.nodes.remove(&id);
+        }
+
+        let target = UnresolvedReference::Node(node_id);
+        let mut degenerate = Vec::new();
+        for way in self.ways.values_mut() {
+            let mut changed = false;
+            for reference in &mut way.nodes {
+                if let UnresolvedReference::Node(id) = *reference {
+                    if merged.contains(id) {
+                        *reference = target;
+                        changed = true;
+                    }
+                }
+            }
+            if !changed {
+                continue;
+            }
+
+            // Replacing the end nodes keeps the way closed or closes it
+            if !clean_way(way, false) {
+                degenerate.push(way.id);
+            }
+        }
+
+        for relation in self.relations.values_mut() {
+            for member in &mut relation.members {
+                if let Member::Node(ref mut reference, _) = *member {
+                    if let UnresolvedReference::Node(id) = *reference {
+                        if merged.contains(id) {
+                            *reference = target;
+                        }
+                    }
+                }
+            }
+        }
+
+        for id in degenerate {
+            try!(self.delete(ElementId::Way(id), DeleteMode::Cascade));
+        }
+
+        Ok(())
+    }
+
+    fn existing_way(&self, id: Id) -> Result<&Way, Error> {
+        self.ways
+            .get(&id)
+            .ok_or(Error::ElementNotFound(ElementId::Way(id)))
+    }
+}
+
+type Endpoints = (UnresolvedReference, UnresolvedReference);
+
+fn member_endpoints(ways: &FnvHashMap<Id, Way>, member: &Member) -> Option<Endpoints> {
+    match *member {
+        Member::Way(UnresolvedReference::Way(id), _) => ways
+            .get(&id)
+            .and_then(|way| Some((*way.nodes.first()?, *way.nodes.last()?))),
+        _ => None,
+    }
+}
+
+fn touches(ends: Endpoints, node: UnresolvedReference) -> bool {
+    ends.0 == node || ends.1 == node
+}
+
+/// End nodes of a way which is not closed.
+fn open_endpoints(way: &Way) -> Result<Endpoints, Error> {
+    match (way.nodes.first(), way.nodes.last()) {
+        (Some(&start), Some(&end)) if start != end => Ok((start, end)),
+        _ => Err(Error::InvalidEdit(format!(
+            "way {} is closed or has no nodes",
+            way.id
+        ))),
+    }
+}
+
+/// Tags with the same meaning for the way in the other direction.
+fn reverse_tags(tags: &Tags) -> Tags {
+    let mut reversed = Tags::new();
+
+    for tag in tags.iter() {
+        let key = match reverse_key(&tag.key) {
+            Some(key) => Str::from(key),
+            None => tag.key.clone(),
+        };
+        let val = match reverse_value(&key, &tag.val) {
+            Some(val) => Str::from(val),
+            None => tag.val.clone(),
+        };
+        // Swapping is one to one, so keys stay unique
+        reversed.insert(key, val);
+    }
+
+    reversed
+}
+
+fn reverse_key(key: &str) -> Option<String> {
+    if !key.split(':').any(|part| swap_word(part).is_some()) {
+        return None;
+    }
+
+    let parts: Vec<&str> = key.split(':')
+        .map(|part| swap_word(part).unwrap_or(part))
+        .collect();
+    Some(parts.join(":"))
+}
+
+fn reverse_value(key: &str, val: &str) -> Option<String> {
+    if key == "oneway" || key.starts_with("oneway:") {
+        return match val {
+            "yes" | "true" | "1" => Some("-1".to_string()),
+            "-1" | "reverse" => Some("yes".to_string()),
+            _ => None,
+        };
+    }
+
+    if key == "incline" {
+        return match val {
+            "up" => Some("down".to_string()),
+            "down" => Some("up".to_string()),
+            _ => negate_measure(val),
+        };
+    }
+
+    if key == "direction" {
+        if let Some(index) = COMPASS.iter().position(|point| *point == val) {
+            return Some(COMPASS[(index + 8) % 16].to_string());
+        }
+        if let Ok(degrees) = val.parse::<u32>() {
+            return Some(((degrees + 180) % 360).to_string());
+        }
+    }
+
+    swap_word(val).map(str::to_string)
+}
+
+fn swap_word(word: &str) -> Option<&'static str> {
+    match word {
+        "left" => Some("right"),
+        "right" => Some("left"),
+        "forward" => Some("backward"),
+        "backward" => Some("forward"),
+        _ => None,
+    }
+}
+
+/// Flips sign of values like `10%` or `-5°`.
+fn negate_measure(val: &str) -> Option<String> {
+    let number = val.trim_end_matches(['%', '°']);
+    match number.parse::<f64>() {
+        Ok(value) if value != 0.0 => match val.strip_prefix('-') {
+            Some(positive) => Some(positive.to_string()),
+            None => Some(format!("-{}", val)),
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use elements::Tag;
+
+    #[test]
+    fn tags_reversed() {
+        let tags: Tags = vec![
+            Tag::new("highway", "primary"),
+            Tag::new("oneway", "yes"),
+            Tag::new("sidewalk:left", "separate"),
+            Tag::new("sidewalk:right", "no"),
+            Tag::new("parking:lane:both", "parallel"),
+            Tag::new("cycleway", "right"),
+            Tag::new("maxspeed:forward", "50"),
+            Tag::new("incline", "12%"),
+            Tag::new("direction", "NE"),
+        ].into();
+
+        let expected: Tags = vec![
+            Tag::new("highway", "primary"),
+            Tag::new("oneway", "-1"),
+            Tag::new("sidewalk:right", "separate"),
+            Tag::new("sidewalk:left", "no"),
+            Tag::new("parking:lane:both", "parallel"),
+            Tag::new("cycleway", "left"),
+            Tag::new("maxspeed:backward", "50"),
+            Tag::new("incline", "-12%"),
+            Tag::new("direction", "SW"),
+        ].into();
+
+        assert_eq!(reverse_tags(&tags), expected);
+        assert_eq!(reverse_tags(&expected), tags);
+        assert_eq!(reverse_value("incline", "0%"), None);
+        assert_eq!(reverse_value("incline", "down"), Some("up".to_string()));
+        assert_eq!(reverse_value("direction", "270"), Some("90".to_string()));
+        assert_eq!(reverse_value("oneway", "no"), None);
+    }
+}
@@ -0,0 +1,124 @@
+use elements::ElementKind;
+
+/// What to keep when parsing, see `OSM::parse_with_options`. Skipped data is dropped while
+/// reading, before anything is allocated for it.
+///
+/// ```
+/// use osm_xml::{ElementKind, ParseOptions};
+///
+/// let options = ParseOptions {
+///     kinds: vec![ElementKind::Node],
+///     deny_keys: vec!["created_by".to_string(), "tiger:*".to_string()],
+///     untagged_nodes: false,
+///     ..ParseOptions::default()
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct ParseOptions {
+    /// Element kinds to keep, default is all.
+    pub kinds: Vec<ElementKind>,
+    /// Whether to keep tags at all.
+    pub tags: bool,
+    /// Only tags with these keys are kept when not empty. `*` in a key matches any characters,
+    /// eg. `name:*`.
+    pub allow_keys: Vec<String>,
+    /// Tags with these keys are skipped, same patterns as in `allow_keys`.
+    pub deny_keys: Vec<String>,
+    /// Whether to keep nodes without tags, after the tags have been filtered.
+    pub untagged_nodes: bool,
+    /// Whether to keep element metadata, `Metadata::default()` is used when not.
+    pub metadata: bool,
+}
+
+impl Default for ParseOptions {
+    fn default() -> ParseOptions {
+        ParseOptions {
+            kinds: vec![ElementKind::Node, ElementKind::Way, ElementKind::Relation],
+            tags: true,
+            allow_keys: Vec::new(),
+            deny_keys: Vec::new(),
+            untagged_nodes: true,
+            metadata: true,
+        }
+    }
+}
+
+impl ParseOptions {
+    pub fn keeps(&self, kind: ElementKind) -> bool {
+        self.kinds.contains(&kind)
+    }
+
+    pub fn keeps_key(&self, key: &str) -> bool {
+        self.tags && (self.allow_keys.is_empty() || matches_any(&self.allow_keys, key))
+            && !matches_any(&self.deny_keys, key)
+    }
+}
+
+fn matches_any(patterns: &[String], key: &str) -> bool {
+    patterns.iter().any(|pattern| glob_matches(pattern, key))
+}
+
+/// Matches `key` against `pattern` where `*` is any sequence of characters.
+fn glob_matches(pattern: &str, key: &str) -> bool {
+    let mut parts = pattern.split('*');
+    // `split` always yields at least one part
+    let first = parts.next().unwrap_or("");
+    if !key.starts_with(first) {
+        return false;
+    }
+
+    let mut rest = &key[first.len()..];
+    let mut parts = parts.peekable();
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            // Last part after `*` has to end the key
+            return rest.len() >= part.len() && rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    // No `*` in the pattern
+    rest.is_empty()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn glob_patterns() {
+        assert!(glob_matches("name", "name"));
+        assert!(!glob_matches("name", "name:fi"));
+        assert!(glob_matches("tiger:*", "tiger:county"));
+        assert!(!glob_matches("tiger:*", "tiger"));
+        assert!(glob_matches("*:fi", "name:fi"));
+        assert!(glob_matches("a*b*c", "aXXbYYc"));
+        assert!(!glob_matches("a*b*c", "aXXcYYb"));
+        assert!(glob_matches("ab*b", "abb"));
+        assert!(!glob_matches("ab*ab", "ab"));
+        assert!(glob_matches("*", ""));
+    }
+
+    #[test]
+    fn allow_and_deny_keys() {
+        let options = ParseOptions {
+            allow_keys: vec!["name*".to_string(), "highway".to_string()],
+            deny_keys: vec!["name:*".to_string()],
+            ..ParseOptions::default()
+        };
+
+        assert!(options.keeps_key("name"));
+        assert!(options.keeps_key("highway"));
+        assert!(!options.keeps_key("name:fi"));
+        assert!(!options.keeps_key("building"));
+
+        let options = ParseOptions {
+            tags: false,
+            ..ParseOptions::default()
+        };
+        assert!(!options.keeps_key("name"));
+    }
+}
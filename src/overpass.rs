@@ -0,0 +1,1038 @@
+//! Interpreter for a practical subset of Overpass QL, evaluated against an in-memory `OSM`.
+//!
+//! Supported statements:
+//!
+//! - queries `node`, `way`, `relation` (or `rel`), `nwr`, `nw`, `wr` and `nr` with optional
+//!   input set (`node.a`) and filters:
+//!   - tags `[key]`, `[!key]`, `[key=val]`, `[key!=val]`, `[key~regex]`, `[key!~regex]`,
+//!     case-insensitive regex with `[key~regex,i]`
+//!   - bounding box `(south,west,north,east)`, `(bbox)` for the global `[bbox:...]` setting
+//!     which also restricts every query without it
+//!   - `(around:radius,lat,lon)` and `(around.set:radius)`
+//!   - ids `(123)` and `(id:1,2,3)`
+//!   - recursion filters `(w)`, `(r)`, `(bn)`, `(bw)` and `(br)` with optional input set
+//! - union `(...;);` and difference `(a; - b;);` blocks
+//! - recursion `>`, `>>`, `<` and `<<`
+//! - named sets with `->.name` and `.name;`
+//! - `out`, its options are accepted but ignored
+//!
+//! Ways and relations are matched spatially by their nodes: way is inside a bbox or around a
+//! point if any of its nodes is, relations are matched by their node members and the nodes of
+//! their way members.
+
+use std::str::FromStr;
+
+use fnv::FnvHashMap;
+use regex::RegexBuilder;
+
+use elements::{Bounds, Coordinate, ElementId, ElementKind, ElementRef, Id, OSMElement};
+use error::{Error, ErrorReason};
+use filter::Condition;
+use idset::{ElementIdSet, IdSet};
+use recursion::member_id;
+use OSM;
+
+type Set = ElementIdSet;
+
+const DEFAULT_SET: &str = "_";
+const EARTH_RADIUS: f64 = 6_371_000.0;
+
+/// Parsed Overpass QL program.
+#[derive(Debug, Clone)]
+pub struct Query {
+    statements: Vec<Statement>,
+    bbox: Option<Bounds>,
+}
+
+#[derive(Debug, Clone)]
+enum Statement {
+    Query {
+        kinds: Vec<ElementKind>,
+        input: Option<String>,
+        filters: Vec<QueryFilter>,
+        output: String,
+    },
+    Union {
+        statements: Vec<Statement>,
+        output: String,
+    },
+    Difference {
+        left: Box<Statement>,
+        right: Box<Statement>,
+        output: String,
+    },
+    Recurse {
+        input: String,
+        recurse: Recurse,
+        output: String,
+    },
+    Item {
+        input: String,
+        output: String,
+    },
+    Out {
+        input: String,
+    },
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Recurse {
+    Down,
+    DownAll,
+    Up,
+    UpAll,
+}
+
+#[derive(Debug, Clone)]
+enum QueryFilter {
+    Tag(Condition),
+    Bbox(Bounds),
+    Around {
+        radius: f64,
+        center: Option<(Coordinate, Coordinate)>,
+        input: String,
+    },
+    Ids(Vec<Id>),
+    Recurse {
+        recurse: RecurseFilter,
+        input: String,
+    },
+}
+
+#[derive(Debug, Copy, Clone)]
+enum RecurseFilter {
+    WayNodes,
+    RelationMembers,
+    BackNodes,
+    BackWays,
+    BackRelations,
+}
+
+/// Query filter with the data it needs computed up front.
+enum PreparedFilter<'q> {
+    Tag(&'q Condition),
+    Bbox(Bounds),
+    Around(f64, Vec<(Coordinate, Coordinate)>),
+    Ids(IdSet),
+    Members(Set),
+}
+
+impl Query {
+    /// Runs the query and collects everything written out by `out` statements to a new
+    /// document.
+    pub fn execute(&self, osm: &OSM) -> OSM {
+        osm.subset(self.output_ids(osm))
+    }
+
+    /// Runs the query and returns the output elements in output order. Each `out` writes its
+    /// set sorted by type and id, like Overpass does by default.
+    pub fn execute_elements<'a>(&self, osm: &'a OSM) -> Vec<ElementRef<'a>> {
+        self.output_ids(osm)
+            .into_iter()
+            .filter_map(|id| osm.get(id))
+            .collect()
+    }
+
+    fn output_ids(&self, osm: &OSM) -> Vec<ElementId> {
+        let mut executor = Executor {
+            osm: osm,
+            bbox: self.bbox,
+            sets: FnvHashMap::default(),
+            output: Vec::new(),
+        };
+
+        for statement in &self.statements {
+            executor.run(statement);
+        }
+
+        executor.output
+    }
+}
+
+impl FromStr for Query {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Query, Error> {
+        Parser {
+            src: s,
+            pos: 0,
+            bbox: None,
+        }.program()
+    }
+}
+
+impl OSM {
+    /// Parses and runs Overpass QL query against the document, see `overpass::Query`.
+    pub fn query(&self, query: &str) -> Result<OSM, Error> {
+        let query: Query = try!(query.parse());
+        Ok(query.execute(self))
+    }
+}
+
+struct Executor<'a> {
+    osm: &'a OSM,
+    bbox: Option<Bounds>,
+    sets: FnvHashMap<String, Set>,
+    output: Vec<ElementId>,
+}
+
+impl<'a> Executor<'a> {
+    fn run(&mut self, statement: &Statement) -> Set {
+        match *statement {
+            Statement::Query {
+                ref kinds,
+                ref input,
+                ref filters,
+                ref output,
+            } => {
+                let result = self.query(kinds, input.as_ref(), filters);
+                self.assign(output, result)
+            }
+            Statement::Union {
+                ref statements,
+                ref output,
+            } => {
+                let mut result = Set::default();
+                for statement in statements {
+                    result.union_with(&self.run(statement));
+                }
+                self.assign(output, result)
+            }
+            Statement::Difference {
+                ref left,
+                ref right,
+                ref output,
+            } => {
+                let result = self.run(left).difference(&self.run(right));
+                self.assign(output, result)
+            }
+            Statement::Recurse {
+                ref input,
+                recurse,
+                ref output,
+            } => {
+                let input = self.set(input).iter().collect::<Vec<_>>();
+                let result = match recurse {
+                    Recurse::Down => self.osm.children(input),
+                    Recurse::DownAll => self.osm.descendants(input),
+                    Recurse::Up => self.osm.parents(input),
+                    Recurse::UpAll => self.osm.ancestors(input),
+                };
+                self.assign(output, result)
+            }
+            Statement::Item {
+                ref input,
+                ref output,
+            } => {
+                let result = self.set(input).clone();
+                self.assign(output, result)
+            }
+            Statement::Out { ref input } => {
+                let result = self.set(input).clone();
+                // Sets iterate in file order
+                self.output.extend(result.iter());
+                result
+            }
+        }
+    }
+
+    fn assign(&mut self, name: &str, set: Set) -> Set {
+        self.sets.insert(name.to_string(), set.clone());
+        set
+    }
+
+    fn set(&self, name: &str) -> &Set {
+        // Unknown sets are empty like in Overpass
+        self.sets.get(name).unwrap_or_else(|| empty_set())
+    }
+
+    fn query(&self, kinds: &[ElementKind], input: Option<&String>, filters: &[QueryFilter]) -> Set {
+        let mut prepared: Vec<_> = filters.iter().map(|filter| self.prepare(filter)).collect();
+        // Global bbox applies to every query like in Overpass, not only ones with `(bbox)`
+        if let Some(bounds) = self.bbox {
+            prepared.push(PreparedFilter::Bbox(bounds));
+        }
+
+        let candidates: Vec<ElementRef> = match input {
+            Some(name) => self.set(name)
+                .iter()
+                .filter(|id| kinds.contains(&id.kind()))
+                .filter_map(|id| self.osm.get(id))
+                .collect(),
+            None => self.osm
+                .elements()
+                .filter(|element| kinds.contains(&element.kind()))
+                .collect(),
+        };
+
+        candidates
+            .into_iter()
+            .filter(|element| prepared.iter().all(|filter| self.matches(filter, element)))
+            .map(|element| element.element_id())
+            .collect()
+    }
+
+    fn prepare<'q>(&self, filter: &'q QueryFilter) -> PreparedFilter<'q> {
+        match *filter {
+            QueryFilter::Tag(ref condition) => PreparedFilter::Tag(condition),
+            QueryFilter::Bbox(bounds) => PreparedFilter::Bbox(bounds),
+            QueryFilter::Around {
+                radius,
+                center,
+                ref input,
+            } => {
+                let centers = match center {
+                    Some(center) => vec![center],
+                    None => self.set(input)
+                        .iter()
+                        .filter_map(|id| self.osm.get(id))
+                        .flat_map(|element| self.points(&element))
+                        .collect(),
+                };
+                PreparedFilter::Around(radius, centers)
+            }
+            QueryFilter::Ids(ref ids) => PreparedFilter::Ids(ids.iter().cloned().collect()),
+            QueryFilter::Recurse { recurse, ref input } => {
+                PreparedFilter::Members(self.recurse_filter(recurse, self.set(input)))
+            }
+        }
+    }
+
+    fn matches(&self, filter: &PreparedFilter, element: &ElementRef) -> bool {
+        match *filter {
+            PreparedFilter::Tag(condition) => condition.matches(element.tags()),
+            PreparedFilter::Bbox(ref bounds) => self.points(element)
+                .iter()
+                .any(|&point| in_bounds(bounds, point)),
+            PreparedFilter::Around(radius, ref centers) => {
+                self.points(element).iter().any(|&point| {
+                    centers
+                        .iter()
+                        .any(|&center| distance(point, center) <= radius)
+                })
+            }
+            PreparedFilter::Ids(ref ids) => ids.contains(element.element_id().id()),
+            PreparedFilter::Members(ref members) => members.contains(element.element_id()),
+        }
+    }
+
+    fn recurse_filter(&self, recurse: RecurseFilter, input: &Set) -> Set {
+        let mut result = Set::default();
+
+        match recurse {
+            RecurseFilter::WayNodes => for id in &input.ways {
+                let nodes = self.osm.ways.get(&id).map(|way| way.nodes.iter());
+                result.extend(nodes.into_iter().flatten().map(|r| ElementId::from(*r)));
+            },
+            RecurseFilter::RelationMembers => for id in &input.relations {
+                let members = self.osm.relations.get(&id).map(|rel| rel.members.iter());
+                result.extend(members.into_iter().flatten().map(member_id));
+            },
+            RecurseFilter::BackNodes => {
+                for way in self.osm.ways.values() {
+                    if way.nodes
+                        .iter()
+                        .any(|r| input.contains(ElementId::from(*r)))
+                    {
+                        result.insert(ElementId::Way(way.id));
+                    }
+                }
+                result.extend(self.parent_relations(input, ElementKind::Node));
+            }
+            RecurseFilter::BackWays => {
+                result.extend(self.parent_relations(input, ElementKind::Way));
+            }
+            RecurseFilter::BackRelations => {
+                result.extend(self.parent_relations(input, ElementKind::Relation));
+            }
+        }
+
+        result
+    }
+
+    fn parent_relations(&self, input: &Set, kind: ElementKind) -> Vec<ElementId> {
+        self.osm
+            .relations
+            .values()
+            .filter(|rel| {
+                rel.members.iter().any(|member| {
+                    let id = member_id(member);
+                    id.kind() == kind && input.contains(id)
+                })
+            })
+            .map(|rel| ElementId::Relation(rel.id))
+            .collect()
+    }
+
+    fn points(&self, element: &ElementRef) -> Vec<(Coordinate, Coordinate)> {
+        match *element {
+            ElementRef::Node(node) => vec![(node.lat, node.lon)],
+            ElementRef::Way(way) => self.way_points(way.id),
+            ElementRef::Relation(relation) => {
+                let mut points = Vec::new();
+                for member in &relation.members {
+                    match member_id(member) {
+                        ElementId::Node(id) => if let Some(node) = self.osm.nodes.get(&id) {
+                            points.push((node.lat, node.lon));
+                        },
+                        ElementId::Way(id) => points.extend(self.way_points(id)),
+                        ElementId::Relation(_) => (),
+                    }
+                }
+                points
+            }
+        }
+    }
+
+    fn way_points(&self, id: Id) -> Vec<(Coordinate, Coordinate)> {
+        let nodes = self.osm.ways.get(&id).map(|way| way.nodes.iter());
+        nodes
+            .into_iter()
+            .flatten()
+            .filter_map(|r| self.osm.nodes.get(&ElementId::from(*r).id()))
+            .map(|node| (node.lat, node.lon))
+            .collect()
+    }
+}
+
+fn empty_set() -> &'static Set {
+    use std::sync::OnceLock;
+    static EMPTY: OnceLock<Set> = OnceLock::new();
+    EMPTY.get_or_init(Set::default)
+}
+
+fn in_bounds(bounds: &Bounds, (lat, lon): (Coordinate, Coordinate)) -> bool {
+    lat >= bounds.minlat && lat <= bounds.maxlat && lon >= bounds.minlon && lon <= bounds.maxlon
+}
+
+/// Great-circle distance in meters.
+fn distance(a: (Coordinate, Coordinate), b: (Coordinate, Coordinate)) -> f64 {
+    let (lat1, lon1) = (a.0.to_radians(), a.1.to_radians());
+    let (lat2, lon2) = (b.0.to_radians(), b.1.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = lon2 - lon1;
+
+    let h = (dlat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / 2.0).sin().powi(2);
+    2.0 * EARTH_RADIUS * h.sqrt().asin()
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    /// Global bbox from the settings
+    bbox: Option<Bounds>,
+}
+
+impl<'a> Parser<'a> {
+    fn program(&mut self) -> Result<Query, Error> {
+        self.skip_ws();
+        if self.peek() == Some('[') {
+            while self.eat("[") {
+                let name = try!(self.word());
+                try!(self.expect(":"));
+                if name == "bbox" {
+                    self.bbox = Some(try!(self.bounds()));
+                } else {
+                    while self.peek().map(|c| c != ']').unwrap_or(false) {
+                        self.pos += 1;
+                    }
+                }
+                try!(self.expect("]"));
+                self.skip_ws();
+            }
+            try!(self.expect(";"));
+        }
+
+        let mut statements = Vec::new();
+        loop {
+            self.skip_ws();
+            if self.pos == self.src.len() {
+                break;
+            }
+            statements.push(try!(self.statement()));
+        }
+
+        Ok(Query {
+            statements: statements,
+            bbox: self.bbox,
+        })
+    }
+
+    fn statement(&mut self) -> Result<Statement, Error> {
+        self.skip_ws();
+
+        if self.eat("(") {
+            return self.block();
+        }
+
+        if self.eat(".") {
+            let input = try!(self.word()).to_string();
+            self.skip_ws();
+            if self.eat_word("out") {
+                return self.out(input);
+            }
+            if self.peek() == Some('>') || self.peek() == Some('<') {
+                return self.recurse(input);
+            }
+
+            let output = try!(self.output());
+            try!(self.expect(";"));
+            return Ok(Statement::Item {
+                input: input,
+                output: output,
+            });
+        }
+
+        if self.peek() == Some('>') || self.peek() == Some('<') {
+            return self.recurse(DEFAULT_SET.to_string());
+        }
+
+        let start = self.pos;
+        let kinds = match try!(self.word()) {
+            "out" => return self.out(DEFAULT_SET.to_string()),
+            "node" => vec![ElementKind::Node],
+            "way" => vec![ElementKind::Way],
+            "relation" | "rel" => vec![ElementKind::Relation],
+            "nwr" => vec![ElementKind::Node, ElementKind::Way, ElementKind::Relation],
+            "nw" => vec![ElementKind::Node, ElementKind::Way],
+            "wr" => vec![ElementKind::Way, ElementKind::Relation],
+            "nr" => vec![ElementKind::Node, ElementKind::Relation],
+            _ => return Err(syntax_error(start)),
+        };
+
+        self.query(kinds)
+    }
+
+    fn block(&mut self) -> Result<Statement, Error> {
+        let mut statements = Vec::new();
+
+        loop {
+            self.skip_ws();
+            if self.eat(")") {
+                let output = try!(self.output());
+                try!(self.expect(";"));
+                return Ok(Statement::Union {
+                    statements: statements,
+                    output: output,
+                });
+            }
+
+            if self.peek() == Some('-') && !self.starts_with("->") {
+                let start = self.pos;
+                self.pos += 1;
+                if statements.len() != 1 {
+                    return Err(syntax_error(start));
+                }
+
+                let right = try!(self.statement());
+                self.skip_ws();
+                try!(self.expect(")"));
+                let output = try!(self.output());
+                try!(self.expect(";"));
+                return Ok(Statement::Difference {
+                    left: Box::new(statements.remove(0)),
+                    right: Box::new(right),
+                    output: output,
+                });
+            }
+
+            statements.push(try!(self.statement()));
+        }
+    }
+
+    fn out(&mut self, input: String) -> Result<Statement, Error> {
+        // Output options (verbosity, geometry, sorting) do not affect the result
+        while self.peek().map(|c| c != ';').unwrap_or(false) {
+            self.pos += self.peek().map(|c| c.len_utf8()).unwrap_or(1);
+        }
+        try!(self.expect(";"));
+        Ok(Statement::Out { input: input })
+    }
+
+    fn recurse(&mut self, input: String) -> Result<Statement, Error> {
+        let recurse = if self.eat(">>") {
+            Recurse::DownAll
+        } else if self.eat(">") {
+            Recurse::Down
+        } else if self.eat("<<") {
+            Recurse::UpAll
+        } else if self.eat("<") {
+            Recurse::Up
+        } else {
+            return Err(syntax_error(self.pos));
+        };
+
+        let output = try!(self.output());
+        try!(self.expect(";"));
+        Ok(Statement::Recurse {
+            input: input,
+            recurse: recurse,
+            output: output,
+        })
+    }
+
+    fn query(&mut self, kinds: Vec<ElementKind>) -> Result<Statement, Error> {
+        let input = if self.eat(".") {
+            Some(try!(self.word()).to_string())
+        } else {
+            None
+        };
+
+        let mut filters = Vec::new();
+        loop {
+            self.skip_ws();
+            if self.eat("[") {
+                filters.push(try!(self.tag_filter()));
+            } else if self.eat("(") {
+                filters.push(try!(self.paren_filter()));
+            } else {
+                break;
+            }
+        }
+
+        let output = try!(self.output());
+        try!(self.expect(";"));
+        Ok(Statement::Query {
+            kinds: kinds,
+            input: input,
+            filters: filters,
+            output: output,
+        })
+    }
+
+    fn tag_filter(&mut self) -> Result<QueryFilter, Error> {
+        self.skip_ws();
+        if self.eat("!") {
+            let key = try!(self.string());
+            try!(self.expect("]"));
+            return Ok(QueryFilter::Tag(Condition::Missing(key)));
+        }
+
+        let key = try!(self.string());
+        self.skip_ws();
+        if self.eat("]") {
+            return Ok(QueryFilter::Tag(Condition::Exists(key)));
+        }
+
+        let start = self.pos;
+        let operator = if self.eat("!=") {
+            "!="
+        } else if self.eat("!~") {
+            "!~"
+        } else if self.eat("=") {
+            "="
+        } else if self.eat("~") {
+            "~"
+        } else {
+            return Err(syntax_error(start));
+        };
+
+        self.skip_ws();
+        let value = try!(self.string());
+        self.skip_ws();
+        let case_insensitive = if self.eat(",") {
+            self.skip_ws();
+            try!(self.expect("i"));
+            true
+        } else {
+            false
+        };
+        try!(self.expect("]"));
+
+        let condition = match operator {
+            "=" => Condition::ValueIn(key, vec![value]),
+            "!=" => Condition::ValueNotIn(key, vec![value]),
+            _ => {
+                let regex = try!(
+                    RegexBuilder::new(&value)
+                        .case_insensitive(case_insensitive)
+                        .build()
+                        .map_err(|err| Error::MalformedQuery(ErrorReason::from(err)))
+                );
+                if operator == "~" {
+                    Condition::Matches(key, regex)
+                } else {
+                    Condition::NotMatches(key, regex)
+                }
+            }
+        };
+
+        Ok(QueryFilter::Tag(condition))
+    }
+
+    fn paren_filter(&mut self) -> Result<QueryFilter, Error> {
+        self.skip_ws();
+        let start = self.pos;
+
+        if self.peek().map(|c| c.is_alphabetic()).unwrap_or(false) {
+            let word = try!(self.word());
+            let filter = match word {
+                "bbox" => match self.bbox {
+                    Some(bounds) => QueryFilter::Bbox(bounds),
+                    // `(bbox)` needs the global bbox setting
+                    None => return Err(syntax_error(start)),
+                },
+                "id" => {
+                    try!(self.expect(":"));
+                    let mut ids = vec![try!(self.id())];
+                    while self.eat(",") {
+                        ids.push(try!(self.id()));
+                    }
+                    QueryFilter::Ids(ids)
+                }
+                "around" => {
+                    let input = try!(self.input_set());
+                    try!(self.expect(":"));
+                    let radius = try!(self.number());
+                    let center = if self.eat(",") {
+                        let lat = try!(self.number());
+                        try!(self.expect(","));
+                        Some((lat, try!(self.number())))
+                    } else {
+                        None
+                    };
+                    QueryFilter::Around {
+                        radius: radius,
+                        center: center,
+                        input: input,
+                    }
+                }
+                _ => {
+                    let recurse = match word {
+                        "w" => RecurseFilter::WayNodes,
+                        "r" => RecurseFilter::RelationMembers,
+                        "bn" => RecurseFilter::BackNodes,
+                        "bw" => RecurseFilter::BackWays,
+                        "br" => RecurseFilter::BackRelations,
+                        _ => return Err(syntax_error(start)),
+                    };
+                    QueryFilter::Recurse {
+                        recurse: recurse,
+                        input: try!(self.input_set()),
+                    }
+                }
+            };
+
+            try!(self.expect(")"));
+            return Ok(filter);
+        }
+
+        // Either id or the first coordinate of a bbox
+        let first = self.number_text();
+        if self.eat(",") {
+            let first = try!(parse_number(first));
+            let west = try!(self.number());
+            try!(self.expect(","));
+            let north = try!(self.number());
+            try!(self.expect(","));
+            let east = try!(self.number());
+            try!(self.expect(")"));
+            return Ok(QueryFilter::Bbox(Bounds {
+                minlat: first,
+                minlon: west,
+                maxlat: north,
+                maxlon: east,
+            }));
+        }
+
+        let id = try!(parse_number(first));
+        try!(self.expect(")"));
+        Ok(QueryFilter::Ids(vec![id]))
+    }
+
+    fn input_set(&mut self) -> Result<String, Error> {
+        if self.eat(".") {
+            Ok(try!(self.word()).to_string())
+        } else {
+            Ok(DEFAULT_SET.to_string())
+        }
+    }
+
+    fn output(&mut self) -> Result<String, Error> {
+        self.skip_ws();
+        if !self.eat("->") {
+            return Ok(DEFAULT_SET.to_string());
+        }
+
+        self.skip_ws();
+        try!(self.expect("."));
+        Ok(try!(self.word()).to_string())
+    }
+
+    fn bounds(&mut self) -> Result<Bounds, Error> {
+        let south = try!(self.number());
+        try!(self.expect(","));
+        let west = try!(self.number());
+        try!(self.expect(","));
+        let north = try!(self.number());
+        try!(self.expect(","));
+        let east = try!(self.number());
+
+        Ok(Bounds {
+            minlat: south,
+            minlon: west,
+            maxlat: north,
+            maxlon: east,
+        })
+    }
+
+    /// Quoted string or bare tag key/value.
+    fn string(&mut self) -> Result<String, Error> {
+        self.skip_ws();
+        let quote = match self.peek() {
+            Some(c) if c == '"' || c == '\'' => c,
+            _ => {
+                let start = self.pos;
+                let len = self.rest()
+                    .find(|c: char| !(c.is_alphanumeric() || "_:-.".contains(c)))
+                    .unwrap_or(self.rest().len());
+                if len == 0 {
+                    return Err(syntax_error(start));
+                }
+                self.pos += len;
+                return Ok(self.src[start..self.pos].to_string());
+            }
+        };
+
+        let start = self.pos;
+        self.pos += 1;
+        let mut value = String::new();
+        let mut chars = self.rest().chars();
+        loop {
+            match chars.next() {
+                Some('\\') => match chars.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(c) => value.push(c),
+                    None => return Err(syntax_error(start)),
+                },
+                Some(c) if c == quote => break,
+                Some(c) => value.push(c),
+                None => return Err(syntax_error(start)),
+            }
+        }
+
+        self.pos = self.src.len() - chars.as_str().len();
+        Ok(value)
+    }
+
+    fn word(&mut self) -> Result<&'a str, Error> {
+        self.skip_ws();
+        let start = self.pos;
+        let len = self.rest()
+            .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(self.rest().len());
+        if len == 0 {
+            return Err(syntax_error(start));
+        }
+
+        self.pos += len;
+        Ok(&self.src[start..self.pos])
+    }
+
+    fn number(&mut self) -> Result<f64, Error> {
+        let text = self.number_text();
+        parse_number(text)
+    }
+
+    fn id(&mut self) -> Result<Id, Error> {
+        let text = self.number_text();
+        parse_number(text)
+    }
+
+    /// Characters of a number and its position, whitespace around it is skipped.
+    fn number_text(&mut self) -> (&'a str, usize) {
+        self.skip_ws();
+        let start = self.pos;
+        let len = self.rest()
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(self.rest().len());
+        self.pos += len;
+        self.skip_ws();
+
+        (&self.src[start..start + len], start)
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        let boundary = self.rest()[word.len().min(self.rest().len())..]
+            .chars()
+            .next()
+            .map(|c| !(c.is_alphanumeric() || c == '_'))
+            .unwrap_or(true);
+
+        if self.starts_with(word) && boundary {
+            self.pos += word.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_ws();
+        if self.starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), Error> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(syntax_error(self.pos))
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        loop {
+            let rest = self.rest();
+            let trimmed = rest.trim_start();
+            self.pos += rest.len() - trimmed.len();
+
+            if trimmed.starts_with("//") {
+                self.pos += trimmed.find('\n').unwrap_or(trimmed.len());
+            } else if trimmed.starts_with("/*") {
+                self.pos += trimmed.find("*/").map(|end| end + 2).unwrap_or(trimmed.len());
+            } else {
+                return;
+            }
+        }
+    }
+
+    fn starts_with(&self, token: &str) -> bool {
+        self.rest().starts_with(token)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+}
+
+fn parse_number<T: FromStr>((text, pos): (&str, usize)) -> Result<T, Error> {
+    text.parse().map_err(|_| syntax_error(pos))
+}
+
+fn syntax_error(pos: usize) -> Error {
+    Error::MalformedQuery(ErrorReason::Syntax(pos))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(query: &str) -> Query {
+        query.parse().unwrap()
+    }
+
+    #[test]
+    fn parse_settings_and_statements() {
+        let query = parse(
+            "[out:xml][timeout:25][bbox:60.1,24.9,60.2,25.0];\n\
+             // comment\n\
+             node[amenity=cafe](bbox); /* block */ out meta;",
+        );
+        assert_eq!(query.statements.len(), 2);
+        assert_eq!(
+            query.bbox,
+            Some(Bounds {
+                minlat: 60.1,
+                minlon: 24.9,
+                maxlat: 60.2,
+                maxlon: 25.0,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_union_difference_and_sets() {
+        let query = parse(
+            "(way[highway]; - way[highway=primary];)->.roads; \
+             .roads >->.nodes; (.roads; .nodes;); out;",
+        );
+        assert_eq!(query.statements.len(), 4);
+        match query.statements[0] {
+            Statement::Difference { ref output, .. } => assert_eq!(output, "roads"),
+            _ => panic!("Expected difference!"),
+        }
+        match query.statements[1] {
+            Statement::Recurse {
+                ref input,
+                ref output,
+                ..
+            } => {
+                assert_eq!(input, "roads");
+                assert_eq!(output, "nodes");
+            }
+            _ => panic!("Expected recursion!"),
+        }
+    }
+
+    #[test]
+    fn parse_filters() {
+        let query = parse(
+            "nwr[\"name:fi\"~'^hel',i][!building](60.1,24.9,60.2,25.0)(around:100,60.17,24.94)\
+             (id:1,2)(bn.a);",
+        );
+        match query.statements[0] {
+            Statement::Query { ref filters, .. } => assert_eq!(filters.len(), 6),
+            _ => panic!("Expected query!"),
+        }
+    }
+
+    #[test]
+    fn syntax_errors_report_position() {
+        match "node[highway=primary;".parse::<Query>() {
+            Err(Error::MalformedQuery(ErrorReason::Syntax(pos))) => assert_eq!(pos, 20),
+            other => panic!("Unexpected result {:?}", other),
+        }
+        match "nodes;".parse::<Query>() {
+            Err(Error::MalformedQuery(ErrorReason::Syntax(pos))) => assert_eq!(pos, 0),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn global_bbox_is_required_for_bbox_filter() {
+        match "node(bbox); out;".parse::<Query>() {
+            Err(Error::MalformedQuery(ErrorReason::Syntax(pos))) => assert_eq!(pos, 5),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn ids_are_parsed_as_integers() {
+        // Not exactly representable as f64
+        let query = parse("node(9007199254740993); way(id:9007199254740995,2);");
+        match query.statements[0] {
+            Statement::Query { ref filters, .. } => match filters[0] {
+                QueryFilter::Ids(ref ids) => assert_eq!(ids, &[9007199254740993]),
+                _ => panic!("Expected ids!"),
+            },
+            _ => panic!("Expected query!"),
+        }
+        match query.statements[1] {
+            Statement::Query { ref filters, .. } => match filters[0] {
+                QueryFilter::Ids(ref ids) => assert_eq!(ids, &[9007199254740995, 2]),
+                _ => panic!("Expected ids!"),
+            },
+            _ => panic!("Expected query!"),
+        }
+        assert!("node(1.5);".parse::<Query>().is_err());
+    }
+
+    #[test]
+    fn distance_in_meters() {
+        let helsinki = (60.1699, 24.9384);
+        let tallinn = (59.4370, 24.7536);
+        let d = distance(helsinki, tallinn);
+        assert!(d > 81_000.0 && d < 83_000.0);
+        assert_eq!(distance(helsinki, helsinki), 0.0);
+    }
+}
@@ -0,0 +1,163 @@
+use std::fs::File;
+use std::ops::Range;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+use memmap2::Mmap;
+
+use chunks::{self, Chunk, Scanner};
+use elements::{Element, Member, Tag};
+use error::Error;
+use reader::ReaderBuilder;
+use symbols::Symbols;
+use tags::Tags;
+use OSM;
+
+// More chunks than threads so that threads finishing early can pick up more work
+const CHUNKS_PER_THREAD: usize = 4;
+
+impl OSM {
+    /// Parses the document on `threads` threads, or on all available cores when `threads` is 0.
+    ///
+    /// The document is split at top level elements and the chunks are parsed separately, the
+    /// result is the same as with `parse`. Finding the split points needs one quick pass over the
+    /// document before parsing. The document must be UTF-8, which is the case for OSM XML.
+    pub fn parse_parallel(data: &[u8], threads: usize) -> Result<OSM, Error> {
+        ReaderBuilder::new().parse_parallel(data, threads)
+    }
+
+    /// Memory maps the file and parses it with `parse_parallel`.
+    pub fn parse_file_parallel<P: AsRef<Path>>(path: P, threads: usize) -> Result<OSM, Error> {
+        let file = try!(File::open(path));
+        // The file must not be modified while it is being parsed
+        let map = try!(unsafe { Mmap::map(&file) });
+        OSM::parse_parallel(&map, threads)
+    }
+
+    /// Adds the chunk read after the current contents, like `parse` would have. With `intern`
+    /// the strings of the chunk are interned to `self.symbols`.
+    pub(crate) fn merge(&mut self, chunk: Chunk, intern: bool) -> Result<(), Error> {
+        for mut element in chunk.elements {
+            if intern {
+                intern_element(&mut element, &mut self.symbols);
+            }
+            self.insert(element);
+        }
+        for mut area in chunk.areas {
+            if intern {
+                area.tags = intern_tags(&area.tags, &mut self.symbols);
+            }
+            self.areas.insert(area.id, area);
+        }
+        for (id, tag) in chunk.duplicate_tags {
+            let tag = if intern {
+                Tag {
+                    key: self.symbols.intern(&tag.key),
+                    val: self.symbols.intern(&tag.val),
+                }
+            } else {
+                tag
+            };
+            self.duplicate_tags.push((id, tag));
+        }
+        self.counts.extend(chunk.counts);
+        if let Some(bounds) = chunk.bounds {
+            self.bounds = bounds;
+        }
+
+        match chunk.error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ReaderBuilder {
+    /// Parses the document on `threads` threads with this configuration, see
+    /// `OSM::parse_parallel`.
+    pub fn parse_parallel(&self, data: &[u8], threads: usize) -> Result<OSM, Error> {
+        let threads = if threads == 0 {
+            thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+        } else {
+            threads
+        };
+
+        let chunk_size = data.len() / (threads * CHUNKS_PER_THREAD) + 1;
+        let ranges = split(data, chunk_size);
+
+        let next = AtomicUsize::new(0);
+        let parts: Mutex<Vec<Option<Chunk>>> = Mutex::new(ranges.iter().map(|_| None).collect());
+
+        thread::scope(|scope| {
+            for _ in 0..threads.min(ranges.len()) {
+                scope.spawn(|| loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let range = match ranges.get(index) {
+                        Some(range) => range.clone(),
+                        None => break,
+                    };
+
+                    let first = range.start == 0;
+                    let last = range.end == data.len();
+                    // Chunks don't intern, their strings are interned once when merging
+                    let chunk = chunks::read_chunk(&data[range], first, last, self, None);
+                    parts.lock().unwrap()[index] = Some(chunk);
+                });
+            }
+        });
+
+        let mut osm = OSM::new();
+        osm.symbols = self.symbols_copy().unwrap_or_default();
+        for chunk in parts.into_inner().unwrap() {
+            // Every chunk has been read once the scope ends
+            try!(osm.merge(chunk.unwrap(), self.interns()));
+        }
+
+        Ok(osm)
+    }
+}
+
+fn intern_element(element: &mut Element, symbols: &mut Symbols) {
+    match *element {
+        Element::Node(ref mut node) => node.tags = intern_tags(&node.tags, symbols),
+        Element::Way(ref mut way) => way.tags = intern_tags(&way.tags, symbols),
+        Element::Relation(ref mut relation) => {
+            relation.tags = intern_tags(&relation.tags, symbols);
+            for member in &mut relation.members {
+                match *member {
+                    Member::Node(_, ref mut role) |
+                    Member::Way(_, ref mut role) |
+                    Member::Relation(_, ref mut role) => *role = symbols.intern(role),
+                }
+            }
+        }
+    }
+}
+
+fn intern_tags(tags: &Tags, symbols: &mut Symbols) -> Tags {
+    tags.iter()
+        .map(|tag| Tag {
+            key: symbols.intern(&tag.key),
+            val: symbols.intern(&tag.val),
+        })
+        .collect()
+}
+
+/// Splits the document to ranges of about `chunk_size` bytes at top level elements.
+fn split(data: &[u8], chunk_size: usize) -> Vec<Range<usize>> {
+    let mut ranges = Vec::new();
+    let mut scanner = Scanner::new();
+    let mut start = 0;
+
+    while let Some(element) = scanner.next_element(data) {
+        if element >= start + chunk_size {
+            ranges.push(start..element);
+            start = element;
+        }
+    }
+
+    ranges.push(start..data.len());
+    ranges
+}
@@ -1,177 +1,362 @@
-use elements::{Way, Tag};
+use std::io::Read;
+use std::slice;
+use std::sync::OnceLock;
 
-struct Rule {
-    key: &'static str,
-    polygon: RuleType,
-    values: [&'static str; 6],
-}
+use serde_json::{self, Value};
 
-enum RuleType {
+use elements::{ElementRef, Node, Relation, Tag, Way};
+use error::{Error, ErrorReason};
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum RuleType {
     All,
     Blacklist,
     Whitelist,
 }
 
-static RULES: [Rule; 26] =
-    [Rule {
-         key: "building",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "highway",
-         polygon: RuleType::Whitelist,
-         values: ["services", "rest_area", "escape", "elevator", "", ""],
-     },
-     Rule {
-         key: "natural",
-         polygon: RuleType::Blacklist,
-         values: ["coastline", "cliff", "ridge", "arete", "tree_row", ""],
-     },
-     Rule {
-         key: "landuse",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "waterway",
-         polygon: RuleType::Whitelist,
-         values: ["riverbank", "dock", "boatyard", "dam", "", ""],
-     },
-     Rule {
-         key: "amenity",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "leisure",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "barrier",
-         polygon: RuleType::Whitelist,
-         values: ["city_wall", "ditch", "hedge", "retaining_wall", "wall", "spikes"],
-     },
-     Rule {
-         key: "railway",
-         polygon: RuleType::Whitelist,
-         values: ["station", "turntable", "roundhouse", "platform", "", ""],
-     },
-     Rule {
-         key: "area",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "boundary",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "man_made",
-         polygon: RuleType::Blacklist,
-         values: ["cutline", "embankment", "pipeline", "", "", ""],
-     },
-     Rule {
-         key: "power",
-         polygon: RuleType::Whitelist,
-         values: ["plant", "substation", "generator", "transformer", "", ""],
-     },
-     Rule {
-         key: "place",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "shop",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "aeroway",
-         polygon: RuleType::Blacklist,
-         values: ["taxiway", "", "", "", "", ""],
-     },
-     Rule {
-         key: "tourism",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "historic",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "public_transport",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "office",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "building:part",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "military",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "ruins",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "area:highway",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "craft",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     },
-     Rule {
-         key: "golf",
-         polygon: RuleType::All,
-         values: ["", "", "", "", "", ""],
-     }];
+/// Decides whether closed way with the tag `key` is an area. Depending on the rule type either
+/// all values, only the listed values or all but the listed values are areas.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PolygonRule {
+    pub key: String,
+    pub polygon: RuleType,
+    pub values: Vec<String>,
+}
 
-pub fn is_polygon(way: &Way) -> bool {
-    if is_closed_loop(way) {
-        return true;
+/// Set of polygon rules, by default the ones from osm-polygon-features. Rules can be modified
+/// or loaded from the osm-polygon-features JSON file.
+#[derive(Debug, PartialEq, Clone)]
+pub struct PolygonRules {
+    rules: Vec<PolygonRule>,
+}
+
+struct DefaultRule {
+    key: &'static str,
+    polygon: RuleType,
+    values: &'static [&'static str],
+}
+
+static DEFAULT_RULES: [DefaultRule; 26] = [
+    DefaultRule {
+        key: "building",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "highway",
+        polygon: RuleType::Whitelist,
+        values: &["services", "rest_area", "escape", "elevator"],
+    },
+    DefaultRule {
+        key: "natural",
+        polygon: RuleType::Blacklist,
+        values: &["coastline", "cliff", "ridge", "arete", "tree_row"],
+    },
+    DefaultRule {
+        key: "landuse",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "waterway",
+        polygon: RuleType::Whitelist,
+        values: &["riverbank", "dock", "boatyard", "dam"],
+    },
+    DefaultRule {
+        key: "amenity",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "leisure",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "barrier",
+        polygon: RuleType::Whitelist,
+        values: &["city_wall", "ditch", "hedge", "retaining_wall", "wall", "spikes"],
+    },
+    DefaultRule {
+        key: "railway",
+        polygon: RuleType::Whitelist,
+        values: &["station", "turntable", "roundhouse", "platform"],
+    },
+    DefaultRule {
+        key: "area",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "boundary",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "man_made",
+        polygon: RuleType::Blacklist,
+        values: &["cutline", "embankment", "pipeline"],
+    },
+    DefaultRule {
+        key: "power",
+        polygon: RuleType::Whitelist,
+        values: &["plant", "substation", "generator", "transformer"],
+    },
+    DefaultRule {
+        key: "place",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "shop",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "aeroway",
+        polygon: RuleType::Blacklist,
+        values: &["taxiway"],
+    },
+    DefaultRule {
+        key: "tourism",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "historic",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "public_transport",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "office",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "building:part",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "military",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "ruins",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "area:highway",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "craft",
+        polygon: RuleType::All,
+        values: &[],
+    },
+    DefaultRule {
+        key: "golf",
+        polygon: RuleType::All,
+        values: &[],
+    },
+];
+
+impl PolygonRules {
+    pub fn new() -> PolygonRules {
+        PolygonRules { rules: Vec::new() }
+    }
+
+    /// Reads rules from JSON in the osm-polygon-features format:
+    /// `[{"key": "highway", "polygon": "whitelist", "values": ["services"]}, ...]`
+    pub fn from_json<R: Read>(source: R) -> Result<PolygonRules, Error> {
+        let json: Value = try!(serde_json::from_reader(source));
+        let entries = try!(
+            json.as_array()
+                .ok_or(Error::MalformedPolygonRule(ErrorReason::InvalidValue))
+        );
+
+        let mut rules = PolygonRules::new();
+        for entry in entries {
+            rules.insert(try!(parse_rule(entry)));
+        }
+
+        Ok(rules)
+    }
+
+    pub fn get(&self, key: &str) -> Option<&PolygonRule> {
+        self.rules.iter().find(|rule| rule.key == key)
+    }
+
+    /// Adds the rule, replacing and returning the existing rule for the same key.
+    pub fn insert(&mut self, rule: PolygonRule) -> Option<PolygonRule> {
+        match self.rules.iter().position(|existing| existing.key == rule.key) {
+            Some(pos) => Some(::std::mem::replace(&mut self.rules[pos], rule)),
+            None => {
+                self.rules.push(rule);
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<PolygonRule> {
+        let pos = self.rules.iter().position(|rule| rule.key == key);
+        pos.map(|pos| self.rules.remove(pos))
+    }
+
+    pub fn iter(&self) -> slice::Iter<'_, PolygonRule> {
+        self.rules.iter()
+    }
+
+    fn matches(&self, tag: &Tag) -> bool {
+        tag.val != "no" && self.get(&tag.key)
+            .map(|rule| has_matching_rule_value(rule, tag))
+            .unwrap_or(false)
+    }
+}
+
+impl Default for PolygonRules {
+    fn default() -> PolygonRules {
+        let rules = DEFAULT_RULES
+            .iter()
+            .map(|rule| PolygonRule {
+                key: rule.key.to_string(),
+                polygon: rule.polygon,
+                values: rule.values.iter().map(|val| val.to_string()).collect(),
+            })
+            .collect();
+
+        PolygonRules { rules: rules }
+    }
+}
+
+fn default_rules() -> &'static PolygonRules {
+    static RULES: OnceLock<PolygonRules> = OnceLock::new();
+    RULES.get_or_init(PolygonRules::default)
+}
+
+fn parse_rule(entry: &Value) -> Result<PolygonRule, Error> {
+    let key = try!(
+        entry.get("key")
+            .and_then(Value::as_str)
+            .ok_or(Error::MalformedPolygonRule(ErrorReason::Missing))
+    );
+    let polygon = match entry.get("polygon").and_then(Value::as_str) {
+        Some("all") => RuleType::All,
+        Some("whitelist") => RuleType::Whitelist,
+        Some("blacklist") => RuleType::Blacklist,
+        Some(_) => return Err(Error::MalformedPolygonRule(ErrorReason::InvalidValue)),
+        None => return Err(Error::MalformedPolygonRule(ErrorReason::Missing)),
+    };
+
+    let mut values = Vec::new();
+    if let Some(list) = entry.get("values") {
+        let list = try!(
+            list.as_array()
+                .ok_or(Error::MalformedPolygonRule(ErrorReason::InvalidValue))
+        );
+        for val in list {
+            let val = try!(
+                val.as_str()
+                    .ok_or(Error::MalformedPolygonRule(ErrorReason::InvalidValue))
+            );
+            values.push(val.to_string());
+        }
     }
 
-    way.tags.iter().any(|tag| {
-        RULES.iter().any(|rule| {
-            rule.key == tag.key && tag.val != "no" && has_matching_rule_value(rule, tag)
-        })
+    Ok(PolygonRule {
+        key: key.to_string(),
+        polygon: polygon,
+        values: values,
     })
 }
 
-fn is_closed_loop(way: &Way) -> bool {
-    let first = way.nodes.first();
-    let last = way.nodes.last();
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum GeometryType {
+    Point,
+    Line,
+    Area,
+}
+
+/// Nodes are always points, tags don't change that.
+pub fn classify_node(_node: &Node) -> GeometryType {
+    GeometryType::Point
+}
+
+/// Geometry type of any element, `None` for relations without geometry of their own.
+pub fn classify(element: ElementRef<'_>) -> Option<GeometryType> {
+    match element {
+        ElementRef::Node(node) => Some(classify_node(node)),
+        ElementRef::Way(way) => Some(classify_way(way)),
+        ElementRef::Relation(relation) => classify_relation(relation),
+    }
+}
+
+pub fn is_polygon(way: &Way) -> bool {
+    classify_way(way) == GeometryType::Area
+}
+
+pub fn is_polygon_with(way: &Way, rules: &PolygonRules) -> bool {
+    classify_way_with(way, rules) == GeometryType::Area
+}
+
+/// Classifies way following osm-polygon-features: closed way is an area only if explicitly
+/// tagged `area=yes` or if it has a tag matching the polygon rules. `area=no` always makes the
+/// way a line.
+pub fn classify_way(way: &Way) -> GeometryType {
+    classify_way_with(way, default_rules())
+}
 
-    if let None = first.and(last) {
+pub fn classify_way_with(way: &Way, rules: &PolygonRules) -> GeometryType {
+    if !is_closed_loop(way) {
+        return GeometryType::Line;
+    }
+
+    match way.tags.get("area") {
+        Some("no") => return GeometryType::Line,
+        Some("yes") => return GeometryType::Area,
+        _ => (),
+    }
+
+    if way.tags.iter().any(|tag| rules.matches(tag)) {
+        GeometryType::Area
+    } else {
+        GeometryType::Line
+    }
+}
+
+/// Multipolygon and boundary relations are areas, other relations have no geometry of their
+/// own.
+pub fn classify_relation(relation: &Relation) -> Option<GeometryType> {
+    match relation.tags.get("type") {
+        Some("multipolygon") | Some("boundary") => Some(GeometryType::Area),
+        _ => None,
+    }
+}
+
+fn is_closed_loop(way: &Way) -> bool {
+    // Closing a ring needs at least three distinct nodes and the repeated first one
+    if way.nodes.len() < 4 {
         return false;
     }
 
-    first.unwrap() == last.unwrap()
+    way.nodes.first() == way.nodes.last()
 }
 
-fn has_matching_rule_value(rule: &Rule, tag: &Tag) -> bool {
+fn has_matching_rule_value(rule: &PolygonRule, tag: &Tag) -> bool {
     match rule.polygon {
         RuleType::All => true,
-        RuleType::Whitelist => rule.values.iter().any(|val| *val != "" && *val == tag.val),
-        RuleType::Blacklist => !rule.values.iter().any(|val| *val == tag.val),
+        RuleType::Whitelist => rule.values.iter().any(|value| *value == tag.val),
+        RuleType::Blacklist => {
+            !tag.val.is_empty() && !rule.values.iter().any(|value| *value == tag.val)
+        }
     }
 }
 
@@ -182,24 +367,48 @@ fn has_matching_rule_value(rule: &Rule, tag: &Tag) -> bool {
 #[cfg(test)]
 mod test {
     use super::*;
-    use elements::{Way, Tag, UnresolvedReference};
+    use elements::{ElementRef, Geometry, Metadata, Node, Relation, Way, Tag,
+                   UnresolvedReference};
+    use symbols::Str;
+    use tags::Tags;
+
+    fn closed_ring() -> Vec<UnresolvedReference> {
+        vec![
+            UnresolvedReference::Node(1),
+            UnresolvedReference::Node(2),
+            UnresolvedReference::Node(3),
+            UnresolvedReference::Node(1),
+        ]
+    }
+
+    fn closed_way(tags: &[(&str, &str)]) -> Way {
+        Way {
+            id: 1234567,
+            tags: tags.iter().map(|&(k, v)| Tag::new(k, v)).collect(),
+            nodes: closed_ring(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
+        }
+    }
 
     #[test]
     fn tagless_and_nonloop_is_not_polygon() {
         let way = Way {
             id: 1234567,
-            tags: Vec::new(),
+            tags: Tags::new(),
             nodes: Vec::new(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
         assert!(!is_polygon(&way));
     }
 
     #[test]
-    fn closed_loop_is_polygon() {
+    fn closed_loop_without_area_tags_is_not_polygon() {
         let way = Way {
             id: 1234567,
-            tags: Vec::new(),
+            tags: Tags::new(),
             nodes: vec![
                 UnresolvedReference::Node(1),
                 UnresolvedReference::Node(2),
@@ -207,9 +416,11 @@ mod test {
                 UnresolvedReference::Node(26),
                 UnresolvedReference::Node(1),
                 ],
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
-        assert!(is_polygon(&way));
+        assert!(!is_polygon(&way));
     }
 
     #[test]
@@ -217,10 +428,12 @@ mod test {
         let way = Way {
             id: 1234567,
             tags: vec![Tag {
-                           key: String::from("building"),
-                           val: String::from("this_is_not_valid"),
-                       }],
-            nodes: Vec::new(),
+                           key: Str::from("building"),
+                           val: Str::from("this_is_not_valid"),
+                       }].into(),
+            nodes: closed_ring(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
         assert!(is_polygon(&way));
@@ -231,10 +444,12 @@ mod test {
         let way = Way {
             id: 1234567,
             tags: vec![Tag {
-                           key: String::from("building"),
-                           val: String::from(""),
-                       }],
-            nodes: Vec::new(),
+                           key: Str::from("building"),
+                           val: Str::from(""),
+                       }].into(),
+            nodes: closed_ring(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
         assert!(is_polygon(&way));
@@ -245,10 +460,12 @@ mod test {
         let way = Way {
             id: 1234567,
             tags: vec![Tag {
-                           key: String::from("highway"),
-                           val: String::from("escape"),
-                       }],
-            nodes: Vec::new(),
+                           key: Str::from("highway"),
+                           val: Str::from("escape"),
+                       }].into(),
+            nodes: closed_ring(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
         assert!(is_polygon(&way));
@@ -259,10 +476,12 @@ mod test {
         let way = Way {
             id: 1234567,
             tags: vec![Tag {
-                           key: String::from("highway"),
-                           val: String::from("footway"),
-                       }],
-            nodes: Vec::new(),
+                           key: Str::from("highway"),
+                           val: Str::from("footway"),
+                       }].into(),
+            nodes: closed_ring(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
         assert!(!is_polygon(&way));
@@ -273,10 +492,12 @@ mod test {
         let way = Way {
             id: 1234567,
             tags: vec![Tag {
-                           key: String::from("highway"),
-                           val: String::from(""),
-                       }],
-            nodes: Vec::new(),
+                           key: Str::from("highway"),
+                           val: Str::from(""),
+                       }].into(),
+            nodes: closed_ring(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
         assert!(!is_polygon(&way));
@@ -287,31 +508,35 @@ mod test {
         let way = Way {
             id: 1234567,
             tags: vec![
-                Tag { key: String::from("highway"), val: String::from("footway") },
-                Tag { key: String::from("highway"), val: String::from("escape") },
-                ],
-            nodes: Vec::new(),
+                Tag { key: Str::from("highway"), val: Str::from("footway") },
+                Tag { key: Str::from("waterway"), val: Str::from("dam") },
+                ].into(),
+            nodes: closed_ring(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
         assert!(is_polygon(&way));
     }
 
     #[test]
-    fn nonloop_and_whitelist_match_is_polygon() {
+    fn nonloop_and_whitelist_match_is_not_polygon() {
         let way = Way {
             id: 1234567,
             tags: vec![Tag {
-                           key: String::from("highway"),
-                           val: String::from("escape"),
-                       }],
+                           key: Str::from("highway"),
+                           val: Str::from("escape"),
+                       }].into(),
             nodes: vec![
                 UnresolvedReference::Node(1),
                 UnresolvedReference::Node(2),
                 UnresolvedReference::Node(3),
                 ],
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
-        assert!(is_polygon(&way));
+        assert!(!is_polygon(&way));
     }
 
     #[test]
@@ -319,10 +544,12 @@ mod test {
         let way = Way {
             id: 1234567,
             tags: vec![Tag {
-                           key: String::from("natural"),
-                           val: String::from("cliff"),
-                       }],
-            nodes: Vec::new(),
+                           key: Str::from("natural"),
+                           val: Str::from("cliff"),
+                       }].into(),
+            nodes: closed_ring(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
         assert!(!is_polygon(&way));
@@ -333,10 +560,12 @@ mod test {
         let way = Way {
             id: 1234567,
             tags: vec![Tag {
-                           key: String::from("natural"),
-                           val: String::from("tree"),
-                       }],
-            nodes: Vec::new(),
+                           key: Str::from("natural"),
+                           val: Str::from("tree"),
+                       }].into(),
+            nodes: closed_ring(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
         assert!(is_polygon(&way));
@@ -347,10 +576,12 @@ mod test {
         let way = Way {
             id: 1234567,
             tags: vec![Tag {
-                           key: String::from("natural"),
-                           val: String::from(""),
-                       }],
-            nodes: Vec::new(),
+                           key: Str::from("natural"),
+                           val: Str::from(""),
+                       }].into(),
+            nodes: closed_ring(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
         assert!(!is_polygon(&way));
@@ -361,31 +592,35 @@ mod test {
         let way = Way {
             id: 1234567,
             tags: vec![
-                Tag { key: String::from("natural"), val: String::from("cliff") },
-                Tag { key: String::from("natural"), val: String::from("tree") },
-                ],
-            nodes: Vec::new(),
+                Tag { key: Str::from("natural"), val: Str::from("cliff") },
+                Tag { key: Str::from("man_made"), val: Str::from("tower") },
+                ].into(),
+            nodes: closed_ring(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
         assert!(is_polygon(&way));
     }
 
     #[test]
-    fn nonloop_and_blacklist_cleared_is_polygon() {
+    fn nonloop_and_blacklist_cleared_is_not_polygon() {
         let way = Way {
             id: 1234567,
             tags: vec![Tag {
-                           key: String::from("natural"),
-                           val: String::from("tree"),
-                       }],
+                           key: Str::from("natural"),
+                           val: Str::from("tree"),
+                       }].into(),
             nodes: vec![
                 UnresolvedReference::Node(1),
                 UnresolvedReference::Node(2),
                 UnresolvedReference::Node(3),
                 ],
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
         };
 
-        assert!(is_polygon(&way));
+        assert!(!is_polygon(&way));
     }
 
 
@@ -423,8 +658,10 @@ mod test {
         let ways = keys.iter().map(|key| {
             return Way {
                 id: 1234567,
-                tags: vec![ Tag { key: String::from(*key), val: String::from("no") }, ],
-                nodes: Vec::new(),
+                tags: vec![ Tag { key: Str::from(*key), val: Str::from("no") }, ].into(),
+                nodes: closed_ring(),
+                meta: Metadata::default(),
+                geometry: Geometry::default(),
             };
         });
 
@@ -432,4 +669,139 @@ mod test {
             assert!(!is_polygon(&way));
         }
     }
+
+    #[test]
+    fn closed_linear_highways_are_not_polygons() {
+        assert!(!is_polygon(&closed_way(&[("highway", "footway")])));
+        assert!(!is_polygon(&closed_way(&[("highway", "primary"), ("junction", "roundabout")])));
+    }
+
+    #[test]
+    fn area_no_overrides_area_tags() {
+        let way = closed_way(&[("building", "yes"), ("area", "no")]);
+        assert_eq!(classify_way(&way), GeometryType::Line);
+    }
+
+    #[test]
+    fn area_yes_makes_closed_linear_way_polygon() {
+        let way = closed_way(&[("highway", "pedestrian"), ("area", "yes")]);
+        assert_eq!(classify_way(&way), GeometryType::Area);
+    }
+
+    #[test]
+    fn area_yes_on_open_way_is_not_polygon() {
+        let mut way = closed_way(&[("highway", "pedestrian"), ("area", "yes")]);
+        way.nodes.pop();
+        assert_eq!(classify_way(&way), GeometryType::Line);
+    }
+
+    #[test]
+    fn degenerate_ring_is_not_polygon() {
+        let mut way = closed_way(&[("building", "yes")]);
+        way.nodes = vec![UnresolvedReference::Node(1), UnresolvedReference::Node(1)];
+        assert!(!is_polygon(&way));
+    }
+
+    #[test]
+    fn multipolygon_and_boundary_relations_are_areas() {
+        let mut relation = Relation {
+            id: 1,
+            members: Vec::new(),
+            tags: vec![Tag::new("type", "multipolygon")].into(),
+            meta: Metadata::default(),
+            geometry: Geometry::default(),
+        };
+        assert_eq!(classify_relation(&relation), Some(GeometryType::Area));
+
+        relation.tags.insert("type", "boundary");
+        assert_eq!(classify_relation(&relation), Some(GeometryType::Area));
+
+        relation.tags.insert("type", "route");
+        assert_eq!(classify_relation(&relation), None);
+        assert_eq!(classify(ElementRef::Relation(&relation)), None);
+    }
+
+    #[test]
+    fn nodes_are_points() {
+        let node = Node {
+            id: 1,
+            lat: 0.0,
+            lon: 0.0,
+            tags: vec![Tag::new("building", "yes")].into(),
+            meta: Metadata::default(),
+        };
+        assert_eq!(classify_node(&node), GeometryType::Point);
+        assert_eq!(classify(ElementRef::Node(&node)), Some(GeometryType::Point));
+        assert_eq!(
+            classify(ElementRef::Way(&closed_way(&[("building", "yes")]))),
+            Some(GeometryType::Area)
+        );
+    }
+
+    #[test]
+    fn default_rules_match_builtin_table() {
+        let rules = PolygonRules::default();
+        assert_eq!(rules.iter().count(), 26);
+        assert_eq!(rules.get("barrier").unwrap().values.len(), 6);
+        assert_eq!(rules.get("aeroway").unwrap().polygon, RuleType::Blacklist);
+    }
+
+    #[test]
+    fn custom_rule_is_used() {
+        let way = closed_way(&[("indoor", "room")]);
+        assert!(!way.is_polygon());
+
+        let mut rules = PolygonRules::default();
+        rules.insert(PolygonRule {
+            key: "indoor".to_string(),
+            polygon: RuleType::Blacklist,
+            values: vec!["corridor".to_string()],
+        });
+        assert!(way.is_polygon_with(&rules));
+        assert!(!closed_way(&[("indoor", "corridor")]).is_polygon_with(&rules));
+    }
+
+    #[test]
+    fn removed_rule_is_not_used() {
+        let mut rules = PolygonRules::default();
+        assert!(rules.remove("building").is_some());
+        assert!(!closed_way(&[("building", "yes")]).is_polygon_with(&rules));
+    }
+
+    #[test]
+    fn rules_from_json() {
+        let json = r#"[
+            {"key": "building", "polygon": "all"},
+            {"key": "playground", "polygon": "all"},
+            {"key": "highway", "polygon": "whitelist",
+             "values": ["services", "rest_area", "escape", "elevator", "platform", "corridor",
+                        "pedestrian"]}
+        ]"#;
+        let rules = PolygonRules::from_json(json.as_bytes()).unwrap();
+
+        assert_eq!(rules.get("highway").unwrap().values.len(), 7);
+        assert!(closed_way(&[("playground", "sandpit")]).is_polygon_with(&rules));
+        assert!(closed_way(&[("highway", "pedestrian")]).is_polygon_with(&rules));
+        assert!(!closed_way(&[("landuse", "forest")]).is_polygon_with(&rules));
+    }
+
+    #[test]
+    fn malformed_json_rules() {
+        let missing_key = r#"[{"polygon": "all"}]"#;
+        match PolygonRules::from_json(missing_key.as_bytes()) {
+            Err(Error::MalformedPolygonRule(ErrorReason::Missing)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        let unknown_type = r#"[{"key": "building", "polygon": "sometimes"}]"#;
+        match PolygonRules::from_json(unknown_type.as_bytes()) {
+            Err(Error::MalformedPolygonRule(ErrorReason::InvalidValue)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+
+        match PolygonRules::from_json("{".as_bytes()) {
+            Err(Error::JsonParseError(_)) => (),
+            other => panic!("Unexpected result {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,40 @@
+//! Reads at an offset of a shared file without moving its cursor, so that readers of the same
+//! `File` can't interleave their seeks and reads.
+
+use std::fs::File;
+use std::io::{self, Read};
+
+/// Reader of `file` starting from `offset`.
+pub(crate) struct PositionedReader<'a> {
+    file: &'a File,
+    offset: u64,
+}
+
+impl<'a> PositionedReader<'a> {
+    pub(crate) fn new(file: &'a File, offset: u64) -> PositionedReader<'a> {
+        PositionedReader {
+            file: file,
+            offset: offset,
+        }
+    }
+}
+
+impl<'a> Read for PositionedReader<'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = try!(read_at(self.file, buf, self.offset));
+        self.offset += read as u64;
+        Ok(read)
+    }
+}
+
+#[cfg(unix)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::unix::fs::FileExt;
+    file.read_at(buf, offset)
+}
+
+#[cfg(windows)]
+fn read_at(file: &File, buf: &mut [u8], offset: u64) -> io::Result<usize> {
+    use std::os::windows::fs::FileExt;
+    file.seek_read(buf, offset)
+}
@@ -0,0 +1,894 @@
+use std::borrow::Cow;
+use std::io::prelude::*;
+#[cfg(feature = "fast")]
+use std::io::BufReader;
+use std::str::FromStr;
+
+use xml::reader::{EventReader, XmlEvent};
+use xml::attribute::OwnedAttribute;
+
+use elements::{Area, Bounds, Count, Element, ElementId, ElementKind, Geometry, Id, Location,
+               Member, Metadata, Node, Relation, Tag, UnresolvedReference, Way};
+use error::{Error, ErrorReason};
+#[cfg(feature = "fast")]
+use fast::FastEvents;
+use options::ParseOptions;
+use symbols::{Str, Symbols};
+use tags::Tags;
+use OSM;
+
+/// Streams elements from the document one at a time without collecting them into `OSM`.
+/// `ReaderBuilder` creates readers with other backends and options.
+///
+/// Malformed elements are skipped the same way as in `OSM::parse`, errors are returned only for
+/// broken XML and Overpass API runtime errors, after which the iteration ends.
+///
+/// Overpass API `<area>` and `<count>` results are not elements, they are collected to the
+/// reader instead.
+pub struct ElementReader<R: Read> {
+    events: Backend<R>,
+    state: State,
+}
+
+enum Backend<R: Read> {
+    Xml(Box<XmlEvents<R>>),
+    #[cfg(feature = "fast")]
+    Fast(FastEvents<BufReader<R>>),
+}
+
+struct State {
+    bounds: Option<Bounds>,
+    bounds_read: bool,
+    areas: Vec<Area>,
+    counts: Vec<Count>,
+    context: Context,
+    finished: bool,
+}
+
+struct Context {
+    options: ParseOptions,
+    symbols: Option<Symbols>,
+    duplicate_tags: Vec<(ElementId, Tag)>,
+}
+
+/// Configuration for reading documents, shared by `ElementReader`, `parse_parallel` and
+/// `ElementStream`. The options and interning work with either backend.
+///
+/// ```
+/// use osm_xml::{ParseOptions, ReaderBuilder, Symbols};
+///
+/// let doc = r#"<osm>
+///     <node id="1" lat="0.0" lon="0.0"><tag k="amenity" v="bench"/></node>
+///     <node id="2" lat="0.0" lon="0.0"/>
+///   </osm>"#;
+/// let osm = ReaderBuilder::new()
+///     .options(ParseOptions {
+///         untagged_nodes: false,
+///         ..ParseOptions::default()
+///     })
+///     .symbols(Symbols::new())
+///     .parse(doc.as_bytes())
+///     .unwrap();
+/// assert_eq!(osm.nodes.len(), 1);
+/// assert!(osm.symbols.get("bench").is_some());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ReaderBuilder {
+    options: ParseOptions,
+    symbols: Option<Symbols>,
+    #[cfg(feature = "fast")]
+    fast: bool,
+}
+
+impl ReaderBuilder {
+    /// Default backend keeping all data, same as `ElementReader::new`.
+    pub fn new() -> ReaderBuilder {
+        ReaderBuilder::default()
+    }
+
+    /// Keeps only the data selected by `options`.
+    pub fn options(mut self, options: ParseOptions) -> ReaderBuilder {
+        self.options = options;
+        self
+    }
+
+    /// Interns tag keys, tag values and member roles to `symbols`, so that equal strings share
+    /// one allocation.
+    pub fn symbols(mut self, symbols: Symbols) -> ReaderBuilder {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    /// Uses the `quick-xml` backend, which parses values directly from the read buffer without
+    /// per-element allocations. Produces the same elements as the default backend.
+    #[cfg(feature = "fast")]
+    pub fn fast(mut self) -> ReaderBuilder {
+        self.fast = true;
+        self
+    }
+
+    pub fn build<R: Read>(&self, source: R) -> ElementReader<R> {
+        self.build_with_symbols(source, self.symbols_copy())
+    }
+
+    /// Parses the whole document, interned strings end up in `OSM::symbols`.
+    pub fn parse<R: Read>(&self, source: R) -> Result<OSM, Error> {
+        OSM::read(self.build(source))
+    }
+
+    pub(crate) fn interns(&self) -> bool {
+        self.symbols.is_some()
+    }
+
+    /// Copy of the table readers intern to, `None` when they don't intern.
+    pub(crate) fn symbols_copy(&self) -> Option<Symbols> {
+        self.symbols.clone()
+    }
+
+    /// Reader interning to `symbols` instead of a copy of the configured table.
+    pub(crate) fn build_with_symbols<R: Read>(
+        &self,
+        source: R,
+        symbols: Option<Symbols>,
+    ) -> ElementReader<R> {
+        ElementReader {
+            events: self.backend(source),
+            state: State {
+                bounds: None,
+                bounds_read: false,
+                areas: Vec::new(),
+                counts: Vec::new(),
+                context: Context {
+                    options: self.options.clone(),
+                    symbols: symbols,
+                    duplicate_tags: Vec::new(),
+                },
+                finished: false,
+            },
+        }
+    }
+
+    fn backend<R: Read>(&self, source: R) -> Backend<R> {
+        #[cfg(feature = "fast")]
+        {
+            if self.fast {
+                return Backend::Fast(FastEvents::new(BufReader::new(source)));
+            }
+        }
+
+        Backend::Xml(Box::new(XmlEvents::new(source)))
+    }
+}
+
+impl<R: Read> ElementReader<R> {
+    pub fn new(source: R) -> ElementReader<R> {
+        ReaderBuilder::new().build(source)
+    }
+
+    /// Symbol table of an interning reader.
+    pub fn symbols(&self) -> Option<&Symbols> {
+        self.state.context.symbols.as_ref()
+    }
+
+    pub fn take_symbols(&mut self) -> Option<Symbols> {
+        self.state.context.symbols.take()
+    }
+
+    /// Bounds of the document, available once the `<bounds>` element has been read.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.state.bounds
+    }
+
+    /// Whether a `<bounds>` element has been read, `bounds` is `None` also when it was invalid.
+    pub(crate) fn bounds_read(&self) -> bool {
+        self.state.bounds_read
+    }
+
+    /// Takes the areas read so far. Long running readers can call this periodically to avoid
+    /// collecting all areas in memory.
+    pub fn take_areas(&mut self) -> Vec<Area> {
+        ::std::mem::take(&mut self.state.areas)
+    }
+
+    /// Results of `out count` read so far.
+    pub fn counts(&self) -> &[Count] {
+        &self.state.counts
+    }
+
+    /// Takes the tags dropped so far because the element already had a tag with the same key.
+    /// The first value of a key is kept, the later ones end up here.
+    pub fn take_duplicate_tags(&mut self) -> Vec<(ElementId, Tag)> {
+        ::std::mem::take(&mut self.state.context.duplicate_tags)
+    }
+
+    /// Reads the first node, way or relation, returning it as an error when it's malformed
+    /// instead of skipping to the next one. `None` at the end of the document.
+    pub(crate) fn first_element(&mut self) -> Result<Option<Element>, Error> {
+        match self.events {
+            Backend::Xml(ref mut events) => self.state.first(&mut **events),
+            #[cfg(feature = "fast")]
+            Backend::Fast(ref mut events) => self.state.first(events),
+        }
+    }
+}
+
+impl<R: Read> Iterator for ElementReader<R> {
+    type Item = Result<Element, Error>;
+
+    fn next(&mut self) -> Option<Result<Element, Error>> {
+        match self.events {
+            Backend::Xml(ref mut events) => self.state.next(&mut **events),
+            #[cfg(feature = "fast")]
+            Backend::Fast(ref mut events) => self.state.next(events),
+        }
+    }
+}
+
+impl State {
+    fn next<E: Events>(&mut self, events: &mut E) -> Option<Result<Element, Error>> {
+        while !self.finished {
+            match parse_element_data(events, &mut self.context) {
+                Err(Error::BoundsMissing(_)) => {
+                    self.bounds = None;
+                    self.bounds_read = true;
+                }
+                Err(Error::MalformedTag(_)) |
+                Err(Error::MalformedNode(_)) |
+                Err(Error::MalformedWay(_)) |
+                Err(Error::MalformedRelation(_)) |
+                Err(Error::UnknownElement) => continue,
+                Err(err) => {
+                    self.finished = true;
+                    return Some(Err(err));
+                }
+                Ok(data) => match data {
+                    ElementData::EndOfDocument => self.finished = true,
+                    ElementData::Ignored => continue,
+                    ElementData::Bounds(bounds) => {
+                        self.bounds = Some(bounds);
+                        self.bounds_read = true;
+                    }
+                    ElementData::Area(area) => self.areas.push(area),
+                    ElementData::Count(count) => self.counts.push(count),
+                    ElementData::Node(node) => return Some(Ok(Element::Node(node))),
+                    ElementData::Way(way) => return Some(Ok(Element::Way(way))),
+                    ElementData::Relation(relation) => {
+                        return Some(Ok(Element::Relation(relation)))
+                    }
+                },
+            }
+        }
+
+        None
+    }
+
+    fn first<E: Events>(&mut self, events: &mut E) -> Result<Option<Element>, Error> {
+        loop {
+            match parse_element_data(events, &mut self.context) {
+                // Enclosing `<osm>` and other elements before it
+                Err(Error::UnknownElement) | Err(Error::BoundsMissing(_)) => continue,
+                Err(err) => return Err(err),
+                Ok(ElementData::EndOfDocument) => return Ok(None),
+                Ok(ElementData::Node(node)) => return Ok(Some(Element::Node(node))),
+                Ok(ElementData::Way(way)) => return Ok(Some(Element::Way(way))),
+                Ok(ElementData::Relation(relation)) => {
+                    return Ok(Some(Element::Relation(relation)))
+                }
+                Ok(_) => continue,
+            }
+        }
+    }
+}
+
+/// XML event from one of the reading backends. Element names are classified by the backend,
+/// `None` is an unknown element.
+pub(crate) enum Event<'a, A> {
+    /// Start of an element, the flag is set when the element is known to be empty and its end
+    /// follows right away
+    Start(Option<ElementType>, A, bool),
+    End(Option<ElementType>),
+    Text(Cow<'a, str>),
+    EndDocument,
+    Other,
+}
+
+/// XML reading backend. Events borrow from the backend until the next event is read.
+pub(crate) trait Events {
+    type Attributes<'a>: Attributes
+    where
+        Self: 'a;
+
+    fn next_event(&mut self) -> Result<Event<'_, Self::Attributes<'_>>, Error>;
+}
+
+/// Attributes of a start element.
+pub(crate) trait Attributes {
+    /// Unescaped value of the attribute, borrowed from the input when possible.
+    fn value(&self, name: &str) -> Option<Cow<'_, str>>;
+}
+
+struct XmlEvents<R: Read> {
+    parser: EventReader<R>,
+    /// Event read ahead after a start element to tell whether it was empty
+    pending: Option<XmlEvent>,
+}
+
+impl<R: Read> XmlEvents<R> {
+    fn new(source: R) -> XmlEvents<R> {
+        XmlEvents {
+            parser: EventReader::new(source),
+            pending: None,
+        }
+    }
+}
+
+impl<R: Read> Events for XmlEvents<R> {
+    type Attributes<'a> = Vec<OwnedAttribute> where Self: 'a;
+
+    fn next_event(&mut self) -> Result<Event<'_, Vec<OwnedAttribute>>, Error> {
+        let event = match self.pending.take() {
+            Some(event) => event,
+            None => try!(self.parser.next()),
+        };
+
+        match event {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let next = try!(self.parser.next());
+                let empty = matches!(next, XmlEvent::EndElement { .. });
+                self.pending = Some(next);
+
+                Ok(Event::Start(
+                    ElementType::from_name(name.local_name.as_bytes()),
+                    attributes,
+                    empty,
+                ))
+            }
+            XmlEvent::EndElement { name } => Ok(Event::End(
+                ElementType::from_name(name.local_name.as_bytes()),
+            )),
+            XmlEvent::Characters(text) | XmlEvent::CData(text) => Ok(Event::Text(text.into())),
+            XmlEvent::EndDocument => Ok(Event::EndDocument),
+            _ => Ok(Event::Other),
+        }
+    }
+}
+
+impl Attributes for Vec<OwnedAttribute> {
+    fn value(&self, name: &str) -> Option<Cow<'_, str>> {
+        self.iter()
+            .find(|attr| attr.name.local_name == name)
+            .map(|attr| Cow::Borrowed(attr.value.as_str()))
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub(crate) enum ElementType {
+    Bounds,
+    Node,
+    Way,
+    Relation,
+    Tag,
+    NodeRef,
+    Member,
+    Center,
+    Area,
+    Count,
+    Remark,
+}
+
+enum ElementData {
+    Bounds(Bounds),
+    Node(Node),
+    Way(Way),
+    Relation(Relation),
+    Area(Area),
+    Count(Count),
+    // These two are here so we can terminate and skip uninteresting data without
+    // using error handling.
+    EndOfDocument,
+    Ignored,
+}
+
+/// Data read from the start tag of a top level element, children are read after it.
+enum Start {
+    Bounds(Bounds),
+    Node(Node),
+    Way(Way),
+    Relation(Relation),
+    Area(Option<Id>, Metadata),
+    Count,
+    Remark,
+    /// Element kind not kept by the options
+    Skipped,
+}
+
+// Most common names first
+const ELEMENT_NAMES: [(&str, ElementType); 11] = [
+    ("nd", ElementType::NodeRef),
+    ("tag", ElementType::Tag),
+    ("node", ElementType::Node),
+    ("way", ElementType::Way),
+    ("member", ElementType::Member),
+    ("relation", ElementType::Relation),
+    ("bounds", ElementType::Bounds),
+    ("center", ElementType::Center),
+    ("area", ElementType::Area),
+    ("count", ElementType::Count),
+    ("remark", ElementType::Remark),
+];
+
+impl ElementType {
+    /// Element type from case insensitive element name.
+    pub(crate) fn from_name(name: &[u8]) -> Option<ElementType> {
+        ELEMENT_NAMES
+            .iter()
+            .find(|&&(element_name, _)| element_name.as_bytes().eq_ignore_ascii_case(name))
+            .map(|&(_, element_type)| element_type)
+    }
+}
+
+fn known(element_type: Option<ElementType>) -> Result<ElementType, Error> {
+    element_type.ok_or(Error::UnknownElement)
+}
+
+fn parse_element_data<E: Events>(
+    events: &mut E,
+    context: &mut Context,
+) -> Result<ElementData, Error> {
+    let start = match try!(events.next_event()) {
+        Event::EndDocument => return Ok(ElementData::EndOfDocument),
+        Event::Start(element_type, attributes, empty) => {
+            let element_type = try!(known(element_type));
+            if keeps(&context.options, element_type) {
+                try!(parse_start(element_type, &attributes, empty, &context.options))
+            } else {
+                Start::Skipped
+            }
+        }
+        _ => return Ok(ElementData::Ignored),
+    };
+
+    match start {
+        Start::Bounds(bounds) => Ok(ElementData::Bounds(bounds)),
+        Start::Node(node) => parse_node(events, node, context),
+        Start::Way(way) => parse_way(events, way, context),
+        Start::Relation(relation) => parse_relation(events, relation, context),
+        Start::Area(id, meta) => parse_area(events, id, meta, context),
+        Start::Count => parse_count(events, context),
+        Start::Remark => parse_remark(events),
+        Start::Skipped => skip_element(events),
+    }
+}
+
+fn keeps(options: &ParseOptions, element_type: ElementType) -> bool {
+    match element_type {
+        ElementType::Node => options.keeps(ElementKind::Node),
+        ElementType::Way => options.keeps(ElementKind::Way),
+        ElementType::Relation => options.keeps(ElementKind::Relation),
+        _ => true,
+    }
+}
+
+/// Reads past the element which has been started.
+fn skip_element<E: Events>(events: &mut E) -> Result<ElementData, Error> {
+    let mut depth = 1;
+
+    while depth > 0 {
+        match try!(events.next_event()) {
+            Event::Start(..) => depth += 1,
+            Event::End(_) => depth -= 1,
+            Event::EndDocument => break,
+            _ => continue,
+        }
+    }
+
+    Ok(ElementData::Ignored)
+}
+
+/// Parses the start tag, `empty` is set when the element is known to have no children.
+fn parse_start<A: Attributes>(
+    element_type: ElementType,
+    attrs: &A,
+    empty: bool,
+    options: &ParseOptions,
+) -> Result<Start, Error> {
+    let meta = |attrs: &A| if options.metadata {
+        parse_metadata(attrs)
+    } else {
+        Metadata::default()
+    };
+
+    match element_type {
+        ElementType::Bounds => Ok(Start::Bounds(
+            try!(parse_bounds(attrs).map_err(Error::BoundsMissing)),
+        )),
+        // Node without children has no tags, nothing needs to be parsed to drop it
+        ElementType::Node if empty && !options.untagged_nodes => Ok(Start::Skipped),
+        ElementType::Node => Ok(Start::Node(Node {
+            id: try!(find_attribute("id", attrs).map_err(Error::MalformedNode)),
+            lat: try!(find_attribute("lat", attrs).map_err(Error::MalformedNode)),
+            lon: try!(find_attribute("lon", attrs).map_err(Error::MalformedNode)),
+            tags: Tags::new(),
+            meta: meta(attrs),
+        })),
+        ElementType::Way => Ok(Start::Way(Way {
+            id: try!(find_attribute("id", attrs).map_err(Error::MalformedWay)),
+            nodes: Vec::new(),
+            tags: Tags::new(),
+            meta: meta(attrs),
+            geometry: Geometry::default(),
+        })),
+        ElementType::Relation => Ok(Start::Relation(Relation {
+            id: try!(find_attribute("id", attrs).map_err(Error::MalformedRelation)),
+            members: Vec::new(),
+            tags: Tags::new(),
+            meta: meta(attrs),
+            geometry: Geometry::default(),
+        })),
+        // Areas are only informative, ones without id are skipped after reading them
+        ElementType::Area => Ok(Start::Area(
+            find_attribute("id", attrs).ok(),
+            meta(attrs),
+        )),
+        ElementType::Count => Ok(Start::Count),
+        ElementType::Remark => Ok(Start::Remark),
+        _ => Err(Error::UnknownElement),
+    }
+}
+
+fn parse_relation<E: Events>(
+    events: &mut E,
+    mut relation: Relation,
+    context: &mut Context,
+) -> Result<ElementData, Error> {
+    let mut in_member = false;
+
+    loop {
+        match try!(events.next_event()) {
+            Event::End(element_type) => {
+                match try!(known(element_type)) {
+                    ElementType::Relation => {
+                        let geometry = &mut relation.geometry;
+                        if !has_location(geometry.members.iter().flat_map(|m| m.iter())) {
+                            geometry.members.clear();
+                        }
+
+                        return Ok(ElementData::Relation(relation));
+                    }
+                    ElementType::Member => in_member = false,
+                    _ => continue,
+                }
+            }
+            Event::Start(element_type, attributes, _) => {
+                match try!(known(element_type)) {
+                    ElementType::Tag => if let Some(tag) = parse_tag(&attributes, true, context) {
+                        let id = ElementId::Relation(relation.id);
+                        push_tag(&mut relation.tags, tag, id, context);
+                    },
+                    ElementType::Member => {
+                        let el_type = try!(
+                            find_attribute_uncasted("type", &attributes)
+                                .map_err(Error::MalformedRelation)
+                        );
+                        let el_ref = try!(
+                            find_attribute("ref", &attributes).map_err(Error::MalformedRelation)
+                        );
+                        let el_role = to_str(
+                            &try!(
+                                find_attribute_uncasted("role", &attributes)
+                                    .map_err(Error::MalformedRelation)
+                            ),
+                            context,
+                        );
+
+                        let el = match ElementType::from_name(el_type.as_bytes()) {
+                            Some(ElementType::Node) => {
+                                Member::Node(UnresolvedReference::Node(el_ref), el_role)
+                            }
+                            Some(ElementType::Way) => {
+                                Member::Way(UnresolvedReference::Way(el_ref), el_role)
+                            }
+                            Some(ElementType::Relation) => {
+                                Member::Relation(UnresolvedReference::Relation(el_ref), el_role)
+                            }
+                            _ => return Err(Error::MalformedRelation(ErrorReason::Missing)),
+                        };
+
+                        // Node member location is in the member itself, way member node
+                        // locations follow as nested `<nd>` elements
+                        relation.geometry.members.push(match el {
+                            Member::Node(..) => vec![parse_location(&attributes)],
+                            _ => Vec::new(),
+                        });
+                        relation.members.push(el);
+                        in_member = true;
+                    }
+                    // Way member geometry from `out geom`
+                    ElementType::NodeRef if in_member => {
+                        if let Some(locations) = relation.geometry.members.last_mut() {
+                            locations.push(parse_location(&attributes));
+                        }
+                    }
+                    ElementType::Bounds => {
+                        relation.geometry.bounds = Some(try!(
+                            parse_bounds(&attributes).map_err(Error::MalformedRelation)
+                        ));
+                    }
+                    ElementType::Center => {
+                        relation.geometry.center = parse_location(&attributes)
+                    }
+                    ElementType::Node |
+                    ElementType::Relation |
+                    ElementType::Way |
+                    ElementType::NodeRef |
+                    ElementType::Area |
+                    ElementType::Count |
+                    ElementType::Remark => {
+                        return Err(Error::MalformedRelation(ErrorReason::IllegalNesting))
+                    }
+                }
+            }
+            _ => continue,
+        }
+    }
+}
+
+fn parse_way<E: Events>(
+    events: &mut E,
+    mut way: Way,
+    context: &mut Context,
+) -> Result<ElementData, Error> {
+    loop {
+        match try!(events.next_event()) {
+            Event::End(element_type) => {
+                match try!(known(element_type)) {
+                    ElementType::Way => {
+                        if !has_location(way.geometry.nodes.iter()) {
+                            way.geometry.nodes.clear();
+                        }
+
+                        return Ok(ElementData::Way(way));
+                    }
+                    _ => continue,
+                }
+            }
+            Event::Start(element_type, attributes, _) => {
+                match try!(known(element_type)) {
+                    ElementType::Tag => if let Some(tag) = parse_tag(&attributes, true, context) {
+                        let id = ElementId::Way(way.id);
+                        push_tag(&mut way.tags, tag, id, context);
+                    },
+                    ElementType::NodeRef => {
+                        let node_ref =
+                            try!(find_attribute("ref", &attributes).map_err(Error::MalformedWay));
+                        way.nodes.push(UnresolvedReference::Node(node_ref));
+                        way.geometry.nodes.push(parse_location(&attributes));
+                    }
+                    ElementType::Bounds => {
+                        way.geometry.bounds =
+                            Some(try!(parse_bounds(&attributes).map_err(Error::MalformedWay)));
+                    }
+                    ElementType::Center => way.geometry.center = parse_location(&attributes),
+                    ElementType::Node |
+                    ElementType::Relation |
+                    ElementType::Way |
+                    ElementType::Member |
+                    ElementType::Area |
+                    ElementType::Count |
+                    ElementType::Remark => {
+                        return Err(Error::MalformedWay(ErrorReason::IllegalNesting))
+                    }
+                }
+            }
+            _ => continue,
+        }
+    }
+}
+
+fn parse_node<E: Events>(
+    events: &mut E,
+    mut node: Node,
+    context: &mut Context,
+) -> Result<ElementData, Error> {
+    loop {
+        match try!(events.next_event()) {
+            Event::End(element_type) => {
+                match try!(known(element_type)) {
+                    ElementType::Node => {
+                        if node.tags.is_empty() && !context.options.untagged_nodes {
+                            return Ok(ElementData::Ignored);
+                        }
+                        return Ok(ElementData::Node(node));
+                    }
+                    _ => continue,
+                }
+            }
+            Event::Start(element_type, attributes, _) => {
+                match try!(known(element_type)) {
+                    ElementType::Tag => if let Some(tag) = parse_tag(&attributes, true, context) {
+                        let id = ElementId::Node(node.id);
+                        push_tag(&mut node.tags, tag, id, context);
+                    },
+                    ElementType::Bounds |
+                    ElementType::Node |
+                    ElementType::Relation |
+                    ElementType::Way |
+                    ElementType::NodeRef |
+                    ElementType::Member |
+                    ElementType::Center |
+                    ElementType::Area |
+                    ElementType::Count |
+                    ElementType::Remark => {
+                        return Err(Error::MalformedNode(ErrorReason::IllegalNesting))
+                    }
+                }
+            }
+            _ => continue,
+        }
+    }
+}
+
+fn parse_area<E: Events>(
+    events: &mut E,
+    id: Option<Id>,
+    meta: Metadata,
+    context: &mut Context,
+) -> Result<ElementData, Error> {
+    let tags = try!(parse_child_tags(events, ElementType::Area, context));
+
+    match id {
+        Some(id) => Ok(ElementData::Area(Area {
+            id: id,
+            tags: tags,
+            meta: meta,
+        })),
+        None => Ok(ElementData::Ignored),
+    }
+}
+
+fn parse_count<E: Events>(
+    events: &mut E,
+    context: &mut Context,
+) -> Result<ElementData, Error> {
+    let tags = try!(parse_child_tags(events, ElementType::Count, context));
+    let count = |key| tags.get(key).and_then(|val| val.parse().ok()).unwrap_or(0);
+
+    Ok(ElementData::Count(Count {
+        nodes: count("nodes"),
+        ways: count("ways"),
+        relations: count("relations"),
+        areas: count("areas"),
+        total: count("total"),
+    }))
+}
+
+/// Overpass API reports errors that happen after the response has been started as remarks at
+/// the end of the document, eg. `<remark> runtime error: Query timed out ... </remark>`.
+fn parse_remark<E: Events>(events: &mut E) -> Result<ElementData, Error> {
+    let mut text = String::new();
+
+    loop {
+        match try!(events.next_event()) {
+            Event::Text(chars) => text.push_str(&chars),
+            Event::End(_) | Event::EndDocument => break,
+            _ => continue,
+        }
+    }
+
+    let text = text.trim();
+    if text.starts_with("runtime error") {
+        Err(Error::RuntimeError(text.to_string()))
+    } else {
+        Ok(ElementData::Ignored)
+    }
+}
+
+/// Reads tags until the end of the `end` element, other nested elements are skipped.
+fn parse_child_tags<E: Events>(
+    events: &mut E,
+    end: ElementType,
+    context: &mut Context,
+) -> Result<Tags, Error> {
+    let mut tags = Tags::new();
+
+    loop {
+        match try!(events.next_event()) {
+            Event::End(element_type) => if element_type == Some(end) {
+                return Ok(tags);
+            },
+            Event::Start(Some(ElementType::Tag), attributes, _) => {
+                if let Some(tag) = parse_tag(&attributes, false, context) {
+                    // Areas and counts are not elements, their duplicate tags are not recorded
+                    tags.push(tag).ok();
+                }
+            }
+            Event::EndDocument => return Ok(tags),
+            _ => continue,
+        }
+    }
+}
+
+/// Appends the tag, duplicate keys are recorded to the context and the first value is kept.
+fn push_tag(tags: &mut Tags, tag: Tag, id: ElementId, context: &mut Context) {
+    if tags.contains(&tag.key) {
+        context.duplicate_tags.push((id, tag));
+    } else {
+        tags.push(tag).ok();
+    }
+}
+
+/// Valid tag, skipped when `filter` is set and the tag key is not kept by the options.
+fn parse_tag<A: Attributes>(attributes: &A, filter: bool, context: &mut Context) -> Option<Tag> {
+    let key = find_attribute_uncasted("k", attributes).ok()?;
+    if filter && !context.options.keeps_key(&key) {
+        return None;
+    }
+    let val = find_attribute_uncasted("v", attributes).ok()?;
+
+    Some(Tag {
+        key: to_str(&key, context),
+        val: to_str(&val, context),
+    })
+}
+
+fn to_str(s: &str, context: &mut Context) -> Str {
+    match context.symbols {
+        Some(ref mut symbols) => symbols.intern(s),
+        None => Str::from(s),
+    }
+}
+
+fn parse_metadata<A: Attributes>(attrs: &A) -> Metadata {
+    Metadata {
+        version: find_attribute("version", attrs).ok(),
+        timestamp: find_attribute_uncasted("timestamp", attrs)
+            .ok()
+            .map(Cow::into_owned),
+        changeset: find_attribute("changeset", attrs).ok(),
+        uid: find_attribute("uid", attrs).ok(),
+        user: find_attribute_uncasted("user", attrs).ok().map(Cow::into_owned),
+        visible: find_attribute_uncasted("visible", attrs)
+            .ok()
+            .and_then(|visible| visible.parse().ok()),
+    }
+}
+
+fn parse_bounds<A: Attributes>(attrs: &A) -> Result<Bounds, ErrorReason> {
+    Ok(Bounds {
+        minlat: try!(find_attribute("minlat", attrs)),
+        minlon: try!(find_attribute("minlon", attrs)),
+        maxlat: try!(find_attribute("maxlat", attrs)),
+        maxlon: try!(find_attribute("maxlon", attrs)),
+    })
+}
+
+fn parse_location<A: Attributes>(attrs: &A) -> Option<Location> {
+    let lat = find_attribute("lat", attrs).ok()?;
+    let lon = find_attribute("lon", attrs).ok()?;
+
+    Some(Location { lat: lat, lon: lon })
+}
+
+fn has_location<'a, I: Iterator<Item = &'a Option<Location>>>(mut locations: I) -> bool {
+    locations.any(|location| location.is_some())
+}
+
+fn find_attribute<T, A>(name: &str, attrs: &A) -> Result<T, ErrorReason>
+where
+    ErrorReason: From<<T as FromStr>::Err>,
+    T: FromStr,
+    A: Attributes,
+{
+    let val_raw = try!(find_attribute_uncasted(name, attrs));
+    let val = try!(val_raw.parse::<T>());
+    Ok(val)
+}
+
+fn find_attribute_uncasted<'a, A: Attributes>(
+    name: &str,
+    attrs: &'a A,
+) -> Result<Cow<'a, str>, ErrorReason> {
+    attrs.value(name).ok_or(ErrorReason::Missing)
+}
@@ -0,0 +1,67 @@
+//! Overpass style recursion over element references.
+//!
+//! Results contain only the elements found by the recursion, not the input elements, and only
+//! elements which exist in the document. The same recursion works over any element store, see
+//! `store::children` and the other functions there.
+
+use std::borrow::Borrow;
+
+use elements::{ElementId, Member};
+use idset::ElementIdSet;
+use store::{self, in_memory};
+use OSM;
+
+impl OSM {
+    /// Overpass `>`: nodes of the ways, node and way members of the relations and the nodes of
+    /// those member ways.
+    pub fn children<I>(&self, ids: I) -> ElementIdSet
+    where
+        I: IntoIterator<Item = ElementId>,
+    {
+        in_memory(store::children(self, ids))
+    }
+
+    /// Overpass `>>`: all members of the relations recursively, including sub-relations, and
+    /// all nodes of the found ways.
+    pub fn descendants<I>(&self, ids: I) -> ElementIdSet
+    where
+        I: IntoIterator<Item = ElementId>,
+    {
+        in_memory(store::descendants(self, ids))
+    }
+
+    /// Overpass `<`: ways which contain any of the nodes and relations which have any of the
+    /// nodes or ways as members.
+    pub fn parents<I>(&self, ids: I) -> ElementIdSet
+    where
+        I: IntoIterator<Item = ElementId>,
+    {
+        in_memory(store::parents(self, ids))
+    }
+
+    /// Overpass `<<`: all ways and relations which refer to any of the elements, directly or
+    /// through other relations.
+    pub fn ancestors<I>(&self, ids: I) -> ElementIdSet
+    where
+        I: IntoIterator<Item = ElementId>,
+    {
+        in_memory(store::ancestors(self, ids))
+    }
+
+    /// Copies the given elements to a new document. Ids which are not found are ignored.
+    pub fn subset<I>(&self, ids: I) -> OSM
+    where
+        I: IntoIterator,
+        I::Item: Borrow<ElementId>,
+    {
+        let mut osm = in_memory(store::extract(self, ids));
+        osm.bounds = self.bounds;
+        osm
+    }
+}
+
+pub(crate) fn member_id(member: &Member) -> ElementId {
+    match *member {
+        Member::Node(r, _) | Member::Way(r, _) | Member::Relation(r, _) => ElementId::from(r),
+    }
+}
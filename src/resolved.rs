@@ -0,0 +1,219 @@
+use std::borrow::Cow;
+
+use fnv::{FnvHashMap, FnvHashSet};
+
+use elements::{ElementId, ElementRef, Id, Member, Node, Relation, Role, UnresolvedReference,
+               Way};
+use error::Error;
+use store::{self, ElementStore, StoredReference};
+use OSM;
+
+/// Connected view of the document where all references are resolved up front. Built once with
+/// `OSM::resolve` or `ResolvedOSM::new` for other stores. Elements are borrowed from in-memory
+/// stores and copied from others.
+#[derive(Debug)]
+pub struct ResolvedOSM<'a, S: ElementStore + ?Sized + 'a = OSM> {
+    store: &'a S,
+    ways: FnvHashMap<Id, ResolvedWay<'a>>,
+    relations: FnvHashMap<Id, ResolvedRelation<'a>>,
+    missing: Vec<MissingReference>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedWay<'a> {
+    pub way: Cow<'a, Way>,
+    /// Nodes of the way in order. Nodes missing from the document are left out and listed in
+    /// `ResolvedOSM::missing`.
+    pub nodes: Vec<Cow<'a, Node>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedRelation<'a> {
+    pub relation: Cow<'a, Relation>,
+    pub members: Vec<ResolvedMember<'a>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ResolvedMember<'a> {
+    pub reference: StoredReference<'a>,
+    pub role: Role,
+}
+
+/// Reference from `parent` which points to element not found in the document.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct MissingReference {
+    pub parent: ElementId,
+    pub reference: UnresolvedReference,
+}
+
+impl<'a, S: ElementStore + ?Sized> ResolvedOSM<'a, S> {
+    /// Resolves the references of all ways and relations in the store.
+    pub fn new(store: &'a S) -> Result<ResolvedOSM<'a, S>, Error> {
+        let mut missing = Vec::new();
+
+        let mut ways = FnvHashMap::default();
+        for way in store.ways() {
+            let way = try!(way);
+            let mut nodes = Vec::with_capacity(way.nodes.len());
+            for node_ref in &way.nodes {
+                match try!(store::resolve_reference(store, node_ref)) {
+                    StoredReference::Node(node) => nodes.push(node),
+                    _ => missing.push(MissingReference {
+                        parent: ElementId::Way(way.id),
+                        reference: *node_ref,
+                    }),
+                }
+            }
+
+            ways.insert(way.id, ResolvedWay { way: way, nodes: nodes });
+        }
+
+        let mut relations = FnvHashMap::default();
+        for relation in store.relations() {
+            let relation = try!(relation);
+            let mut members = Vec::with_capacity(relation.members.len());
+            for member in &relation.members {
+                let (member_ref, role) = match *member {
+                    Member::Node(ref r, ref role) |
+                    Member::Way(ref r, ref role) |
+                    Member::Relation(ref r, ref role) => (r, role),
+                };
+                let reference = try!(store::resolve_reference(store, member_ref));
+                if let StoredReference::Unresolved = reference {
+                    missing.push(MissingReference {
+                        parent: ElementId::Relation(relation.id),
+                        reference: *member_ref,
+                    });
+                }
+
+                members.push(ResolvedMember {
+                    reference: reference,
+                    role: role.clone(),
+                });
+            }
+
+            relations.insert(
+                relation.id,
+                ResolvedRelation {
+                    relation: relation,
+                    members: members,
+                },
+            );
+        }
+
+        Ok(ResolvedOSM {
+            store: store,
+            ways: ways,
+            relations: relations,
+            missing: missing,
+        })
+    }
+
+    pub fn store(&self) -> &'a S {
+        self.store
+    }
+
+    pub fn node(&self, id: Id) -> Result<Option<Cow<'a, Node>>, Error> {
+        self.store.node(id)
+    }
+
+    pub fn way(&self, id: Id) -> Option<&ResolvedWay<'a>> {
+        self.ways.get(&id)
+    }
+
+    pub fn relation(&self, id: Id) -> Option<&ResolvedRelation<'a>> {
+        self.relations.get(&id)
+    }
+
+    pub fn ways(&self) -> impl Iterator<Item = &ResolvedWay<'a>> {
+        self.ways.values()
+    }
+
+    pub fn relations(&self) -> impl Iterator<Item = &ResolvedRelation<'a>> {
+        self.relations.values()
+    }
+
+    /// All references which could not be resolved, ie. the document is incomplete if this is not
+    /// empty.
+    pub fn missing(&self) -> &[MissingReference] {
+        &self.missing
+    }
+
+    /// Iterates depth-first over all members of the relation and members of its sub-relations.
+    /// Each relation is descended into only once, so cyclic memberships terminate. Unresolved
+    /// members are skipped.
+    pub fn recursive_members<'r>(&'r self, id: Id) -> RecursiveMembers<'r, 'a> {
+        let mut visited = FnvHashSet::default();
+        visited.insert(id);
+
+        RecursiveMembers {
+            relations: &self.relations,
+            stack: self.relations
+                .get(&id)
+                .map(|relation| vec![relation.members.iter()])
+                .unwrap_or_default(),
+            visited: visited,
+        }
+    }
+
+    /// Whether the relation contains itself through its sub-relations.
+    pub fn is_cyclic(&self, id: Id) -> bool {
+        self.recursive_members(id).any(|member| match member {
+            ElementRef::Relation(relation) => relation.id == id,
+            _ => false,
+        })
+    }
+}
+
+impl<'a> ResolvedOSM<'a, OSM> {
+    pub fn osm(&self) -> &'a OSM {
+        self.store
+    }
+}
+
+pub struct RecursiveMembers<'r, 'a: 'r> {
+    relations: &'r FnvHashMap<Id, ResolvedRelation<'a>>,
+    stack: Vec<::std::slice::Iter<'r, ResolvedMember<'a>>>,
+    visited: FnvHashSet<Id>,
+}
+
+impl<'r, 'a> Iterator for RecursiveMembers<'r, 'a> {
+    type Item = ElementRef<'r>;
+
+    fn next(&mut self) -> Option<ElementRef<'r>> {
+        loop {
+            let member = match self.stack.last_mut() {
+                Some(members) => members.next(),
+                None => return None,
+            };
+
+            let member = match member {
+                Some(member) => member,
+                None => {
+                    self.stack.pop();
+                    continue;
+                }
+            };
+
+            match member.reference {
+                StoredReference::Node(ref node) => return Some(ElementRef::Node(node)),
+                StoredReference::Way(ref way) => return Some(ElementRef::Way(way)),
+                StoredReference::Relation(ref relation) => {
+                    if self.visited.insert(relation.id) {
+                        if let Some(resolved) = self.relations.get(&relation.id) {
+                            self.stack.push(resolved.members.iter());
+                        }
+                    }
+                    return Some(ElementRef::Relation(relation));
+                }
+                StoredReference::Unresolved => continue,
+            }
+        }
+    }
+}
+
+impl OSM {
+    pub fn resolve(&self) -> ResolvedOSM<'_> {
+        store::in_memory(ResolvedOSM::new(self))
+    }
+}
@@ -0,0 +1,887 @@
+//! Binary snapshot of a parsed document, memory mapped when opened so that loading takes no
+//! parsing and elements are read from the file only when accessed.
+//!
+//! ```no_run
+//! use osm_xml::OSM;
+//! use osm_xml::snapshot::Snapshot;
+//!
+//! let osm = OSM::parse(std::fs::File::open("extract.osm").unwrap()).unwrap();
+//! osm.save_snapshot("extract.snapshot").unwrap();
+//!
+//! let snapshot = Snapshot::open("extract.snapshot").unwrap();
+//! let name = snapshot.way(4253174).and_then(|way| way.tags().get("name"));
+//! ```
+//!
+//! Layout, all values little endian:
+//!
+//! - header: magic, format version, section count, FNV-1a checksum of the data after the header,
+//!   bounds, and offset and length of each section
+//! - node, way and relation tables of fixed size records sorted by id, looked up with binary
+//!   search
+//! - metadata tables with one fixed size record per element, in the same order
+//! - way node, member and tag tables which the element records refer to by index and length
+//! - string table with all tag keys, tag values, roles, timestamps and user names once
+//!
+//! Overpass geometry, areas and counts are not stored.
+
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::str;
+
+use fnv::{FnvHashMap, FnvHasher};
+use memmap2::Mmap;
+
+use elements::{Bounds, Element, ElementId, Id, Location, Member, Metadata, Node, Relation, Role,
+               Tag, UnresolvedReference, Way};
+use error::{Error, ErrorReason};
+use tags::Tags;
+use OSM;
+
+const MAGIC: &[u8; 8] = b"OSMSNAP\0";
+const VERSION: u32 = 1;
+
+const NODES: usize = 0;
+const NODE_META: usize = 1;
+const WAYS: usize = 2;
+const WAY_META: usize = 3;
+const WAY_NODES: usize = 4;
+const RELATIONS: usize = 5;
+const RELATION_META: usize = 6;
+const MEMBERS: usize = 7;
+const TAGS: usize = 8;
+const STRING_OFFSETS: usize = 9;
+const STRING_DATA: usize = 10;
+const SECTIONS: usize = 11;
+
+// Magic, version and section count, checksum, bounds and the section table
+const HEADER_LEN: usize = 16 + 8 + 40 + SECTIONS * 16;
+
+/// Record size of each section, string data is a plain byte array.
+const RECORD_SIZES: [usize; SECTIONS] = [
+    40, META_SIZE, 32, META_SIZE, 8, 32, META_SIZE, 16, 8, 8, 1
+];
+const META_SIZE: usize = 32;
+
+const HAS_VERSION: u32 = 1;
+const HAS_TIMESTAMP: u32 = 1 << 1;
+const HAS_CHANGESET: u32 = 1 << 2;
+const HAS_UID: u32 = 1 << 3;
+const HAS_USER: u32 = 1 << 4;
+const HAS_VISIBLE: u32 = 1 << 5;
+const VISIBLE: u32 = 1 << 6;
+
+impl OSM {
+    /// Writes the document as a snapshot which can be opened with `Snapshot::open` or read back
+    /// with `OSM::load_snapshot`.
+    pub fn save_snapshot<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut nodes: Vec<&Node> = self.nodes.values().collect();
+        nodes.sort_by_key(|node| node.id);
+        let mut ways: Vec<&Way> = self.ways.values().collect();
+        ways.sort_by_key(|way| way.id);
+        let mut relations: Vec<&Relation> = self.relations.values().collect();
+        relations.sort_by_key(|relation| relation.id);
+
+        let mut strings = StringTable::default();
+        for node in &nodes {
+            strings.add_tags(&node.tags);
+            strings.add_meta(&node.meta);
+        }
+        for way in &ways {
+            strings.add_tags(&way.tags);
+            strings.add_meta(&way.meta);
+        }
+        for relation in &relations {
+            strings.add_tags(&relation.tags);
+            strings.add_meta(&relation.meta);
+            for member in &relation.members {
+                strings.add(member_parts(member).2);
+            }
+        }
+
+        let tag_count = nodes.iter().map(|node| node.tags.len()).sum::<usize>() +
+            ways.iter().map(|way| way.tags.len()).sum::<usize>() +
+            relations.iter().map(|relation| relation.tags.len()).sum::<usize>();
+        let mut counts = [0; SECTIONS];
+        counts[NODES] = nodes.len();
+        counts[NODE_META] = nodes.len();
+        counts[WAYS] = ways.len();
+        counts[WAY_META] = ways.len();
+        counts[WAY_NODES] = ways.iter().map(|way| way.nodes.len()).sum();
+        counts[RELATIONS] = relations.len();
+        counts[RELATION_META] = relations.len();
+        counts[MEMBERS] = relations.iter().map(|relation| relation.members.len()).sum();
+        counts[TAGS] = tag_count;
+        counts[STRING_OFFSETS] = strings.strings.len() + 1;
+        counts[STRING_DATA] = strings.len;
+
+        let mut file = BufWriter::new(try!(File::create(path)));
+        // Header is written last, when the checksum is known
+        try!(file.write_all(&[0; HEADER_LEN]));
+        let mut out = HashingWriter {
+            out: &mut file,
+            hasher: FnvHasher::default(),
+        };
+
+        let mut tag_start = 0;
+        for node in &nodes {
+            try!(out.i64(node.id));
+            try!(out.u64(node.lat.to_bits()));
+            try!(out.u64(node.lon.to_bits()));
+            try!(out.u64(tag_start));
+            try!(out.u32(to_u32(node.tags.len())));
+            try!(out.u32(0));
+            tag_start += node.tags.len() as u64;
+        }
+        for node in &nodes {
+            try!(out.meta(&node.meta, &strings));
+        }
+
+        let mut node_start = 0;
+        for way in &ways {
+            try!(out.i64(way.id));
+            try!(out.u64(node_start));
+            try!(out.u32(to_u32(way.nodes.len())));
+            try!(out.u32(to_u32(way.tags.len())));
+            try!(out.u64(tag_start));
+            node_start += way.nodes.len() as u64;
+            tag_start += way.tags.len() as u64;
+        }
+        for way in &ways {
+            try!(out.meta(&way.meta, &strings));
+        }
+        for way in &ways {
+            for node in &way.nodes {
+                try!(out.i64(ElementId::from(*node).id()));
+            }
+        }
+
+        let mut member_start = 0;
+        for relation in &relations {
+            try!(out.i64(relation.id));
+            try!(out.u64(member_start));
+            try!(out.u32(to_u32(relation.members.len())));
+            try!(out.u32(to_u32(relation.tags.len())));
+            try!(out.u64(tag_start));
+            member_start += relation.members.len() as u64;
+            tag_start += relation.tags.len() as u64;
+        }
+        for relation in &relations {
+            try!(out.meta(&relation.meta, &strings));
+        }
+        for relation in &relations {
+            for member in &relation.members {
+                let (kind, reference, role) = member_parts(member);
+                try!(out.i64(ElementId::from(reference).id()));
+                try!(out.u32(strings.index(role)));
+                try!(out.u32(kind));
+            }
+        }
+
+        let tags = nodes
+            .iter()
+            .map(|node| &node.tags)
+            .chain(ways.iter().map(|way| &way.tags))
+            .chain(relations.iter().map(|relation| &relation.tags));
+        for tags in tags {
+            for tag in tags.iter() {
+                try!(out.u32(strings.index(&tag.key)));
+                try!(out.u32(strings.index(&tag.val)));
+            }
+        }
+
+        let mut string_start = 0;
+        for string in &strings.strings {
+            try!(out.u64(string_start));
+            string_start += string.len() as u64;
+        }
+        try!(out.u64(string_start));
+        for string in &strings.strings {
+            try!(out.write(string.as_bytes()));
+        }
+
+        let checksum = out.hasher.finish();
+        let header = header(self.bounds, checksum, &counts);
+        try!(file.seek(SeekFrom::Start(0)));
+        try!(file.write_all(&header));
+        try!(file.flush());
+        Ok(())
+    }
+
+    /// Reads a whole snapshot written with `save_snapshot`, checking its checksum.
+    pub fn load_snapshot<P: AsRef<Path>>(path: P) -> Result<OSM, Error> {
+        let snapshot = try!(Snapshot::open(path));
+        try!(snapshot.verify());
+        Ok(snapshot.to_osm())
+    }
+}
+
+/// Memory mapped snapshot written with `OSM::save_snapshot`.
+///
+/// Opening checks the header and the layout of the file but not the data, use `verify` to
+/// check the checksum first when the file may be corrupt. Element views of a corrupt snapshot
+/// may panic.
+#[derive(Debug)]
+pub struct Snapshot {
+    map: Mmap,
+    checksum: u64,
+    bounds: Option<Bounds>,
+    sections: [(usize, usize); SECTIONS],
+}
+
+impl Snapshot {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Snapshot, Error> {
+        let file = try!(File::open(path));
+        // The file must not be modified while it is mapped
+        let map = try!(unsafe { Mmap::map(&file) });
+
+        if map.len() < HEADER_LEN || &map[..8] != MAGIC {
+            return Err(malformed());
+        }
+        let version = read_u32(&map, 8);
+        if version != VERSION {
+            return Err(Error::MalformedSnapshot(ErrorReason::UnsupportedVersion(version)));
+        }
+        if read_u32(&map, 12) as usize != SECTIONS {
+            return Err(malformed());
+        }
+
+        let checksum = read_u64(&map, 16);
+        let bounds = if read_u64(&map, 24) != 0 {
+            Some(Bounds {
+                minlat: read_f64(&map, 32),
+                minlon: read_f64(&map, 40),
+                maxlat: read_f64(&map, 48),
+                maxlon: read_f64(&map, 56),
+            })
+        } else {
+            None
+        };
+
+        let mut sections = [(0, 0); SECTIONS];
+        for (section, range) in sections.iter_mut().enumerate() {
+            let entry = 64 + section * 16;
+            let offset = read_u64(&map, entry) as usize;
+            let len = read_u64(&map, entry + 8) as usize;
+            let in_file = match offset.checked_add(len) {
+                Some(end) => offset >= HEADER_LEN && end <= map.len(),
+                None => false,
+            };
+            if !in_file || len / RECORD_SIZES[section] * RECORD_SIZES[section] != len {
+                return Err(malformed());
+            }
+            *range = (offset, len);
+        }
+
+        let snapshot = Snapshot {
+            map: map,
+            checksum: checksum,
+            bounds: bounds,
+            sections: sections,
+        };
+        let meta_matches = |records, meta| snapshot.count(records) == snapshot.count(meta);
+        if !meta_matches(NODES, NODE_META) || !meta_matches(WAYS, WAY_META) ||
+            !meta_matches(RELATIONS, RELATION_META) || snapshot.count(STRING_OFFSETS) == 0
+        {
+            return Err(malformed());
+        }
+
+        Ok(snapshot)
+    }
+
+    /// Checks the data against the checksum in the header.
+    pub fn verify(&self) -> Result<(), Error> {
+        let mut hasher = FnvHasher::default();
+        hasher.write(&self.map[HEADER_LEN..]);
+        if hasher.finish() != self.checksum {
+            return Err(Error::MalformedSnapshot(ErrorReason::ChecksumMismatch));
+        }
+
+        let strings = self.section(STRING_DATA);
+        for index in 0..self.count(STRING_OFFSETS) - 1 {
+            let (start, end) = self.string_range(index);
+            if start > end || end > strings.len() || str::from_utf8(&strings[start..end]).is_err()
+            {
+                return Err(malformed());
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    pub fn node(&self, id: Id) -> Option<SnapshotNode<'_>> {
+        self.find(NODES, id).map(|index| SnapshotNode {
+            snapshot: self,
+            index: index,
+        })
+    }
+
+    pub fn way(&self, id: Id) -> Option<SnapshotWay<'_>> {
+        self.find(WAYS, id).map(|index| SnapshotWay {
+            snapshot: self,
+            index: index,
+        })
+    }
+
+    pub fn relation(&self, id: Id) -> Option<SnapshotRelation<'_>> {
+        self.find(RELATIONS, id).map(|index| SnapshotRelation {
+            snapshot: self,
+            index: index,
+        })
+    }
+
+    pub fn get(&self, id: ElementId) -> Option<SnapshotElement<'_>> {
+        match id {
+            ElementId::Node(id) => self.node(id).map(SnapshotElement::Node),
+            ElementId::Way(id) => self.way(id).map(SnapshotElement::Way),
+            ElementId::Relation(id) => self.relation(id).map(SnapshotElement::Relation),
+        }
+    }
+
+    pub fn resolve_reference(
+        &self,
+        reference: &UnresolvedReference,
+    ) -> Option<SnapshotElement<'_>> {
+        self.get(ElementId::from(*reference))
+    }
+
+    /// Nodes sorted by id.
+    pub fn nodes(&self) -> impl Iterator<Item = SnapshotNode<'_>> {
+        (0..self.count(NODES)).map(move |index| SnapshotNode {
+            snapshot: self,
+            index: index,
+        })
+    }
+
+    /// Ways sorted by id.
+    pub fn ways(&self) -> impl Iterator<Item = SnapshotWay<'_>> {
+        (0..self.count(WAYS)).map(move |index| SnapshotWay {
+            snapshot: self,
+            index: index,
+        })
+    }
+
+    /// Relations sorted by id.
+    pub fn relations(&self) -> impl Iterator<Item = SnapshotRelation<'_>> {
+        (0..self.count(RELATIONS)).map(move |index| SnapshotRelation {
+            snapshot: self,
+            index: index,
+        })
+    }
+
+    /// Iterates over all elements in file order: nodes first, then ways and relations.
+    pub fn elements(&self) -> impl Iterator<Item = SnapshotElement<'_>> {
+        self.nodes()
+            .map(SnapshotElement::Node)
+            .chain(self.ways().map(SnapshotElement::Way))
+            .chain(self.relations().map(SnapshotElement::Relation))
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.count(NODES)
+    }
+
+    pub fn way_count(&self) -> usize {
+        self.count(WAYS)
+    }
+
+    pub fn relation_count(&self) -> usize {
+        self.count(RELATIONS)
+    }
+
+    /// Reads all elements to a regular `OSM`.
+    pub fn to_osm(&self) -> OSM {
+        let mut osm = OSM::new();
+        osm.bounds = self.bounds;
+        for element in self.elements() {
+            osm.insert(element.to_element());
+        }
+        osm
+    }
+
+    fn section(&self, section: usize) -> &[u8] {
+        let (offset, len) = self.sections[section];
+        &self.map[offset..offset + len]
+    }
+
+    fn count(&self, section: usize) -> usize {
+        self.sections[section].1 / RECORD_SIZES[section]
+    }
+
+    /// Record of the section, `index` must be in range.
+    fn record(&self, section: usize, index: usize) -> &[u8] {
+        let size = RECORD_SIZES[section];
+        &self.section(section)[index * size..(index + 1) * size]
+    }
+
+    /// Index of the record with the id, records are sorted by the id in their first field.
+    fn find(&self, section: usize, id: Id) -> Option<usize> {
+        let (mut low, mut high) = (0, self.count(section));
+        while low < high {
+            let mid = low + (high - low) / 2;
+            let mid_id = read_i64(self.record(section, mid), 0);
+            if mid_id < id {
+                low = mid + 1;
+            } else if mid_id > id {
+                high = mid;
+            } else {
+                return Some(mid);
+            }
+        }
+        None
+    }
+
+    fn string_range(&self, index: usize) -> (usize, usize) {
+        let offsets = self.section(STRING_OFFSETS);
+        (
+            read_u64(offsets, index * 8) as usize,
+            read_u64(offsets, index * 8 + 8) as usize,
+        )
+    }
+
+    fn string(&self, index: u32) -> &str {
+        let (start, end) = self.string_range(index as usize);
+        let bytes = &self.section(STRING_DATA)[start..end];
+        str::from_utf8(bytes).expect("snapshot string is not UTF-8")
+    }
+
+    fn meta(&self, section: usize, index: usize) -> Metadata {
+        let record = self.record(section, index);
+        let flags = read_u32(record, 0);
+        let has = |flag| flags & flag != 0;
+
+        Metadata {
+            version: if has(HAS_VERSION) {
+                Some(read_u32(record, 4))
+            } else {
+                None
+            },
+            changeset: if has(HAS_CHANGESET) {
+                Some(read_i64(record, 8))
+            } else {
+                None
+            },
+            uid: if has(HAS_UID) {
+                Some(read_i64(record, 16))
+            } else {
+                None
+            },
+            timestamp: if has(HAS_TIMESTAMP) {
+                Some(self.string(read_u32(record, 24)).to_string())
+            } else {
+                None
+            },
+            user: if has(HAS_USER) {
+                Some(self.string(read_u32(record, 28)).to_string())
+            } else {
+                None
+            },
+            visible: if has(HAS_VISIBLE) {
+                Some(has(VISIBLE))
+            } else {
+                None
+            },
+        }
+    }
+
+    fn tags(&self, start: u64, len: u32) -> SnapshotTags<'_> {
+        SnapshotTags {
+            snapshot: self,
+            start: start as usize,
+            len: len as usize,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum SnapshotElement<'a> {
+    Node(SnapshotNode<'a>),
+    Way(SnapshotWay<'a>),
+    Relation(SnapshotRelation<'a>),
+}
+
+impl<'a> SnapshotElement<'a> {
+    pub fn element_id(&self) -> ElementId {
+        match *self {
+            SnapshotElement::Node(node) => ElementId::Node(node.id()),
+            SnapshotElement::Way(way) => ElementId::Way(way.id()),
+            SnapshotElement::Relation(relation) => ElementId::Relation(relation.id()),
+        }
+    }
+
+    pub fn tags(&self) -> SnapshotTags<'a> {
+        match *self {
+            SnapshotElement::Node(node) => node.tags(),
+            SnapshotElement::Way(way) => way.tags(),
+            SnapshotElement::Relation(relation) => relation.tags(),
+        }
+    }
+
+    pub fn meta(&self) -> Metadata {
+        match *self {
+            SnapshotElement::Node(node) => node.meta(),
+            SnapshotElement::Way(way) => way.meta(),
+            SnapshotElement::Relation(relation) => relation.meta(),
+        }
+    }
+
+    pub fn to_element(&self) -> Element {
+        match *self {
+            SnapshotElement::Node(node) => Element::Node(node.to_node()),
+            SnapshotElement::Way(way) => Element::Way(way.to_way()),
+            SnapshotElement::Relation(relation) => Element::Relation(relation.to_relation()),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SnapshotNode<'a> {
+    snapshot: &'a Snapshot,
+    index: usize,
+}
+
+impl<'a> SnapshotNode<'a> {
+    fn record(&self) -> &'a [u8] {
+        self.snapshot.record(NODES, self.index)
+    }
+
+    pub fn id(&self) -> Id {
+        read_i64(self.record(), 0)
+    }
+
+    pub fn lat(&self) -> f64 {
+        read_f64(self.record(), 8)
+    }
+
+    pub fn lon(&self) -> f64 {
+        read_f64(self.record(), 16)
+    }
+
+    pub fn location(&self) -> Location {
+        Location {
+            lat: self.lat(),
+            lon: self.lon(),
+        }
+    }
+
+    pub fn tags(&self) -> SnapshotTags<'a> {
+        let record = self.record();
+        self.snapshot.tags(read_u64(record, 24), read_u32(record, 32))
+    }
+
+    pub fn meta(&self) -> Metadata {
+        self.snapshot.meta(NODE_META, self.index)
+    }
+
+    pub fn to_node(&self) -> Node {
+        Node {
+            id: self.id(),
+            lat: self.lat(),
+            lon: self.lon(),
+            tags: self.tags().to_tags(),
+            meta: self.meta(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SnapshotWay<'a> {
+    snapshot: &'a Snapshot,
+    index: usize,
+}
+
+impl<'a> SnapshotWay<'a> {
+    fn record(&self) -> &'a [u8] {
+        self.snapshot.record(WAYS, self.index)
+    }
+
+    pub fn id(&self) -> Id {
+        read_i64(self.record(), 0)
+    }
+
+    /// Node ids of the way in order.
+    pub fn nodes(&self) -> impl Iterator<Item = Id> + 'a {
+        let record = self.record();
+        let start = read_u64(record, 8) as usize;
+        let len = read_u32(record, 16) as usize;
+        let snapshot = self.snapshot;
+        (start..start + len).map(move |index| read_i64(snapshot.record(WAY_NODES, index), 0))
+    }
+
+    pub fn tags(&self) -> SnapshotTags<'a> {
+        let record = self.record();
+        self.snapshot.tags(read_u64(record, 24), read_u32(record, 20))
+    }
+
+    pub fn meta(&self) -> Metadata {
+        self.snapshot.meta(WAY_META, self.index)
+    }
+
+    pub fn to_way(&self) -> Way {
+        Way {
+            id: self.id(),
+            tags: self.tags().to_tags(),
+            nodes: self.nodes().map(UnresolvedReference::Node).collect(),
+            meta: self.meta(),
+            geometry: Default::default(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct SnapshotRelation<'a> {
+    snapshot: &'a Snapshot,
+    index: usize,
+}
+
+impl<'a> SnapshotRelation<'a> {
+    fn record(&self) -> &'a [u8] {
+        self.snapshot.record(RELATIONS, self.index)
+    }
+
+    pub fn id(&self) -> Id {
+        read_i64(self.record(), 0)
+    }
+
+    /// Members of the relation in order with their roles.
+    pub fn members(&self) -> impl Iterator<Item = (ElementId, &'a str)> + 'a {
+        let record = self.record();
+        let start = read_u64(record, 8) as usize;
+        let len = read_u32(record, 16) as usize;
+        let snapshot = self.snapshot;
+        (start..start + len).map(move |index| {
+            let member = snapshot.record(MEMBERS, index);
+            let id = read_i64(member, 0);
+            let id = match read_u32(member, 12) {
+                0 => ElementId::Node(id),
+                1 => ElementId::Way(id),
+                _ => ElementId::Relation(id),
+            };
+            (id, snapshot.string(read_u32(member, 8)))
+        })
+    }
+
+    pub fn tags(&self) -> SnapshotTags<'a> {
+        let record = self.record();
+        self.snapshot.tags(read_u64(record, 24), read_u32(record, 20))
+    }
+
+    pub fn meta(&self) -> Metadata {
+        self.snapshot.meta(RELATION_META, self.index)
+    }
+
+    pub fn to_relation(&self) -> Relation {
+        Relation {
+            id: self.id(),
+            members: self.members()
+                .map(|(id, role)| {
+                    let role = Role::from(role);
+                    match UnresolvedReference::from(id) {
+                        r @ UnresolvedReference::Node(_) => Member::Node(r, role),
+                        r @ UnresolvedReference::Way(_) => Member::Way(r, role),
+                        r @ UnresolvedReference::Relation(_) => Member::Relation(r, role),
+                    }
+                })
+                .collect(),
+            tags: self.tags().to_tags(),
+            meta: self.meta(),
+            geometry: Default::default(),
+        }
+    }
+}
+
+/// Tags of a snapshot element, with the same lookups as `Tags`.
+#[derive(Debug, Copy, Clone)]
+pub struct SnapshotTags<'a> {
+    snapshot: &'a Snapshot,
+    start: usize,
+    len: usize,
+}
+
+impl<'a> SnapshotTags<'a> {
+    pub fn get(&self, key: &str) -> Option<&'a str> {
+        self.iter().find(|&(k, _)| k == key).map(|(_, v)| v)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.get(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterates over `(key, value)` pairs in original order.
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        let snapshot = self.snapshot;
+        (self.start..self.start + self.len).map(move |index| {
+            let tag = snapshot.record(TAGS, index);
+            (
+                snapshot.string(read_u32(tag, 0)),
+                snapshot.string(read_u32(tag, 4)),
+            )
+        })
+    }
+
+    pub fn to_tags(&self) -> Tags {
+        self.iter().map(|(k, v)| Tag::new(k, v)).collect()
+    }
+}
+
+/// Strings of the snapshot in the order they were first seen.
+#[derive(Default)]
+struct StringTable<'a> {
+    strings: Vec<&'a str>,
+    indexes: FnvHashMap<&'a str, u32>,
+    // Total length of the strings
+    len: usize,
+}
+
+impl<'a> StringTable<'a> {
+    fn add(&mut self, string: &'a str) {
+        if !self.indexes.contains_key(string) {
+            self.indexes.insert(string, to_u32(self.strings.len()));
+            self.strings.push(string);
+            self.len += string.len();
+        }
+    }
+
+    fn add_tags(&mut self, tags: &'a Tags) {
+        for tag in tags.iter() {
+            self.add(&tag.key);
+            self.add(&tag.val);
+        }
+    }
+
+    fn add_meta(&mut self, meta: &'a Metadata) {
+        if let Some(ref timestamp) = meta.timestamp {
+            self.add(timestamp);
+        }
+        if let Some(ref user) = meta.user {
+            self.add(user);
+        }
+    }
+
+    /// Index of a string added earlier.
+    fn index(&self, string: &str) -> u32 {
+        self.indexes[string]
+    }
+}
+
+/// Writes the snapshot data hashing it on the way.
+struct HashingWriter<W> {
+    out: W,
+    hasher: FnvHasher,
+}
+
+impl<W: Write> HashingWriter<W> {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        self.hasher.write(bytes);
+        self.out.write_all(bytes)
+    }
+
+    fn u32(&mut self, value: u32) -> io::Result<()> {
+        self.write(&value.to_le_bytes())
+    }
+
+    fn u64(&mut self, value: u64) -> io::Result<()> {
+        self.write(&value.to_le_bytes())
+    }
+
+    fn i64(&mut self, value: i64) -> io::Result<()> {
+        self.write(&value.to_le_bytes())
+    }
+
+    fn meta(&mut self, meta: &Metadata, strings: &StringTable) -> io::Result<()> {
+        let mut flags = 0;
+        let mut set = |flag, present: bool| {
+            if present {
+                flags |= flag;
+            }
+        };
+        set(HAS_VERSION, meta.version.is_some());
+        set(HAS_TIMESTAMP, meta.timestamp.is_some());
+        set(HAS_CHANGESET, meta.changeset.is_some());
+        set(HAS_UID, meta.uid.is_some());
+        set(HAS_USER, meta.user.is_some());
+        set(HAS_VISIBLE, meta.visible.is_some());
+        set(VISIBLE, meta.visible == Some(true));
+
+        try!(self.u32(flags));
+        try!(self.u32(meta.version.unwrap_or(0)));
+        try!(self.i64(meta.changeset.unwrap_or(0)));
+        try!(self.i64(meta.uid.unwrap_or(0)));
+        try!(self.u32(meta.timestamp.as_ref().map_or(0, |timestamp| strings.index(timestamp))));
+        self.u32(meta.user.as_ref().map_or(0, |user| strings.index(user)))
+    }
+}
+
+fn header(bounds: Option<Bounds>, checksum: u64, counts: &[usize; SECTIONS]) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&VERSION.to_le_bytes());
+    header.extend_from_slice(&(SECTIONS as u32).to_le_bytes());
+    header.extend_from_slice(&checksum.to_le_bytes());
+
+    header.extend_from_slice(&u64::from(bounds.is_some()).to_le_bytes());
+    let bounds = bounds.map_or([0.0; 4], |b| [b.minlat, b.minlon, b.maxlat, b.maxlon]);
+    for value in bounds.iter() {
+        header.extend_from_slice(&value.to_bits().to_le_bytes());
+    }
+
+    let mut offset = HEADER_LEN as u64;
+    for (section, &count) in counts.iter().enumerate() {
+        let len = (count * RECORD_SIZES[section]) as u64;
+        header.extend_from_slice(&offset.to_le_bytes());
+        header.extend_from_slice(&len.to_le_bytes());
+        offset += len;
+    }
+
+    header
+}
+
+fn member_parts(member: &Member) -> (u32, UnresolvedReference, &str) {
+    match *member {
+        Member::Node(reference, ref role) => (0, reference, role),
+        Member::Way(reference, ref role) => (1, reference, role),
+        Member::Relation(reference, ref role) => (2, reference, role),
+    }
+}
+
+fn to_u32(len: usize) -> u32 {
+    assert!(len <= u32::MAX as usize, "snapshot table exceeds 2^32 entries");
+    len as u32
+}
+
+fn malformed() -> Error {
+    Error::MalformedSnapshot(ErrorReason::InvalidValue)
+}
+
+fn read_u32(data: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0; 4];
+    bytes.copy_from_slice(&data[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+fn read_i64(data: &[u8], offset: usize) -> i64 {
+    read_u64(data, offset) as i64
+}
+
+fn read_f64(data: &[u8], offset: usize) -> f64 {
+    f64::from_bits(read_u64(data, offset))
+}
@@ -0,0 +1,744 @@
+//! Sorting elements to the usual OSM file order, nodes first, then ways and relations, each by
+//! id, when there are more of them than fit to memory.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use osm_xml::ElementReader;
+//! use osm_xml::sort::{self, ExternalSort};
+//!
+//! let unsorted = File::open("edited.osm").unwrap();
+//! if let Some(violation) = sort::check_sorted(unsorted).unwrap() {
+//!     println!("{:?} after {:?} at byte {}", violation.id, violation.previous, violation.offset);
+//!
+//!     let elements = ElementReader::new(File::open("edited.osm").unwrap());
+//!     for element in ExternalSort::default().sort(elements).unwrap() {
+//!         println!("{:?}", element.unwrap());
+//!     }
+//! }
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::PathBuf;
+use std::mem;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::vec;
+
+use elements::{Bounds, Element, ElementId, Geometry, Location, Member, Metadata, Node,
+               OSMElement, Relation, Role, Tag, UnresolvedReference, Way};
+use error::Error;
+use index;
+use tags::Tags;
+
+const DEFAULT_RUN_SIZE: usize = 1_000_000;
+const DEFAULT_FAN_IN: usize = 64;
+
+// Distinguishes the runs of sorts running at the same time
+static SORTS: AtomicUsize = AtomicUsize::new(0);
+
+/// Sorts element streams larger than memory by sorting runs of `run_size` elements in memory,
+/// writing them to temporary files and merging the runs. When there are more than `fan_in`
+/// runs, they are merged in several passes so that at most `fan_in` files are open at a time.
+///
+/// When an element occurs more than once, the last one is kept like `OSM::parse` would.
+#[derive(Debug, Clone)]
+pub struct ExternalSort {
+    /// Elements sorted in memory at a time, default is one million.
+    pub run_size: usize,
+    /// Directory for the runs, default is the system temporary directory.
+    pub temp_dir: PathBuf,
+    /// Runs merged at a time, default is 64.
+    pub fan_in: usize,
+}
+
+impl Default for ExternalSort {
+    fn default() -> ExternalSort {
+        ExternalSort {
+            run_size: DEFAULT_RUN_SIZE,
+            temp_dir: env::temp_dir(),
+            fan_in: DEFAULT_FAN_IN,
+        }
+    }
+}
+
+impl ExternalSort {
+    /// Reads all elements and returns them in sorted order. The first error of the input ends
+    /// sorting.
+    pub fn sort<I>(&self, elements: I) -> Result<SortedElements, Error>
+    where
+        I: IntoIterator<Item = Result<Element, Error>>,
+    {
+        let run_size = self.run_size.max(1);
+        let fan_in = self.fan_in.max(2);
+        let mut runs = Runs {
+            dir: self.temp_dir.clone(),
+            sort: SORTS.fetch_add(1, Ordering::Relaxed),
+            next: 0,
+            paths: Vec::new(),
+        };
+        let mut run = Vec::new();
+
+        for element in elements {
+            run.push(try!(element));
+            if run.len() == run_size {
+                try!(runs.write(sort_run(run)));
+                run = Vec::new();
+            }
+        }
+
+        let run = sort_run(run);
+        if runs.paths.is_empty() {
+            return Ok(SortedElements {
+                inner: Inner::Memory(run.into_iter()),
+            });
+        }
+        if !run.is_empty() {
+            try!(runs.write(run));
+        }
+
+        while runs.paths.len() > fan_in {
+            // Consecutive runs are merged so that the later ones still win duplicates
+            let mut pass = runs.take_paths();
+            while !pass.paths.is_empty() {
+                let len = pass.paths.len().min(fan_in);
+                let group = runs.with_paths(pass.paths.drain(..len).collect());
+                let mut merge = try!(Merge::open(group));
+                let mut out = try!(runs.create());
+                while let Some(element) = try!(merge.next()) {
+                    try!(write_element(&mut out, &element));
+                }
+                try!(out.flush());
+            }
+        }
+
+        Ok(SortedElements {
+            inner: Inner::Merge(try!(Merge::open(runs))),
+        })
+    }
+}
+
+/// Sorted elements from `ExternalSort::sort`, temporary files are removed when dropped.
+pub struct SortedElements {
+    inner: Inner,
+}
+
+enum Inner {
+    Memory(vec::IntoIter<Element>),
+    Merge(Merge),
+    Failed,
+}
+
+impl Iterator for SortedElements {
+    type Item = Result<Element, Error>;
+
+    fn next(&mut self) -> Option<Result<Element, Error>> {
+        let result = match self.inner {
+            Inner::Memory(ref mut elements) => return elements.next().map(Ok),
+            Inner::Merge(ref mut merge) => merge.next(),
+            Inner::Failed => return None,
+        };
+
+        match result {
+            Ok(element) => element.map(Ok),
+            Err(err) => {
+                // Runs can't be merged without one of them
+                self.inner = Inner::Failed;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Position of a file which breaks sort order, see `check_sorted`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct SortViolation {
+    /// Element which should have come earlier.
+    pub id: ElementId,
+    /// Element before it.
+    pub previous: ElementId,
+    /// Byte offset of the start tag of the element.
+    pub offset: u64,
+    /// Number of elements before the element.
+    pub index: usize,
+}
+
+/// Checks whether the nodes, ways and relations of the document are sorted by kind and then by
+/// id without duplicates, returning the first element out of order. Only the start tags of the
+/// elements are read, so this is much faster than parsing.
+pub fn check_sorted<R: Read>(source: R) -> Result<Option<SortViolation>, Error> {
+    let mut previous = None;
+    let mut violation = None;
+    let mut index = 0;
+
+    try!(index::scan_elements(source, |id, offset| {
+        if let Some(previous) = previous {
+            if id <= previous {
+                violation = Some(SortViolation {
+                    id: id,
+                    previous: previous,
+                    offset: offset,
+                    index: index,
+                });
+                return false;
+            }
+        }
+        previous = Some(id);
+        index += 1;
+        true
+    }));
+
+    Ok(violation)
+}
+
+/// Sorts a run keeping the last one of duplicates.
+fn sort_run(mut run: Vec<Element>) -> Vec<Element> {
+    // Stable sort of the reversed run keeps the last occurrence first
+    run.reverse();
+    run.sort_by_key(|element| element.element_id());
+    run.dedup_by_key(|element| element.element_id());
+    run
+}
+
+/// Temporary files of the sorted runs.
+struct Runs {
+    dir: PathBuf,
+    sort: usize,
+    // Number of the next run file
+    next: usize,
+    paths: Vec<PathBuf>,
+}
+
+impl Runs {
+    /// Creates a new run file, never opening an existing one.
+    fn create(&mut self) -> Result<BufWriter<File>, Error> {
+        loop {
+            let name = format!(
+                "osm-xml-sort-{}-{}-{}.run",
+                process::id(),
+                self.sort,
+                self.next
+            );
+            let path = self.dir.join(name);
+            self.next += 1;
+
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    self.paths.push(path);
+                    return Ok(BufWriter::new(file));
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(Error::Io(err)),
+            }
+        }
+    }
+
+    fn write(&mut self, run: Vec<Element>) -> Result<(), Error> {
+        let mut out = try!(self.create());
+        for element in &run {
+            try!(write_element(&mut out, element));
+        }
+        try!(out.flush());
+        Ok(())
+    }
+
+    /// Runs removing the given files when dropped.
+    fn with_paths(&self, paths: Vec<PathBuf>) -> Runs {
+        Runs {
+            dir: self.dir.clone(),
+            sort: self.sort,
+            next: 0,
+            paths: paths,
+        }
+    }
+
+    /// Moves the current files to a new `Runs`.
+    fn take_paths(&mut self) -> Runs {
+        let paths = mem::take(&mut self.paths);
+        self.with_paths(paths)
+    }
+}
+
+impl Drop for Runs {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// K-way merge of the runs. Equal ids are taken in run order, so the one from the last run wins.
+struct Merge {
+    readers: Vec<BufReader<File>>,
+    // Next element of each run
+    heads: Vec<Option<Element>>,
+    heap: BinaryHeap<Reverse<(ElementId, usize)>>,
+    runs: Runs,
+}
+
+impl Merge {
+    fn open(runs: Runs) -> Result<Merge, Error> {
+        let mut merge = Merge {
+            readers: Vec::new(),
+            heads: Vec::new(),
+            heap: BinaryHeap::new(),
+            runs: runs,
+        };
+        for path in &merge.runs.paths {
+            merge
+                .readers
+                .push(BufReader::new(try!(File::open(path))));
+            merge.heads.push(None);
+        }
+        for run in 0..merge.readers.len() {
+            try!(merge.advance(run));
+        }
+        Ok(merge)
+    }
+
+    /// Reads the next element of the run to its head.
+    fn advance(&mut self, run: usize) -> Result<(), Error> {
+        let element = try!(read_element(&mut self.readers[run]));
+        if let Some(ref element) = element {
+            self.heap.push(Reverse((element.element_id(), run)));
+        }
+        self.heads[run] = element;
+        Ok(())
+    }
+
+    fn next(&mut self) -> Result<Option<Element>, Error> {
+        let Reverse((id, run)) = match self.heap.pop() {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        let mut element = self.heads[run].take();
+        try!(self.advance(run));
+
+        while let Some(&Reverse((next_id, next_run))) = self.heap.peek() {
+            if next_id != id {
+                break;
+            }
+            self.heap.pop();
+            element = self.heads[next_run].take();
+            try!(self.advance(next_run));
+        }
+
+        Ok(element)
+    }
+}
+
+// Runs are written in a simple binary format, all numbers little endian. Records of
+// `store::FileStore` use the same encoding.
+
+pub(crate) fn write_element<W: Write>(out: &mut W, element: &Element) -> io::Result<()> {
+    match *element {
+        Element::Node(ref node) => {
+            try!(out.write_all(&[0]));
+            try!(write_i64(out, node.id));
+            try!(write_f64(out, node.lat));
+            try!(write_f64(out, node.lon));
+            try!(write_tags(out, &node.tags));
+            write_meta(out, &node.meta)
+        }
+        Element::Way(ref way) => {
+            try!(out.write_all(&[1]));
+            try!(write_i64(out, way.id));
+            try!(write_u32(out, way.nodes.len()));
+            for node in &way.nodes {
+                try!(write_reference(out, *node));
+            }
+            try!(write_tags(out, &way.tags));
+            try!(write_meta(out, &way.meta));
+            write_geometry(out, &way.geometry)
+        }
+        Element::Relation(ref relation) => {
+            try!(out.write_all(&[2]));
+            try!(write_i64(out, relation.id));
+            try!(write_u32(out, relation.members.len()));
+            for member in &relation.members {
+                let (kind, reference, role) = match *member {
+                    Member::Node(reference, ref role) => (0, reference, role),
+                    Member::Way(reference, ref role) => (1, reference, role),
+                    Member::Relation(reference, ref role) => (2, reference, role),
+                };
+                try!(out.write_all(&[kind]));
+                try!(write_reference(out, reference));
+                try!(write_str(out, role));
+            }
+            try!(write_tags(out, &relation.tags));
+            try!(write_meta(out, &relation.meta));
+            write_geometry(out, &relation.geometry)
+        }
+    }
+}
+
+fn write_reference<W: Write>(out: &mut W, reference: UnresolvedReference) -> io::Result<()> {
+    let (kind, id) = match reference {
+        UnresolvedReference::Node(id) => (0, id),
+        UnresolvedReference::Way(id) => (1, id),
+        UnresolvedReference::Relation(id) => (2, id),
+    };
+    try!(out.write_all(&[kind]));
+    write_i64(out, id)
+}
+
+fn write_tags<W: Write>(out: &mut W, tags: &Tags) -> io::Result<()> {
+    try!(write_u32(out, tags.len()));
+    for tag in tags.iter() {
+        try!(write_str(out, &tag.key));
+        try!(write_str(out, &tag.val));
+    }
+    Ok(())
+}
+
+fn write_meta<W: Write>(out: &mut W, meta: &Metadata) -> io::Result<()> {
+    try!(write_option(out, meta.version, |out, version| {
+        out.write_all(&version.to_le_bytes())
+    }));
+    try!(write_option(out, meta.timestamp.as_ref(), |out, timestamp| {
+        write_str(out, timestamp)
+    }));
+    try!(write_option(out, meta.changeset, write_i64));
+    try!(write_option(out, meta.uid, write_i64));
+    try!(write_option(out, meta.user.as_ref(), |out, user| write_str(out, user)));
+    write_option(out, meta.visible, |out, visible| out.write_all(&[u8::from(visible)]))
+}
+
+fn write_geometry<W: Write>(out: &mut W, geometry: &Geometry) -> io::Result<()> {
+    try!(write_option(out, geometry.bounds, |out, bounds| {
+        try!(write_f64(out, bounds.minlat));
+        try!(write_f64(out, bounds.minlon));
+        try!(write_f64(out, bounds.maxlat));
+        write_f64(out, bounds.maxlon)
+    }));
+    try!(write_option(out, geometry.center, write_location));
+    try!(write_locations(out, &geometry.nodes));
+    try!(write_u32(out, geometry.members.len()));
+    for member in &geometry.members {
+        try!(write_locations(out, member));
+    }
+    Ok(())
+}
+
+fn write_locations<W: Write>(out: &mut W, locations: &[Option<Location>]) -> io::Result<()> {
+    try!(write_u32(out, locations.len()));
+    for &location in locations {
+        try!(write_option(out, location, write_location));
+    }
+    Ok(())
+}
+
+fn write_location<W: Write>(out: &mut W, location: Location) -> io::Result<()> {
+    try!(write_f64(out, location.lat));
+    write_f64(out, location.lon)
+}
+
+fn write_option<W, T, F>(out: &mut W, value: Option<T>, write: F) -> io::Result<()>
+where
+    W: Write,
+    F: FnOnce(&mut W, T) -> io::Result<()>,
+{
+    match value {
+        Some(value) => {
+            try!(out.write_all(&[1]));
+            write(out, value)
+        }
+        None => out.write_all(&[0]),
+    }
+}
+
+fn write_str<W: Write>(out: &mut W, value: &str) -> io::Result<()> {
+    try!(write_u32(out, value.len()));
+    out.write_all(value.as_bytes())
+}
+
+fn write_u32<W: Write>(out: &mut W, len: usize) -> io::Result<()> {
+    assert!(len <= u32::MAX as usize, "sort run value exceeds 2^32 entries");
+    out.write_all(&(len as u32).to_le_bytes())
+}
+
+fn write_i64<W: Write>(out: &mut W, value: i64) -> io::Result<()> {
+    out.write_all(&value.to_le_bytes())
+}
+
+fn write_f64<W: Write>(out: &mut W, value: f64) -> io::Result<()> {
+    out.write_all(&value.to_bits().to_le_bytes())
+}
+
+/// Next element of the run, `None` at the end of it.
+pub(crate) fn read_element<R: Read>(source: &mut R) -> Result<Option<Element>, Error> {
+    let mut kind = [0; 1];
+    if try!(source.read(&mut kind)) == 0 {
+        return Ok(None);
+    }
+
+    let element = match kind[0] {
+        0 => Element::Node(Node {
+            id: try!(read_i64(source)),
+            lat: try!(read_f64(source)),
+            lon: try!(read_f64(source)),
+            tags: try!(read_tags(source)),
+            meta: try!(read_meta(source)),
+        }),
+        1 => {
+            let id = try!(read_i64(source));
+            let len = try!(read_u32(source));
+            let mut nodes = Vec::with_capacity(len);
+            for _ in 0..len {
+                nodes.push(try!(read_reference(source)));
+            }
+            Element::Way(Way {
+                id: id,
+                nodes: nodes,
+                tags: try!(read_tags(source)),
+                meta: try!(read_meta(source)),
+                geometry: try!(read_geometry(source)),
+            })
+        }
+        2 => {
+            let id = try!(read_i64(source));
+            let len = try!(read_u32(source));
+            let mut members = Vec::with_capacity(len);
+            for _ in 0..len {
+                let kind = try!(read_u8(source));
+                let reference = try!(read_reference(source));
+                let role = Role::from(try!(read_string(source)));
+                members.push(match kind {
+                    0 => Member::Node(reference, role),
+                    1 => Member::Way(reference, role),
+                    2 => Member::Relation(reference, role),
+                    _ => return Err(corrupt()),
+                });
+            }
+            Element::Relation(Relation {
+                id: id,
+                members: members,
+                tags: try!(read_tags(source)),
+                meta: try!(read_meta(source)),
+                geometry: try!(read_geometry(source)),
+            })
+        }
+        _ => return Err(corrupt()),
+    };
+
+    Ok(Some(element))
+}
+
+fn read_reference<R: Read>(source: &mut R) -> Result<UnresolvedReference, Error> {
+    let kind = try!(read_u8(source));
+    let id = try!(read_i64(source));
+    match kind {
+        0 => Ok(UnresolvedReference::Node(id)),
+        1 => Ok(UnresolvedReference::Way(id)),
+        2 => Ok(UnresolvedReference::Relation(id)),
+        _ => Err(corrupt()),
+    }
+}
+
+fn read_tags<R: Read>(source: &mut R) -> Result<Tags, Error> {
+    let len = try!(read_u32(source));
+    let mut tags = Vec::with_capacity(len);
+    for _ in 0..len {
+        let key = try!(read_string(source));
+        let val = try!(read_string(source));
+        tags.push(Tag::new(key, val));
+    }
+    Ok(tags.into())
+}
+
+fn read_meta<R: Read>(source: &mut R) -> Result<Metadata, Error> {
+    let version = if try!(read_flag(source)) {
+        let mut bytes = [0; 4];
+        try!(source.read_exact(&mut bytes));
+        Some(u32::from_le_bytes(bytes))
+    } else {
+        None
+    };
+    let timestamp = try!(read_option(source, read_string));
+    let changeset = try!(read_option(source, read_i64));
+    let uid = try!(read_option(source, read_i64));
+    let user = try!(read_option(source, read_string));
+    let visible = try!(read_option(source, |source| Ok(try!(read_u8(source)) != 0)));
+
+    Ok(Metadata {
+        version: version,
+        timestamp: timestamp,
+        changeset: changeset,
+        uid: uid,
+        user: user,
+        visible: visible,
+    })
+}
+
+fn read_geometry<R: Read>(source: &mut R) -> Result<Geometry, Error> {
+    let bounds = try!(read_option(source, |source| {
+        Ok(Bounds {
+            minlat: try!(read_f64(source)),
+            minlon: try!(read_f64(source)),
+            maxlat: try!(read_f64(source)),
+            maxlon: try!(read_f64(source)),
+        })
+    }));
+    let center = try!(read_option(source, read_location));
+    let nodes = try!(read_locations(source));
+    let len = try!(read_u32(source));
+    let mut members = Vec::with_capacity(len);
+    for _ in 0..len {
+        members.push(try!(read_locations(source)));
+    }
+
+    Ok(Geometry {
+        bounds: bounds,
+        center: center,
+        nodes: nodes,
+        members: members,
+    })
+}
+
+fn read_locations<R: Read>(source: &mut R) -> Result<Vec<Option<Location>>, Error> {
+    let len = try!(read_u32(source));
+    let mut locations = Vec::with_capacity(len);
+    for _ in 0..len {
+        locations.push(try!(read_option(source, read_location)));
+    }
+    Ok(locations)
+}
+
+fn read_location<R: Read>(source: &mut R) -> Result<Location, Error> {
+    Ok(Location {
+        lat: try!(read_f64(source)),
+        lon: try!(read_f64(source)),
+    })
+}
+
+fn read_option<R, T, F>(source: &mut R, read: F) -> Result<Option<T>, Error>
+where
+    R: Read,
+    F: FnOnce(&mut R) -> Result<T, Error>,
+{
+    if try!(read_flag(source)) {
+        read(source).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+fn read_flag<R: Read>(source: &mut R) -> Result<bool, Error> {
+    match try!(read_u8(source)) {
+        0 => Ok(false),
+        1 => Ok(true),
+        _ => Err(corrupt()),
+    }
+}
+
+fn read_string<R: Read>(source: &mut R) -> Result<String, Error> {
+    let len = try!(read_u32(source));
+    let mut bytes = vec![0; len];
+    try!(source.read_exact(&mut bytes));
+    String::from_utf8(bytes).map_err(|_| corrupt())
+}
+
+fn read_u8<R: Read>(source: &mut R) -> Result<u8, Error> {
+    let mut bytes = [0; 1];
+    try!(source.read_exact(&mut bytes));
+    Ok(bytes[0])
+}
+
+fn read_u32<R: Read>(source: &mut R) -> Result<usize, Error> {
+    let mut bytes = [0; 4];
+    try!(source.read_exact(&mut bytes));
+    Ok(u32::from_le_bytes(bytes) as usize)
+}
+
+fn read_i64<R: Read>(source: &mut R) -> Result<i64, Error> {
+    let mut bytes = [0; 8];
+    try!(source.read_exact(&mut bytes));
+    Ok(i64::from_le_bytes(bytes))
+}
+
+fn read_f64<R: Read>(source: &mut R) -> Result<f64, Error> {
+    let mut bytes = [0; 8];
+    try!(source.read_exact(&mut bytes));
+    Ok(f64::from_bits(u64::from_le_bytes(bytes)))
+}
+
+/// Runs are written by this process, so bad data means the file was changed.
+fn corrupt() -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, "corrupt element record"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn run_format_round_trip() {
+        let geometry = Geometry {
+            bounds: Some(Bounds {
+                minlat: 1.0,
+                minlon: 2.0,
+                maxlat: 3.0,
+                maxlon: 4.0,
+            }),
+            center: None,
+            nodes: vec![Some(Location { lat: 1.5, lon: 2.5 }), None],
+            members: vec![vec![None], vec![]],
+        };
+        let meta = Metadata {
+            version: Some(3),
+            timestamp: Some("2018-01-01T00:00:00Z".to_string()),
+            changeset: None,
+            uid: Some(-1),
+            user: Some("mapper ä".to_string()),
+            visible: Some(false),
+        };
+        let elements = vec![
+            Element::Node(Node {
+                id: -5,
+                lat: 60.1,
+                lon: -24.9,
+                tags: vec![Tag::new("name", ""), Tag::new("amenity", "bench")].into(),
+                meta: meta.clone(),
+            }),
+            Element::Way(Way {
+                id: 7,
+                nodes: vec![UnresolvedReference::Node(-5), UnresolvedReference::Node(2)],
+                tags: Tags::default(),
+                meta: Metadata::default(),
+                geometry: geometry.clone(),
+            }),
+            Element::Relation(Relation {
+                id: 9,
+                members: vec![
+                    Member::Way(UnresolvedReference::Way(7), Role::from("outer")),
+                    Member::Relation(UnresolvedReference::Relation(9), Role::default()),
+                ],
+                tags: vec![Tag::new("type", "multipolygon")].into(),
+                meta: meta,
+                geometry: geometry,
+            }),
+        ];
+
+        let mut data = Vec::new();
+        for element in &elements {
+            write_element(&mut data, element).unwrap();
+        }
+
+        let mut source = &data[..];
+        let mut read = Vec::new();
+        while let Some(element) = read_element(&mut source).unwrap() {
+            read.push(element);
+        }
+        assert_eq!(read, elements);
+
+        let mut truncated = &data[..data.len() - 1];
+        let mut result = read_element(&mut truncated);
+        while let Ok(Some(_)) = result {
+            result = read_element(&mut truncated);
+        }
+        assert!(result.is_err());
+    }
+}
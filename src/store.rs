@@ -0,0 +1,1093 @@
+//! Element stores, so that code resolving references or extracting parts of the data works the
+//! same way whether the elements are in memory or on disk.
+//!
+//! ```no_run
+//! # extern crate osm_xml as osm;
+//! # use std::fs::File;
+//! use osm::store::{self, ElementStore, FileStore};
+//!
+//! # fn main() {
+//! let mut store = FileStore::create("planet.store").unwrap();
+//! for element in osm::ElementReader::new(File::open("planet.osm").unwrap()) {
+//!     store.insert(element.unwrap()).unwrap();
+//! }
+//!
+//! let way = store.way(4242).unwrap().unwrap();
+//! println!("{:?}", store::way_locations(&store, &way).unwrap());
+//!
+//! let mut ids = store::descendants(&store, Some(osm::ElementId::Way(4242))).unwrap();
+//! ids.insert(osm::ElementId::Way(4242));
+//! let extract = store::extract(&store, &ids).unwrap();
+//! println!("{} nodes", extract.nodes.len());
+//! # }
+//! ```
+//!
+//! Available stores:
+//!
+//! - `OSM`: the hash maps of the parsed document, the default
+//! - `FileStore`: append-only file with a memory mapped index of record offsets, for when
+//!   elements do not fit to memory or need to be kept for later runs
+//!
+//! `StoreLocations` gives the node locations of a store to `LocationAdder` and multipolygon
+//! assembly.
+
+use std::borrow::{Borrow, Cow};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use fnv::FnvHashMap;
+use memmap2::Mmap;
+
+use elements::{Element, ElementId, ElementKind, Id, Location, Member, Node, OSMElement,
+               Reference, Relation, UnresolvedReference, Way};
+use error::Error;
+use idset::ElementIdSet;
+use locations::LocationStore;
+use positioned::PositionedReader;
+use recursion::member_id;
+use sort;
+use OSM;
+
+const MAGIC: &[u8; 8] = b"OSMSTO\0\x02";
+// Magic and generation
+const HEADER_LEN: u64 = 16;
+const INDEX_MAGIC: &[u8; 8] = b"OSMSTX\0\x01";
+const INDEX_HEADER_LEN: usize = 56;
+const INDEX_ENTRY_LEN: usize = 20;
+// Changes kept in memory before the index is rewritten, at least
+const MIN_PENDING: usize = 65_536;
+const KINDS: [ElementKind; 3] = [ElementKind::Node, ElementKind::Way, ElementKind::Relation];
+const RECORD_HEADER_LEN: usize = 14;
+const REMOVED: u8 = 0;
+const STORED: u8 = 1;
+
+/// `Reference` resolved from any store, borrowed from in-memory stores and copied from others.
+#[derive(Debug, PartialEq, Clone)]
+pub enum StoredReference<'a> {
+    Node(Cow<'a, Node>),
+    Way(Cow<'a, Way>),
+    Relation(Cow<'a, Relation>),
+    Unresolved,
+}
+
+impl<'a> From<Reference<'a>> for StoredReference<'a> {
+    fn from(reference: Reference<'a>) -> StoredReference<'a> {
+        match reference {
+            Reference::Node(node) => StoredReference::Node(Cow::Borrowed(node)),
+            Reference::Way(way) => StoredReference::Way(Cow::Borrowed(way)),
+            Reference::Relation(relation) => StoredReference::Relation(Cow::Borrowed(relation)),
+            Reference::Unresolved => StoredReference::Unresolved,
+        }
+    }
+}
+
+/// Iterator over the elements of one kind in a store.
+pub type StoredElements<'a, T> = Box<dyn Iterator<Item = Result<Cow<'a, T>, Error>> + 'a>;
+
+/// Storage for elements by id, one id space per element kind. In-memory stores lend out their
+/// elements, others return copies.
+pub trait ElementStore {
+    fn node(&self, id: Id) -> Result<Option<Cow<'_, Node>>, Error>;
+    fn way(&self, id: Id) -> Result<Option<Cow<'_, Way>>, Error>;
+    fn relation(&self, id: Id) -> Result<Option<Cow<'_, Relation>>, Error>;
+
+    /// Ids of all elements of the kind, in no particular order.
+    fn ids(&self, kind: ElementKind) -> Box<dyn Iterator<Item = Id> + '_>;
+
+    /// Inserts element, returning the previous element with the same id if there was one.
+    fn insert(&mut self, element: Element) -> Result<Option<Element>, Error>;
+
+    fn remove(&mut self, id: ElementId) -> Result<Option<Element>, Error>;
+
+    fn contains(&self, id: ElementId) -> Result<bool, Error> {
+        Ok(try!(self.element(id)).is_some())
+    }
+
+    fn element(&self, id: ElementId) -> Result<Option<Element>, Error> {
+        Ok(match id {
+            ElementId::Node(id) => try!(self.node(id)).map(|node| Element::Node(node.into_owned())),
+            ElementId::Way(id) => try!(self.way(id)).map(|way| Element::Way(way.into_owned())),
+            ElementId::Relation(id) => try!(self.relation(id))
+                .map(|relation| Element::Relation(relation.into_owned())),
+        })
+    }
+
+    /// Element the reference points to, `None` when it's not in the store.
+    fn referenced(&self, reference: &UnresolvedReference) -> Result<Option<Element>, Error> {
+        self.element(ElementId::from(*reference))
+    }
+
+    fn nodes(&self) -> StoredElements<'_, Node> {
+        Box::new(
+            self.ids(ElementKind::Node)
+                .filter_map(move |id| self.node(id).transpose()),
+        )
+    }
+
+    fn ways(&self) -> StoredElements<'_, Way> {
+        Box::new(
+            self.ids(ElementKind::Way)
+                .filter_map(move |id| self.way(id).transpose()),
+        )
+    }
+
+    fn relations(&self) -> StoredElements<'_, Relation> {
+        Box::new(
+            self.ids(ElementKind::Relation)
+                .filter_map(move |id| self.relation(id).transpose()),
+        )
+    }
+}
+
+impl<S: ElementStore + ?Sized> ElementStore for &mut S {
+    fn node(&self, id: Id) -> Result<Option<Cow<'_, Node>>, Error> {
+        (**self).node(id)
+    }
+
+    fn way(&self, id: Id) -> Result<Option<Cow<'_, Way>>, Error> {
+        (**self).way(id)
+    }
+
+    fn relation(&self, id: Id) -> Result<Option<Cow<'_, Relation>>, Error> {
+        (**self).relation(id)
+    }
+
+    fn ids(&self, kind: ElementKind) -> Box<dyn Iterator<Item = Id> + '_> {
+        (**self).ids(kind)
+    }
+
+    fn insert(&mut self, element: Element) -> Result<Option<Element>, Error> {
+        (**self).insert(element)
+    }
+
+    fn remove(&mut self, id: ElementId) -> Result<Option<Element>, Error> {
+        (**self).remove(id)
+    }
+
+    fn contains(&self, id: ElementId) -> Result<bool, Error> {
+        (**self).contains(id)
+    }
+
+    fn nodes(&self) -> StoredElements<'_, Node> {
+        (**self).nodes()
+    }
+
+    fn ways(&self) -> StoredElements<'_, Way> {
+        (**self).ways()
+    }
+
+    fn relations(&self) -> StoredElements<'_, Relation> {
+        (**self).relations()
+    }
+}
+
+impl ElementStore for OSM {
+    fn node(&self, id: Id) -> Result<Option<Cow<'_, Node>>, Error> {
+        Ok(self.nodes.get(&id).map(Cow::Borrowed))
+    }
+
+    fn way(&self, id: Id) -> Result<Option<Cow<'_, Way>>, Error> {
+        Ok(self.ways.get(&id).map(Cow::Borrowed))
+    }
+
+    fn relation(&self, id: Id) -> Result<Option<Cow<'_, Relation>>, Error> {
+        Ok(self.relations.get(&id).map(Cow::Borrowed))
+    }
+
+    fn ids(&self, kind: ElementKind) -> Box<dyn Iterator<Item = Id> + '_> {
+        match kind {
+            ElementKind::Node => Box::new(self.nodes.keys().cloned()),
+            ElementKind::Way => Box::new(self.ways.keys().cloned()),
+            ElementKind::Relation => Box::new(self.relations.keys().cloned()),
+        }
+    }
+
+    fn insert(&mut self, element: Element) -> Result<Option<Element>, Error> {
+        Ok(OSM::insert(self, element))
+    }
+
+    fn remove(&mut self, id: ElementId) -> Result<Option<Element>, Error> {
+        Ok(OSM::remove(self, id))
+    }
+
+    fn contains(&self, id: ElementId) -> Result<bool, Error> {
+        Ok(self.get(id).is_some())
+    }
+
+    fn nodes(&self) -> StoredElements<'_, Node> {
+        Box::new(self.nodes.values().map(|node| Ok(Cow::Borrowed(node))))
+    }
+
+    fn ways(&self) -> StoredElements<'_, Way> {
+        Box::new(self.ways.values().map(|way| Ok(Cow::Borrowed(way))))
+    }
+
+    fn relations(&self) -> StoredElements<'_, Relation> {
+        Box::new(self.relations.values().map(|rel| Ok(Cow::Borrowed(rel))))
+    }
+}
+
+/// Element store in a single append-only file. Every insert and remove appends a record.
+/// Offsets of the records are kept in a sorted index in the memory mapped sidecar file
+/// `<path>.idx`, only changes made after the index was written are kept in memory. The index is
+/// rewritten by `compact`, `flush` and after many changes. Replaced and removed records take
+/// disk space until `compact` is called.
+///
+/// A record that was only partly written at the end of the file, eg. because of a crash, is cut
+/// off by `open`.
+pub struct FileStore {
+    path: PathBuf,
+    file: File,
+    // Ties the index to the file, changes when the file is created or compacted
+    generation: u64,
+    len: u64,
+    stale: u64,
+    elements: usize,
+    index: Option<Index>,
+    // Records changed after the index was written, `None` for removed ones
+    pending: [FnvHashMap<Id, Option<Record>>; 3],
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Record {
+    offset: u64,
+    len: u32,
+}
+
+impl FileStore {
+    /// Creates new empty store, truncating the file if it exists.
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<FileStore, Error> {
+        let mut file = try!(
+            OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(path.as_ref())
+        );
+        let generation = new_generation();
+        try!(file.write_all(MAGIC));
+        try!(file.write_all(&generation.to_le_bytes()));
+
+        Ok(FileStore {
+            path: path.as_ref().to_path_buf(),
+            file: file,
+            generation: generation,
+            len: HEADER_LEN,
+            stale: 0,
+            elements: 0,
+            index: None,
+            pending: Default::default(),
+        })
+    }
+
+    /// Opens store written earlier. Records appended after the index was written are read to
+    /// find the current records, all of them when the index is missing or out of date. A
+    /// partly written last record is truncated away.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<FileStore, Error> {
+        let mut file = try!(OpenOptions::new().read(true).write(true).open(path.as_ref()));
+        let mut header = [0; HEADER_LEN as usize];
+        match file.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(invalid_data("not an element store"))
+            }
+            Err(err) => return Err(Error::Io(err)),
+        }
+        if &header[..8] != MAGIC {
+            return Err(invalid_data("not an element store"));
+        }
+        let generation = read_u64(&header[8..]);
+
+        let file_len = try!(file.metadata()).len();
+        let index = Index::load(&index_path(path.as_ref()), generation, file_len).ok();
+        let (len, stale, elements) = match index {
+            Some(ref index) => (index.len, index.stale, index.counts.iter().sum()),
+            None => (HEADER_LEN, 0, 0),
+        };
+
+        let mut store = FileStore {
+            path: path.as_ref().to_path_buf(),
+            file: file,
+            generation: generation,
+            len: len,
+            stale: stale,
+            elements: elements,
+            index: index,
+            pending: Default::default(),
+        };
+        try!(store.read_appended(file_len));
+        if store.needs_flush() {
+            try!(store.flush());
+        }
+        Ok(store)
+    }
+
+    /// Number of elements in the store.
+    pub fn len(&self) -> usize {
+        self.elements
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bytes taken by replaced and removed records.
+    pub fn stale_bytes(&self) -> u64 {
+        self.stale
+    }
+
+    /// Writes the index of the current records, so that `open` only reads the records appended
+    /// after this.
+    pub fn flush(&mut self) -> Result<(), Error> {
+        let path = index_path(&self.path);
+        let temp_path = with_suffix(&path, ".new");
+
+        let mut index = try!(IndexWriter::create(&temp_path));
+        for kind in 0..KINDS.len() {
+            try!(self.for_each_record(kind, |id, record| index.push(kind, id, record)));
+        }
+        try!(index.finish(self.generation, self.len, self.stale));
+
+        // Unmapped before it is replaced
+        let previous = self.index.take();
+        if let Err(err) = fs::rename(&temp_path, &path) {
+            self.index = previous;
+            return Err(Error::Io(err));
+        }
+        self.index = Some(try!(Index::load(&path, self.generation, self.len)));
+        for pending in &mut self.pending {
+            pending.clear();
+        }
+        Ok(())
+    }
+
+    /// Rewrites the file and the index with only the current records.
+    pub fn compact(&mut self) -> Result<(), Error> {
+        let temp_path = with_suffix(&self.path, ".compact");
+        let temp_index_path = index_path(&temp_path);
+
+        let mut compacted = try!(FileStore::create(&temp_path));
+        let mut index = try!(IndexWriter::create(&temp_index_path));
+        for (kind, &element_kind) in KINDS.iter().enumerate() {
+            // Records are read in id order, which keeps the file order and reads sequential
+            try!(self.for_each_record(kind, |id, record| {
+                let payload = try!(self.read_payload(record));
+                let stored = ElementId::new(element_kind, id);
+                let record = try!(compacted.append(STORED, stored, &payload));
+                index.push(kind, id, record)
+            }));
+        }
+        try!(index.finish(compacted.generation, compacted.len, 0));
+        drop(compacted);
+
+        // The index of another generation is ignored if the file is not replaced
+        self.index = None;
+        try!(fs::rename(&temp_index_path, index_path(&self.path)));
+        try!(fs::rename(&temp_path, &self.path));
+        *self = try!(FileStore::open(&self.path));
+        Ok(())
+    }
+
+    /// Reads the records after the indexed ones.
+    fn read_appended(&mut self, file_len: u64) -> Result<(), Error> {
+        let mut file = try!(self.file.try_clone());
+        try!(file.seek(SeekFrom::Start(self.len)));
+        let mut source = BufReader::new(file);
+
+        loop {
+            let mut header = [0; RECORD_HEADER_LEN];
+            match source.read_exact(&mut header) {
+                Ok(()) => (),
+                Err(ref err) if err.kind() == io::ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(Error::Io(err)),
+            }
+            let (state, id, len) = try!(decode_header(&header));
+            let record = Record {
+                offset: self.len + RECORD_HEADER_LEN as u64,
+                len: len,
+            };
+            if record.offset + u64::from(len) > file_len {
+                break;
+            }
+            try!(source.seek_relative(i64::from(len)));
+
+            self.update(id, if state == STORED { Some(record) } else { None });
+            self.len = record.offset + u64::from(len);
+        }
+
+        // Partial last record ends the loop like the end of file does
+        if self.len != file_len {
+            try!(self.file.set_len(self.len));
+        }
+        Ok(())
+    }
+
+    /// Current record of the element.
+    fn record(&self, id: ElementId) -> Option<Record> {
+        let kind = kind_byte(id.kind()) as usize;
+        match self.pending[kind].get(&id.id()) {
+            Some(&record) => record,
+            None => self.index.as_ref().and_then(|index| index.get(kind, id.id())),
+        }
+    }
+
+    /// Makes `record` the current record of the element, `None` when the element was removed.
+    fn update(&mut self, id: ElementId, record: Option<Record>) {
+        let previous = self.record(id);
+        if let Some(previous) = previous {
+            self.stale += RECORD_HEADER_LEN as u64 + u64::from(previous.len);
+        }
+        match record {
+            Some(_) if previous.is_none() => self.elements += 1,
+            Some(_) => (),
+            None => {
+                self.stale += RECORD_HEADER_LEN as u64;
+                if previous.is_some() {
+                    self.elements -= 1;
+                }
+            }
+        }
+
+        if record.is_some() || previous.is_some() {
+            self.pending[kind_byte(id.kind()) as usize].insert(id.id(), record);
+        }
+    }
+
+    /// Whether enough changes are kept in memory to rewrite the index.
+    fn needs_flush(&self) -> bool {
+        let pending: usize = self.pending.iter().map(|pending| pending.len()).sum();
+        pending >= MIN_PENDING.max(self.elements / 8)
+    }
+
+    /// Calls `f` with the current records of the kind in id order.
+    fn for_each_record<F>(&self, kind: usize, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(Id, Record) -> Result<(), Error>,
+    {
+        let mut pending: Vec<(Id, Option<Record>)> = self.pending[kind]
+            .iter()
+            .map(|(&id, &record)| (id, record))
+            .collect();
+        pending.sort_by_key(|&(id, _)| id);
+        let mut pending = pending.into_iter().peekable();
+
+        let index = self.index.as_ref();
+        let indexed = index.map_or(0, |index| index.counts[kind]);
+        let mut indexed = (0..indexed)
+            .filter_map(|position| index.map(|index| index.entry(kind, position)))
+            .peekable();
+
+        loop {
+            let from_index = match (indexed.peek(), pending.peek()) {
+                (None, None) => return Ok(()),
+                (Some(&(indexed_id, _)), Some(&(pending_id, _))) => indexed_id < pending_id,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+            };
+
+            if from_index {
+                if let Some((id, record)) = indexed.next() {
+                    try!(f(id, record));
+                }
+            } else if let Some((id, record)) = pending.next() {
+                // Changed records replace the indexed ones
+                if indexed.peek().map(|&(indexed_id, _)| indexed_id) == Some(id) {
+                    indexed.next();
+                }
+                if let Some(record) = record {
+                    try!(f(id, record));
+                }
+            }
+        }
+    }
+
+    fn read_payload(&self, record: Record) -> Result<Vec<u8>, Error> {
+        let mut payload = vec![0; record.len as usize];
+        try!(PositionedReader::new(&self.file, record.offset).read_exact(&mut payload));
+        Ok(payload)
+    }
+
+    fn read(&self, id: ElementId) -> Result<Option<Element>, Error> {
+        let record = match self.record(id) {
+            Some(record) => record,
+            None => return Ok(None),
+        };
+
+        let payload = try!(self.read_payload(record));
+        match try!(sort::read_element(&mut &payload[..])) {
+            Some(element) => if element.element_id() == id {
+                Ok(Some(element))
+            } else {
+                Err(invalid_data("element store record does not match its header"))
+            },
+            None => Err(invalid_data("empty element store record")),
+        }
+    }
+
+    fn append(&mut self, state: u8, id: ElementId, payload: &[u8]) -> Result<Record, Error> {
+        let mut record = Vec::with_capacity(RECORD_HEADER_LEN + payload.len());
+        record.push(state);
+        record.push(kind_byte(id.kind()));
+        record.extend_from_slice(&id.id().to_le_bytes());
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(payload);
+
+        try!(self.file.seek(SeekFrom::Start(self.len)));
+        try!(self.file.write_all(&record));
+
+        let appended = Record {
+            offset: self.len + RECORD_HEADER_LEN as u64,
+            len: payload.len() as u32,
+        };
+        self.len += record.len() as u64;
+        Ok(appended)
+    }
+}
+
+impl ElementStore for FileStore {
+    fn node(&self, id: Id) -> Result<Option<Cow<'_, Node>>, Error> {
+        match try!(self.read(ElementId::Node(id))) {
+            Some(Element::Node(node)) => Ok(Some(Cow::Owned(node))),
+            _ => Ok(None),
+        }
+    }
+
+    fn way(&self, id: Id) -> Result<Option<Cow<'_, Way>>, Error> {
+        match try!(self.read(ElementId::Way(id))) {
+            Some(Element::Way(way)) => Ok(Some(Cow::Owned(way))),
+            _ => Ok(None),
+        }
+    }
+
+    fn relation(&self, id: Id) -> Result<Option<Cow<'_, Relation>>, Error> {
+        match try!(self.read(ElementId::Relation(id))) {
+            Some(Element::Relation(relation)) => Ok(Some(Cow::Owned(relation))),
+            _ => Ok(None),
+        }
+    }
+
+    fn ids(&self, kind: ElementKind) -> Box<dyn Iterator<Item = Id> + '_> {
+        let kind = kind_byte(kind) as usize;
+        let pending = &self.pending[kind];
+        let indexed = self.index.iter().flat_map(move |index| {
+            (0..index.counts[kind]).map(move |position| index.entry(kind, position).0)
+        });
+
+        Box::new(
+            indexed
+                .filter(move |id| !pending.contains_key(id))
+                .chain(
+                    pending
+                        .iter()
+                        .filter(|&(_, record)| record.is_some())
+                        .map(|(&id, _)| id),
+                ),
+        )
+    }
+
+    fn insert(&mut self, element: Element) -> Result<Option<Element>, Error> {
+        let id = element.element_id();
+        let previous = try!(self.read(id));
+
+        let mut payload = Vec::new();
+        try!(sort::write_element(&mut payload, &element));
+        if payload.len() > u32::MAX as usize {
+            return Err(invalid_data("element too large for element store"));
+        }
+
+        let record = try!(self.append(STORED, id, &payload));
+        self.update(id, Some(record));
+        if self.needs_flush() {
+            try!(self.flush());
+        }
+        Ok(previous)
+    }
+
+    fn remove(&mut self, id: ElementId) -> Result<Option<Element>, Error> {
+        let previous = try!(self.read(id));
+        if previous.is_none() {
+            return Ok(None);
+        }
+
+        try!(self.append(REMOVED, id, &[]));
+        self.update(id, None);
+        if self.needs_flush() {
+            try!(self.flush());
+        }
+        Ok(previous)
+    }
+
+    fn contains(&self, id: ElementId) -> Result<bool, Error> {
+        Ok(self.record(id).is_some())
+    }
+}
+
+/// Locations of the way nodes in the same order as `Way::nodes`, like `Geometry::nodes`. Nodes
+/// missing from the store have no location.
+pub fn way_locations<S>(store: &S, way: &Way) -> Result<Vec<Option<Location>>, Error>
+where
+    S: ElementStore + ?Sized,
+{
+    way.nodes
+        .iter()
+        .map(|reference| match *reference {
+            UnresolvedReference::Node(id) => node_location(store, id),
+            _ => Ok(None),
+        })
+        .collect()
+}
+
+/// Locations of the relation members in the same order as `Relation::members`, like
+/// `Geometry::members`: single location for node members, node locations for way members and
+/// nothing for relation members.
+pub fn member_locations<S>(
+    store: &S,
+    relation: &Relation,
+) -> Result<Vec<Vec<Option<Location>>>, Error>
+where
+    S: ElementStore + ?Sized,
+{
+    relation
+        .members
+        .iter()
+        .map(|member| match *member {
+            Member::Node(UnresolvedReference::Node(id), _) => {
+                Ok(vec![try!(node_location(store, id))])
+            }
+            Member::Way(UnresolvedReference::Way(id), _) => match try!(store.way(id)) {
+                Some(way) => way_locations(store, &way),
+                None => Ok(Vec::new()),
+            },
+            _ => Ok(Vec::new()),
+        })
+        .collect()
+}
+
+/// Overpass `>` over any store, see `OSM::children`.
+pub fn children<S, I>(store: &S, ids: I) -> Result<ElementIdSet, Error>
+where
+    S: ElementStore + ?Sized,
+    I: IntoIterator<Item = ElementId>,
+{
+    let mut found = ElementIdSet::new();
+
+    for id in ids {
+        match id {
+            ElementId::Node(_) => (),
+            ElementId::Way(id) => try!(add_way_nodes(store, id, &mut found)),
+            ElementId::Relation(id) => {
+                let relation = match try!(store.relation(id)) {
+                    Some(relation) => relation,
+                    None => continue,
+                };
+                for member in &relation.members {
+                    match *member {
+                        Member::Node(UnresolvedReference::Node(node_id), _) => {
+                            try!(add_existing(store, ElementId::Node(node_id), &mut found));
+                        }
+                        Member::Way(UnresolvedReference::Way(way_id), _) => {
+                            try!(add_existing(store, ElementId::Way(way_id), &mut found));
+                            try!(add_way_nodes(store, way_id, &mut found));
+                        }
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Overpass `>>` over any store, see `OSM::descendants`.
+pub fn descendants<S, I>(store: &S, ids: I) -> Result<ElementIdSet, Error>
+where
+    S: ElementStore + ?Sized,
+    I: IntoIterator<Item = ElementId>,
+{
+    let mut found = ElementIdSet::new();
+    let mut stack: Vec<ElementId> = ids.into_iter().collect();
+
+    while let Some(id) = stack.pop() {
+        match id {
+            ElementId::Node(_) => (),
+            ElementId::Way(id) => try!(add_way_nodes(store, id, &mut found)),
+            ElementId::Relation(id) => {
+                let relation = match try!(store.relation(id)) {
+                    Some(relation) => relation,
+                    None => continue,
+                };
+                for member in &relation.members {
+                    let member_id = member_id(member);
+                    // Already found elements have been descended into, this stops cycles
+                    if !found.contains(member_id) && try!(store.contains(member_id)) {
+                        found.insert(member_id);
+                        stack.push(member_id);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Overpass `<` over any store, see `OSM::parents`. All ways and relations of the store are
+/// read to find the parents.
+pub fn parents<S, I>(store: &S, ids: I) -> Result<ElementIdSet, Error>
+where
+    S: ElementStore + ?Sized,
+    I: IntoIterator<Item = ElementId>,
+{
+    let index = try!(parent_index(store));
+    let mut found = ElementIdSet::new();
+
+    for id in ids {
+        if let ElementId::Relation(_) = id {
+            continue;
+        }
+
+        if let Some(parents) = index.get(&id) {
+            found.extend(parents.iter().cloned());
+        }
+    }
+
+    Ok(found)
+}
+
+/// Overpass `<<` over any store, see `OSM::ancestors`.
+pub fn ancestors<S, I>(store: &S, ids: I) -> Result<ElementIdSet, Error>
+where
+    S: ElementStore + ?Sized,
+    I: IntoIterator<Item = ElementId>,
+{
+    let index = try!(parent_index(store));
+    let mut found = ElementIdSet::new();
+    let mut stack: Vec<ElementId> = ids.into_iter().collect();
+
+    while let Some(id) = stack.pop() {
+        for parent in index.get(&id).into_iter().flatten() {
+            if found.insert(*parent) {
+                stack.push(*parent);
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Copies the given elements to a new document, see `OSM::subset`. Ids which are not found are
+/// ignored.
+pub fn extract<S, I>(store: &S, ids: I) -> Result<OSM, Error>
+where
+    S: ElementStore + ?Sized,
+    I: IntoIterator,
+    I::Item: Borrow<ElementId>,
+{
+    let mut osm = OSM::new();
+
+    for id in ids {
+        if let Some(element) = try!(store.element(*id.borrow())) {
+            osm.insert(element);
+        }
+    }
+
+    Ok(osm)
+}
+
+/// Element the reference points to, see `OSM::resolve_reference`. In-memory stores lend out
+/// the element, others return a copy.
+pub fn resolve_reference<'a, S>(
+    store: &'a S,
+    reference: &UnresolvedReference,
+) -> Result<StoredReference<'a>, Error>
+where
+    S: ElementStore + ?Sized,
+{
+    let reference = match *reference {
+        UnresolvedReference::Node(id) => try!(store.node(id)).map(StoredReference::Node),
+        UnresolvedReference::Way(id) => try!(store.way(id)).map(StoredReference::Way),
+        UnresolvedReference::Relation(id) => {
+            try!(store.relation(id)).map(StoredReference::Relation)
+        }
+    };
+    Ok(reference.unwrap_or(StoredReference::Unresolved))
+}
+
+/// Node locations of an element store, so that `LocationAdder` and multipolygon assembly can
+/// use the nodes of a store. Setting the location of a node missing from the store inserts a
+/// node without tags, other nodes are only written when their location changes.
+#[derive(Debug)]
+pub struct StoreLocations<S>(pub S);
+
+impl<S: ElementStore> LocationStore for StoreLocations<S> {
+    fn set(&mut self, id: Id, location: Location) -> Result<(), Error> {
+        let node = match try!(self.0.node(id)) {
+            Some(node) => {
+                if node.lat == location.lat && node.lon == location.lon {
+                    return Ok(());
+                }
+                Node {
+                    lat: location.lat,
+                    lon: location.lon,
+                    ..node.into_owned()
+                }
+            }
+            None => Node {
+                id: id,
+                lat: location.lat,
+                lon: location.lon,
+                tags: Default::default(),
+                meta: Default::default(),
+            },
+        };
+        try!(self.0.insert(Element::Node(node)));
+        Ok(())
+    }
+
+    fn get(&self, id: Id) -> Option<Location> {
+        node_location(&self.0, id).ok().and_then(|location| location)
+    }
+}
+
+/// Unwraps the result of an in-memory store like `OSM`, which never fails.
+pub(crate) fn in_memory<T>(result: Result<T, Error>) -> T {
+    result.expect("in-memory element store failed")
+}
+
+fn parent_index<S>(store: &S) -> Result<FnvHashMap<ElementId, Vec<ElementId>>, Error>
+where
+    S: ElementStore + ?Sized,
+{
+    let mut index: FnvHashMap<ElementId, Vec<ElementId>> = FnvHashMap::default();
+
+    for way in store.ways() {
+        let way = try!(way);
+        for node_ref in &way.nodes {
+            let parents = index.entry(ElementId::from(*node_ref)).or_default();
+            // Closed ways refer to the same node twice
+            if parents.last() != Some(&ElementId::Way(way.id)) {
+                parents.push(ElementId::Way(way.id));
+            }
+        }
+    }
+
+    for relation in store.relations() {
+        let relation = try!(relation);
+        for member in &relation.members {
+            let parents = index.entry(member_id(member)).or_default();
+            if parents.last() != Some(&ElementId::Relation(relation.id)) {
+                parents.push(ElementId::Relation(relation.id));
+            }
+        }
+    }
+
+    Ok(index)
+}
+
+fn node_location<S: ElementStore + ?Sized>(store: &S, id: Id) -> Result<Option<Location>, Error> {
+    Ok(try!(store.node(id)).map(|node| {
+        Location {
+            lat: node.lat,
+            lon: node.lon,
+        }
+    }))
+}
+
+fn add_way_nodes<S>(store: &S, way_id: Id, found: &mut ElementIdSet) -> Result<(), Error>
+where
+    S: ElementStore + ?Sized,
+{
+    if let Some(way) = try!(store.way(way_id)) {
+        for node_ref in &way.nodes {
+            try!(add_existing(store, ElementId::from(*node_ref), found));
+        }
+    }
+    Ok(())
+}
+
+fn add_existing<S>(store: &S, id: ElementId, found: &mut ElementIdSet) -> Result<(), Error>
+where
+    S: ElementStore + ?Sized,
+{
+    if try!(store.contains(id)) {
+        found.insert(id);
+    }
+    Ok(())
+}
+
+/// Sorted record offsets of each kind in the sidecar file of `FileStore`. The file starts with
+/// `INDEX_MAGIC`, the generation and length of the indexed file, its stale bytes and the number
+/// of entries of each kind. Entries of nodes, ways and relations follow, each the id, offset
+/// and length of the record.
+struct Index {
+    map: Mmap,
+    len: u64,
+    stale: u64,
+    counts: [usize; 3],
+}
+
+impl Index {
+    fn load(path: &Path, generation: u64, file_len: u64) -> Result<Index, Error> {
+        let file = try!(File::open(path));
+        // The index is only replaced by renaming a new file over it
+        let map = try!(unsafe { Mmap::map(&file) });
+        if map.len() < INDEX_HEADER_LEN || &map[..8] != INDEX_MAGIC {
+            return Err(invalid_data("not an element store index"));
+        }
+        if read_u64(&map[8..]) != generation {
+            return Err(invalid_data("element store index of another file"));
+        }
+
+        let len = read_u64(&map[16..]);
+        let stale = read_u64(&map[24..]);
+        let mut counts = [0; 3];
+        for (kind, count) in counts.iter_mut().enumerate() {
+            *count = read_u64(&map[32 + 8 * kind..]) as usize;
+        }
+        let entries: usize = counts.iter().sum();
+        if len < HEADER_LEN || len > file_len
+            || map.len() != INDEX_HEADER_LEN + entries * INDEX_ENTRY_LEN
+        {
+            return Err(invalid_data("element store index does not match the file"));
+        }
+
+        Ok(Index {
+            map: map,
+            len: len,
+            stale: stale,
+            counts: counts,
+        })
+    }
+
+    fn entry(&self, kind: usize, position: usize) -> (Id, Record) {
+        let before: usize = self.counts[..kind].iter().sum();
+        let start = INDEX_HEADER_LEN + (before + position) * INDEX_ENTRY_LEN;
+        let entry = &self.map[start..start + INDEX_ENTRY_LEN];
+
+        let mut len = [0; 4];
+        len.copy_from_slice(&entry[16..20]);
+        let record = Record {
+            offset: read_u64(&entry[8..]),
+            len: u32::from_le_bytes(len),
+        };
+        (read_u64(entry) as Id, record)
+    }
+
+    fn get(&self, kind: usize, id: Id) -> Option<Record> {
+        let mut low = 0;
+        let mut high = self.counts[kind];
+        while low < high {
+            let middle = low + (high - low) / 2;
+            let (middle_id, record) = self.entry(kind, middle);
+            if middle_id < id {
+                low = middle + 1;
+            } else if middle_id > id {
+                high = middle;
+            } else {
+                return Some(record);
+            }
+        }
+        None
+    }
+}
+
+/// Writes `Index` files, entries have to be pushed in kind and id order.
+struct IndexWriter {
+    out: BufWriter<File>,
+    counts: [usize; 3],
+}
+
+impl IndexWriter {
+    fn create(path: &Path) -> Result<IndexWriter, Error> {
+        let mut out = BufWriter::new(try!(File::create(path)));
+        // Header is written when the counts are known
+        try!(out.write_all(&[0; INDEX_HEADER_LEN]));
+        Ok(IndexWriter {
+            out: out,
+            counts: [0; 3],
+        })
+    }
+
+    fn push(&mut self, kind: usize, id: Id, record: Record) -> Result<(), Error> {
+        self.counts[kind] += 1;
+        try!(self.out.write_all(&id.to_le_bytes()));
+        try!(self.out.write_all(&record.offset.to_le_bytes()));
+        try!(self.out.write_all(&record.len.to_le_bytes()));
+        Ok(())
+    }
+
+    fn finish(mut self, generation: u64, len: u64, stale: u64) -> io::Result<()> {
+        try!(self.out.seek(SeekFrom::Start(0)));
+        try!(self.out.write_all(INDEX_MAGIC));
+        for value in &[generation, len, stale] {
+            try!(self.out.write_all(&value.to_le_bytes()));
+        }
+        for &count in &self.counts {
+            try!(self.out.write_all(&(count as u64).to_le_bytes()));
+        }
+        self.out.flush()
+    }
+}
+
+fn index_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".idx")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = OsString::from(path.as_os_str());
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+fn new_generation() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0);
+    now ^ u64::from(process::id()).rotate_left(32)
+}
+
+fn read_u64(bytes: &[u8]) -> u64 {
+    let mut value = [0; 8];
+    value.copy_from_slice(&bytes[..8]);
+    u64::from_le_bytes(value)
+}
+
+fn decode_header(header: &[u8; RECORD_HEADER_LEN]) -> Result<(u8, ElementId, u32), Error> {
+    let state = header[0];
+    if state != STORED && state != REMOVED {
+        return Err(invalid_data("unknown element store record"));
+    }
+
+    let mut id = [0; 8];
+    id.copy_from_slice(&header[2..10]);
+    let id = i64::from_le_bytes(id);
+    let kind = match header[1] {
+        0 => ElementKind::Node,
+        1 => ElementKind::Way,
+        2 => ElementKind::Relation,
+        _ => return Err(invalid_data("unknown element kind in element store")),
+    };
+
+    let mut len = [0; 4];
+    len.copy_from_slice(&header[10..14]);
+    Ok((state, ElementId::new(kind, id), u32::from_le_bytes(len)))
+}
+
+fn kind_byte(kind: ElementKind) -> u8 {
+    match kind {
+        ElementKind::Node => 0,
+        ElementKind::Way => 1,
+        ElementKind::Relation => 2,
+    }
+}
+
+fn invalid_data(message: &str) -> Error {
+    Error::Io(io::Error::new(io::ErrorKind::InvalidData, message))
+}
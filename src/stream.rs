@@ -0,0 +1,255 @@
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use tokio::io::{AsyncRead, ReadBuf};
+
+use chunks::{self, Chunk, Scanner};
+use elements::{Area, Bounds, Count, Element, ElementId, Tag};
+use error::Error;
+use reader::ReaderBuilder;
+use symbols::Symbols;
+use OSM;
+
+const READ_SIZE: usize = 64 * 1024;
+
+/// Async version of `ElementReader`, streams elements from `AsyncRead` source.
+///
+/// Data is read until it has complete top level elements, which are then parsed in one go
+/// without waiting for the rest of the document. Results are the same as with `ElementReader`.
+/// `ReaderBuilder::stream` creates streams with other backends and options.
+pub struct ElementStream<R> {
+    source: R,
+    builder: ReaderBuilder,
+    symbols: Option<Symbols>,
+    read_buf: Vec<u8>,
+    // Data after the elements parsed so far
+    buf: Vec<u8>,
+    scanner: Scanner,
+    // Whether `buf` still has the start of the document
+    first: bool,
+    eof: bool,
+    finished: bool,
+    elements: VecDeque<Element>,
+    error: Option<Error>,
+    bounds: Option<Bounds>,
+    areas: Vec<Area>,
+    counts: Vec<Count>,
+    duplicate_tags: Vec<(ElementId, Tag)>,
+}
+
+impl<R: AsyncRead + Unpin> ElementStream<R> {
+    pub fn new(source: R) -> ElementStream<R> {
+        ReaderBuilder::new().stream(source)
+    }
+
+    fn with_builder(source: R, builder: &ReaderBuilder) -> ElementStream<R> {
+        ElementStream {
+            source: source,
+            builder: builder.clone(),
+            symbols: builder.symbols_copy(),
+            read_buf: vec![0; READ_SIZE],
+            buf: Vec::new(),
+            scanner: Scanner::new(),
+            first: true,
+            eof: false,
+            finished: false,
+            elements: VecDeque::new(),
+            error: None,
+            bounds: None,
+            areas: Vec::new(),
+            counts: Vec::new(),
+            duplicate_tags: Vec::new(),
+        }
+    }
+
+    /// Bounds of the document, available once the `<bounds>` element has been read.
+    pub fn bounds(&self) -> Option<Bounds> {
+        self.bounds
+    }
+
+    /// Takes the areas read so far.
+    pub fn take_areas(&mut self) -> Vec<Area> {
+        ::std::mem::take(&mut self.areas)
+    }
+
+    /// Results of `out count` read so far.
+    pub fn counts(&self) -> &[Count] {
+        &self.counts
+    }
+
+    /// Takes the tags dropped so far because of duplicate keys, see `ElementReader`.
+    pub fn take_duplicate_tags(&mut self) -> Vec<(ElementId, Tag)> {
+        ::std::mem::take(&mut self.duplicate_tags)
+    }
+
+    /// Symbol table of an interning stream.
+    pub fn symbols(&self) -> Option<&Symbols> {
+        self.symbols.as_ref()
+    }
+
+    pub fn take_symbols(&mut self) -> Option<Symbols> {
+        self.symbols.take()
+    }
+
+    fn parse_chunk(&mut self, len: usize, last: bool) {
+        let Chunk {
+            elements,
+            bounds,
+            areas,
+            counts,
+            duplicate_tags,
+            symbols,
+            error,
+        } = chunks::read_chunk(
+            &self.buf[..len],
+            self.first,
+            last,
+            &self.builder,
+            self.symbols.take(),
+        );
+
+        self.buf.drain(..len);
+        self.scanner.consume(len);
+        self.first = false;
+
+        self.elements.extend(elements);
+        if let Some(bounds) = bounds {
+            self.bounds = bounds;
+        }
+        self.areas.extend(areas);
+        self.counts.extend(counts);
+        self.duplicate_tags.extend(duplicate_tags);
+        self.symbols = symbols;
+        self.error = error;
+    }
+
+    fn read(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let mut read_buf = ReadBuf::new(&mut self.read_buf);
+        match Pin::new(&mut self.source).poll_read(cx, &mut read_buf) {
+            Poll::Ready(Ok(())) => {}
+            other => return other,
+        }
+
+        let data = read_buf.filled();
+        if data.is_empty() {
+            self.eof = true;
+            return Poll::Ready(Ok(()));
+        }
+        self.buf.extend_from_slice(data);
+
+        let mut complete = 0;
+        while let Some(element) = self.scanner.next_element(&self.buf) {
+            complete = element;
+        }
+        if complete > 0 {
+            self.parse_chunk(complete, false);
+        }
+
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for ElementStream<R> {
+    type Item = Result<Element, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let stream = &mut *self;
+
+        loop {
+            if let Some(element) = stream.elements.pop_front() {
+                return Poll::Ready(Some(Ok(element)));
+            }
+            if let Some(err) = stream.error.take() {
+                stream.finished = true;
+                return Poll::Ready(Some(Err(err)));
+            }
+            if stream.finished {
+                return Poll::Ready(None);
+            }
+
+            if stream.eof {
+                let len = stream.buf.len();
+                stream.parse_chunk(len, true);
+                stream.finished = true;
+                continue;
+            }
+
+            match stream.read(cx) {
+                Poll::Ready(Ok(())) => continue,
+                Poll::Ready(Err(err)) => stream.error = Some(Error::Io(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl OSM {
+    /// Parses the document from async source, see `ElementStream`.
+    pub fn parse_async<R>(source: R) -> impl Future<Output = Result<OSM, Error>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        ReaderBuilder::new().parse_async(source)
+    }
+}
+
+impl ReaderBuilder {
+    /// Streams elements from async source with this configuration.
+    pub fn stream<R: AsyncRead + Unpin>(&self, source: R) -> ElementStream<R> {
+        ElementStream::with_builder(source, self)
+    }
+
+    /// Parses the document from async source with this configuration, see `OSM::parse_async`.
+    pub fn parse_async<R>(&self, source: R) -> impl Future<Output = Result<OSM, Error>>
+    where
+        R: AsyncRead + Unpin,
+    {
+        ParseAsync {
+            stream: self.stream(source),
+            osm: Some(OSM::new()),
+        }
+    }
+}
+
+struct ParseAsync<R> {
+    stream: ElementStream<R>,
+    osm: Option<OSM>,
+}
+
+impl<R: AsyncRead + Unpin> Future for ParseAsync<R> {
+    type Output = Result<OSM, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<OSM, Error>> {
+        let parse = &mut *self;
+
+        loop {
+            match Pin::new(&mut parse.stream).poll_next(cx) {
+                Poll::Ready(Some(Ok(element))) => {
+                    if let Some(ref mut osm) = parse.osm {
+                        osm.insert(element);
+                    }
+                }
+                Poll::Ready(Some(Err(err))) => return Poll::Ready(Err(err)),
+                Poll::Ready(None) => {
+                    let mut osm = parse.osm.take().expect("polled after completion");
+                    osm.bounds = parse.stream.bounds();
+                    osm.areas = parse
+                        .stream
+                        .take_areas()
+                        .into_iter()
+                        .map(|area| (area.id, area))
+                        .collect();
+                    osm.counts = parse.stream.counts().to_vec();
+                    osm.duplicate_tags = parse.stream.take_duplicate_tags();
+                    osm.symbols = parse.stream.take_symbols().unwrap_or_default();
+                    return Poll::Ready(Ok(osm));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
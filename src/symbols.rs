@@ -0,0 +1,218 @@
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use fnv::FnvHashSet;
+
+/// Immutable shared string used for tag keys, tag values and member roles.
+///
+/// Cloning is cheap. Strings interned to the same `Symbols` table share one allocation, and
+/// equality checks on them are pointer comparisons. `Str` dereferences to `str` and compares
+/// with `str` and `String`, so it can be used mostly like the `String` it replaces.
+#[derive(Clone)]
+pub struct Str(Arc<str>);
+
+impl Str {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether both strings share the same allocation, which is always the case for equal
+    /// strings interned to the same `Symbols`.
+    pub fn ptr_eq(a: &Str, b: &Str) -> bool {
+        Arc::ptr_eq(&a.0, &b.0)
+    }
+}
+
+impl Deref for Str {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Str {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Str {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for Str {
+    fn default() -> Str {
+        Str::from("")
+    }
+}
+
+impl<'a> From<&'a str> for Str {
+    fn from(s: &'a str) -> Str {
+        Str(Arc::from(s))
+    }
+}
+
+impl From<String> for Str {
+    fn from(s: String) -> Str {
+        Str(Arc::from(s))
+    }
+}
+
+impl From<Str> for String {
+    fn from(s: Str) -> String {
+        s.as_str().to_string()
+    }
+}
+
+impl PartialEq for Str {
+    fn eq(&self, other: &Str) -> bool {
+        Str::ptr_eq(self, other) || self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for Str {}
+
+impl PartialEq<str> for Str {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl<'a> PartialEq<&'a str> for Str {
+    fn eq(&self, other: &&'a str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Str {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl PartialEq<Str> for str {
+    fn eq(&self, other: &Str) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Str> for &str {
+    fn eq(&self, other: &Str) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<Str> for String {
+    fn eq(&self, other: &Str) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl PartialOrd for Str {
+    fn partial_cmp(&self, other: &Str) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Str {
+    fn cmp(&self, other: &Str) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for Str {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for Str {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Str {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+/// Symbol table for interning tag keys, tag values and roles, see `OSM::parse_interned`.
+#[derive(Debug, Clone, Default)]
+pub struct Symbols {
+    symbols: FnvHashSet<Str>,
+}
+
+impl Symbols {
+    pub fn new() -> Symbols {
+        Symbols::default()
+    }
+
+    /// Returns the shared copy of the string, adding it to the table if needed.
+    pub fn intern(&mut self, s: &str) -> Str {
+        if let Some(symbol) = self.symbols.get(s) {
+            return symbol.clone();
+        }
+
+        let symbol = Str::from(s);
+        self.symbols.insert(symbol.clone());
+        symbol
+    }
+
+    /// Looks up an existing symbol. Tags of interned documents can be compared against it with
+    /// `Str::ptr_eq`, or with `==` which checks the pointer first.
+    pub fn get(&self, s: &str) -> Option<Str> {
+        self.symbols.get(s).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Str> {
+        self.symbols.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn interned_strings_share_allocation() {
+        let mut symbols = Symbols::new();
+        let a = symbols.intern("highway");
+        let b = symbols.intern("highway");
+        let c = Str::from("highway");
+
+        assert!(Str::ptr_eq(&a, &b));
+        assert!(!Str::ptr_eq(&a, &c));
+        assert_eq!(a, c);
+        assert_eq!(symbols.len(), 1);
+        assert!(Str::ptr_eq(&symbols.get("highway").unwrap(), &a));
+        assert_eq!(symbols.get("building"), None);
+    }
+
+    #[test]
+    fn compares_with_strings() {
+        let s = Str::from("yes");
+        assert_eq!(s, "yes");
+        assert_eq!(s, "yes".to_string());
+        assert_eq!("yes", s);
+        assert!(s.starts_with("y"));
+        assert_eq!(format!("{} {:?}", s, s), "yes \"yes\"");
+        assert_eq!(String::from(s), "yes");
+    }
+}
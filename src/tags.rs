@@ -0,0 +1,234 @@
+use std::iter::FromIterator;
+use std::ops::Deref;
+use std::slice;
+use std::vec;
+
+use elements::Tag;
+use error::{Error, ErrorReason};
+use symbols::Str;
+
+/// Tags of single element. Keys are unique and the order in which tags were added is preserved,
+/// so elements can be written back out the way they were read.
+///
+/// `Tags` dereferences to `[Tag]`, so it can be indexed and iterated like the plain tag vector.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Tags {
+    tags: Vec<Tag>,
+}
+
+impl Tags {
+    pub fn new() -> Tags {
+        Tags { tags: Vec::new() }
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|tag| tag.key == key)
+            .map(|tag| tag.val.as_str())
+    }
+
+    /// Like `get`, but checks the pointer first so lookups with symbols from the same
+    /// `Symbols` table as the tags skip string comparison.
+    pub fn get_symbol(&self, key: &Str) -> Option<&Str> {
+        self.tags
+            .iter()
+            .find(|tag| tag.key == *key)
+            .map(|tag| &tag.val)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.tags.iter().any(|tag| tag.key == key)
+    }
+
+    /// Sets value for the key and returns the old value. Existing tag keeps its position, new
+    /// tags are appended to the end.
+    pub fn insert<K, V>(&mut self, key: K, val: V) -> Option<String>
+    where
+        K: Into<Str>,
+        V: Into<Str>,
+    {
+        let key = key.into();
+        let val = val.into();
+
+        match self.tags.iter_mut().find(|tag| tag.key == key) {
+            Some(tag) => Some(::std::mem::replace(&mut tag.val, val).into()),
+            None => {
+                self.tags.push(Tag { key: key, val: val });
+                None
+            }
+        }
+    }
+
+    /// Appends the tag, refusing it if a tag with the same key already exists.
+    pub fn push(&mut self, tag: Tag) -> Result<(), Error> {
+        if self.contains(&tag.key) {
+            return Err(Error::MalformedTag(ErrorReason::DuplicateKey));
+        }
+
+        self.tags.push(tag);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let pos = self.tags.iter().position(|tag| tag.key == key);
+        pos.map(|pos| self.tags.remove(pos).val.into())
+    }
+
+    /// Iterates over tags whose key starts with `prefix`.
+    pub fn with_prefix<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a Tag> + 'a {
+        self.tags.iter().filter(move |tag| tag.key.starts_with(prefix))
+    }
+
+    /// Iterates over keys in the namespace, eg. `namespace("addr")` yields `("street", ..)` for
+    /// `addr:street`. Key equal to the namespace itself is not included.
+    pub fn namespace<'a>(
+        &'a self,
+        namespace: &'a str,
+    ) -> impl Iterator<Item = (&'a str, &'a str)> + 'a {
+        self.tags.iter().filter_map(move |tag| {
+            if tag.key.len() > namespace.len() && tag.key.starts_with(namespace)
+                && tag.key.as_bytes()[namespace.len()] == b':'
+            {
+                Some((&tag.key[namespace.len() + 1..], tag.val.as_str()))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Splits semicolon separated value list, eg. `cuisine=pizza;kebab`. Whitespace around
+    /// the values is trimmed and empty values are skipped.
+    pub fn values<'a>(&'a self, key: &str) -> impl Iterator<Item = &'a str> + 'a {
+        self.get(key)
+            .unwrap_or("")
+            .split(';')
+            .map(|val| val.trim())
+            .filter(|val| !val.is_empty())
+    }
+}
+
+impl Deref for Tags {
+    type Target = [Tag];
+
+    fn deref(&self) -> &[Tag] {
+        &self.tags
+    }
+}
+
+/// Duplicate keys are dropped, the first tag with the key is kept.
+impl From<Vec<Tag>> for Tags {
+    fn from(tags: Vec<Tag>) -> Tags {
+        tags.into_iter().collect()
+    }
+}
+
+impl From<Tags> for Vec<Tag> {
+    fn from(tags: Tags) -> Vec<Tag> {
+        tags.tags
+    }
+}
+
+impl FromIterator<Tag> for Tags {
+    fn from_iter<I: IntoIterator<Item = Tag>>(iter: I) -> Tags {
+        let mut tags = Tags::new();
+        for tag in iter {
+            tags.push(tag).ok();
+        }
+        tags
+    }
+}
+
+impl IntoIterator for Tags {
+    type Item = Tag;
+    type IntoIter = vec::IntoIter<Tag>;
+
+    fn into_iter(self) -> vec::IntoIter<Tag> {
+        self.tags.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Tags {
+    type Item = &'a Tag;
+    type IntoIter = slice::Iter<'a, Tag>;
+
+    fn into_iter(self) -> slice::Iter<'a, Tag> {
+        self.tags.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn tags() -> Tags {
+        vec![
+            Tag::new("name", "Helsinki"),
+            Tag::new("name:fi", "Helsinki"),
+            Tag::new("name:sv", "Helsingfors"),
+            Tag::new("addr:street", "Mannerheimintie"),
+            Tag::new("cuisine", "pizza; kebab;;"),
+        ].into()
+    }
+
+    #[test]
+    fn get_and_contains() {
+        let tags = tags();
+        assert_eq!(tags.get("name:sv"), Some("Helsingfors"));
+        assert_eq!(tags.get("name:en"), None);
+        assert!(tags.contains("addr:street"));
+        assert!(!tags.contains("addr"));
+    }
+
+    #[test]
+    fn insert_keeps_position() {
+        let mut tags = tags();
+        assert_eq!(tags.insert("name:fi", "Stadi"), Some("Helsinki".to_string()));
+        assert_eq!(tags[1].val, "Stadi".to_string());
+
+        assert_eq!(tags.insert("highway", "primary"), None);
+        assert_eq!(tags[5].key, "highway".to_string());
+    }
+
+    #[test]
+    fn remove_tag() {
+        let mut tags = tags();
+        assert_eq!(tags.remove("name"), Some("Helsinki".to_string()));
+        assert_eq!(tags.remove("name"), None);
+        assert_eq!(tags.len(), 4);
+        assert_eq!(tags[0].key, "name:fi".to_string());
+    }
+
+    #[test]
+    fn push_refuses_duplicate_keys() {
+        let mut tags = tags();
+        assert!(tags.push(Tag::new("name", "Stadi")).is_err());
+        assert!(tags.push(Tag::new("highway", "primary")).is_ok());
+        assert_eq!(tags.get("name"), Some("Helsinki"));
+    }
+
+    #[test]
+    fn from_vec_drops_duplicates() {
+        let tags: Tags = vec![Tag::new("a", "1"), Tag::new("b", "2"), Tag::new("a", "3")].into();
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags.get("a"), Some("1"));
+    }
+
+    #[test]
+    fn namespace_iteration() {
+        let tags = tags();
+        let names: Vec<_> = tags.namespace("name").collect();
+        assert_eq!(names, vec![("fi", "Helsinki"), ("sv", "Helsingfors")]);
+
+        let prefixed: Vec<_> = tags.with_prefix("name").map(|tag| tag.key.as_str()).collect();
+        assert_eq!(prefixed, vec!["name", "name:fi", "name:sv"]);
+    }
+
+    #[test]
+    fn semicolon_values() {
+        let tags = tags();
+        assert_eq!(tags.values("cuisine").collect::<Vec<_>>(), vec!["pizza", "kebab"]);
+        assert_eq!(tags.values("name").collect::<Vec<_>>(), vec!["Helsinki"]);
+        assert_eq!(tags.values("missing").count(), 0);
+    }
+}
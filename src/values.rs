@@ -0,0 +1,325 @@
+//! Typed interpretation of commonly used tag values.
+//!
+//! Getters are implemented on `Tags` and return `None` when the tag is missing or its value
+//! cannot be interpreted, eg. `layer=high`.
+
+use tags::Tags;
+
+/// Travel direction restriction of a way, see `oneway=*`.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Oneway {
+    No,
+    Forward,
+    Backward,
+    Reversible,
+    Alternating,
+}
+
+/// Single `level=*` entry. Plain levels have equal `min` and `max`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct LevelRange {
+    pub min: f64,
+    pub max: f64,
+}
+
+macro_rules! tag_value_enum {
+    ($(#[$attr:meta])* pub enum $name:ident { $($variant:ident => $val:expr,)* }) => {
+        $(#[$attr])*
+        #[derive(Debug, PartialEq, Eq, Clone)]
+        pub enum $name {
+            $($variant,)*
+            Other(String),
+        }
+
+        impl $name {
+            pub fn from_value(val: &str) -> $name {
+                match val {
+                    $($val => $name::$variant,)*
+                    _ => $name::Other(val.to_string()),
+                }
+            }
+
+            pub fn as_str(&self) -> &str {
+                match *self {
+                    $($name::$variant => $val,)*
+                    $name::Other(ref val) => val,
+                }
+            }
+        }
+    }
+}
+
+tag_value_enum! {
+    /// Value of `highway=*`.
+    pub enum Highway {
+        Motorway => "motorway",
+        Trunk => "trunk",
+        Primary => "primary",
+        Secondary => "secondary",
+        Tertiary => "tertiary",
+        Unclassified => "unclassified",
+        Residential => "residential",
+        MotorwayLink => "motorway_link",
+        TrunkLink => "trunk_link",
+        PrimaryLink => "primary_link",
+        SecondaryLink => "secondary_link",
+        TertiaryLink => "tertiary_link",
+        LivingStreet => "living_street",
+        Service => "service",
+        Pedestrian => "pedestrian",
+        Track => "track",
+        BusGuideway => "bus_guideway",
+        Road => "road",
+        Footway => "footway",
+        Bridleway => "bridleway",
+        Steps => "steps",
+        Path => "path",
+        Cycleway => "cycleway",
+        Construction => "construction",
+        Platform => "platform",
+        BusStop => "bus_stop",
+        Crossing => "crossing",
+        TrafficSignals => "traffic_signals",
+        StreetLamp => "street_lamp",
+    }
+}
+
+tag_value_enum! {
+    /// Value of `building=*`.
+    pub enum Building {
+        Yes => "yes",
+        House => "house",
+        Detached => "detached",
+        Residential => "residential",
+        Apartments => "apartments",
+        Terrace => "terrace",
+        Commercial => "commercial",
+        Industrial => "industrial",
+        Retail => "retail",
+        Office => "office",
+        Warehouse => "warehouse",
+        Garage => "garage",
+        Garages => "garages",
+        Shed => "shed",
+        Hut => "hut",
+        Roof => "roof",
+        School => "school",
+        University => "university",
+        Hospital => "hospital",
+        Church => "church",
+        Public => "public",
+        Construction => "construction",
+    }
+}
+
+tag_value_enum! {
+    /// Value of `amenity=*`.
+    pub enum Amenity {
+        Restaurant => "restaurant",
+        Cafe => "cafe",
+        FastFood => "fast_food",
+        Bar => "bar",
+        Pub => "pub",
+        School => "school",
+        Kindergarten => "kindergarten",
+        University => "university",
+        Library => "library",
+        Hospital => "hospital",
+        Clinic => "clinic",
+        Doctors => "doctors",
+        Pharmacy => "pharmacy",
+        Bank => "bank",
+        Atm => "atm",
+        PostOffice => "post_office",
+        PostBox => "post_box",
+        Police => "police",
+        FireStation => "fire_station",
+        Townhall => "townhall",
+        PlaceOfWorship => "place_of_worship",
+        Parking => "parking",
+        BicycleParking => "bicycle_parking",
+        Fuel => "fuel",
+        Toilets => "toilets",
+        DrinkingWater => "drinking_water",
+        Bench => "bench",
+        WasteBasket => "waste_basket",
+        Recycling => "recycling",
+        Shelter => "shelter",
+    }
+}
+
+impl Tags {
+    /// Interprets `yes`, `true` and `1` as true and `no`, `false` and `0` as false.
+    pub fn get_bool(&self, key: &str) -> Option<bool> {
+        match self.get(key).map(|val| val.trim()) {
+            Some("yes") | Some("true") | Some("1") => Some(true),
+            Some("no") | Some("false") | Some("0") => Some(false),
+            _ => None,
+        }
+    }
+
+    pub fn get_int(&self, key: &str) -> Option<i64> {
+        self.get(key).and_then(|val| val.trim().parse().ok())
+    }
+
+    /// Direction restriction from `oneway=*`. When the tag is missing roundabouts, motorways and
+    /// motorway links are `Forward` and other ways `No`, `None` is only returned for unknown
+    /// values.
+    pub fn oneway(&self) -> Option<Oneway> {
+        match self.get("oneway").map(|val| val.trim()) {
+            Some("yes") | Some("true") | Some("1") => Some(Oneway::Forward),
+            Some("-1") | Some("reverse") => Some(Oneway::Backward),
+            Some("no") | Some("false") | Some("0") => Some(Oneway::No),
+            Some("reversible") => Some(Oneway::Reversible),
+            Some("alternating") => Some(Oneway::Alternating),
+            Some(_) => None,
+            None => {
+                let implied = self.get("junction") == Some("roundabout")
+                    || self.get("highway") == Some("motorway")
+                    || self.get("highway") == Some("motorway_link");
+                if implied {
+                    Some(Oneway::Forward)
+                } else {
+                    Some(Oneway::No)
+                }
+            }
+        }
+    }
+
+    /// Value of `layer=*`, missing tag means layer 0.
+    pub fn layer(&self) -> Option<i64> {
+        if !self.contains("layer") {
+            return Some(0);
+        }
+
+        self.get_int("layer")
+    }
+
+    /// Parses `level=*` lists and ranges like `0;1`, `-1-2` or `0.5`.
+    pub fn levels(&self) -> Option<Vec<LevelRange>> {
+        let mut levels = Vec::new();
+        for val in self.values("level") {
+            levels.push(parse_level_range(val)?);
+        }
+
+        if levels.is_empty() {
+            None
+        } else {
+            Some(levels)
+        }
+    }
+
+    pub fn highway(&self) -> Option<Highway> {
+        self.get("highway").map(Highway::from_value)
+    }
+
+    pub fn building(&self) -> Option<Building> {
+        self.get("building").map(Building::from_value)
+    }
+
+    pub fn amenity(&self) -> Option<Amenity> {
+        self.get("amenity").map(Amenity::from_value)
+    }
+}
+
+fn parse_level_range(val: &str) -> Option<LevelRange> {
+    // Range separator is the first dash which is not a sign of either number
+    let separator = val.char_indices()
+        .skip(1)
+        .find(|&(i, c)| c == '-' && val.as_bytes()[i - 1] != b'-')
+        .map(|(i, _)| i);
+
+    let (min, max) = match separator {
+        Some(i) => (
+            val[..i].trim().parse().ok()?,
+            val[i + 1..].trim().parse().ok()?,
+        ),
+        None => {
+            let level = val.parse().ok()?;
+            (level, level)
+        }
+    };
+
+    Some(LevelRange { min: min, max: max })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use elements::Tag;
+
+    fn tags(tags: &[(&str, &str)]) -> Tags {
+        tags.iter().map(|&(k, v)| Tag::new(k, v)).collect()
+    }
+
+    #[test]
+    fn boolean_values() {
+        let tags = tags(&[("lit", "yes"), ("bridge", "no"), ("tunnel", "culvert")]);
+        assert_eq!(tags.get_bool("lit"), Some(true));
+        assert_eq!(tags.get_bool("bridge"), Some(false));
+        assert_eq!(tags.get_bool("tunnel"), None);
+        assert_eq!(tags.get_bool("area"), None);
+    }
+
+    #[test]
+    fn oneway_values() {
+        assert_eq!(tags(&[("oneway", "-1")]).oneway(), Some(Oneway::Backward));
+        assert_eq!(tags(&[("oneway", "yes")]).oneway(), Some(Oneway::Forward));
+        assert_eq!(tags(&[("oneway", "reversible")]).oneway(), Some(Oneway::Reversible));
+        assert_eq!(tags(&[("oneway", "maybe")]).oneway(), None);
+        assert_eq!(tags(&[("highway", "primary")]).oneway(), Some(Oneway::No));
+    }
+
+    #[test]
+    fn implied_oneway() {
+        let roundabout = tags(&[("highway", "primary"), ("junction", "roundabout")]);
+        assert_eq!(roundabout.oneway(), Some(Oneway::Forward));
+        assert_eq!(tags(&[("highway", "motorway_link")]).oneway(), Some(Oneway::Forward));
+        assert_eq!(tags(&[]).oneway(), Some(Oneway::No));
+
+        let twoway_motorway = tags(&[("highway", "motorway"), ("oneway", "no")]);
+        assert_eq!(twoway_motorway.oneway(), Some(Oneway::No));
+    }
+
+    #[test]
+    fn layer_values() {
+        assert_eq!(tags(&[("layer", "-2")]).layer(), Some(-2));
+        assert_eq!(tags(&[]).layer(), Some(0));
+        assert_eq!(tags(&[("layer", "high")]).layer(), None);
+    }
+
+    #[test]
+    fn level_values() {
+        let levels = tags(&[("level", "0;1")]).levels().unwrap();
+        assert_eq!(
+            levels,
+            vec![LevelRange { min: 0.0, max: 0.0 }, LevelRange { min: 1.0, max: 1.0 }]
+        );
+
+        let levels = tags(&[("level", "-1-2")]).levels().unwrap();
+        assert_eq!(levels, vec![LevelRange { min: -1.0, max: 2.0 }]);
+
+        let levels = tags(&[("level", "-3--1")]).levels().unwrap();
+        assert_eq!(levels, vec![LevelRange { min: -3.0, max: -1.0 }]);
+
+        let levels = tags(&[("level", "0.5")]).levels().unwrap();
+        assert_eq!(levels, vec![LevelRange { min: 0.5, max: 0.5 }]);
+
+        assert_eq!(tags(&[("level", "ground")]).levels(), None);
+        assert_eq!(tags(&[]).levels(), None);
+    }
+
+    #[test]
+    fn enumerations() {
+        let tags = tags(&[
+            ("highway", "residential"),
+            ("building", "yes"),
+            ("amenity", "fountain"),
+        ]);
+        assert_eq!(tags.highway(), Some(Highway::Residential));
+        assert_eq!(tags.building(), Some(Building::Yes));
+        assert_eq!(tags.amenity(), Some(Amenity::Other("fountain".to_string())));
+        assert_eq!(tags.amenity().unwrap().as_str(), "fountain");
+        assert_eq!(Highway::LivingStreet.as_str(), "living_street");
+    }
+}
@@ -0,0 +1,123 @@
+extern crate osm_xml as osm;
+
+use std::fs::File;
+use osm::store::{self, ElementStore};
+use osm::{CompactElement, CompactOSM, ElementId, Location, OSMElement, UnresolvedReference,
+          OSM};
+
+fn parse_both(path: &str) -> (OSM, CompactOSM) {
+    let osm = OSM::parse(File::open(path).unwrap()).unwrap();
+    let compact = CompactOSM::parse(File::open(path).unwrap()).unwrap();
+    (osm, compact)
+}
+
+#[test]
+fn same_content_as_osm() {
+    for path in &[
+        "./tests/test_data/way.osm",
+        "./tests/test_data/relations.osm",
+        "./tests/test_data/relation_cycle.osm",
+    ] {
+        let (osm, compact) = parse_both(path);
+
+        assert_eq!(compact.bounds(), osm.bounds);
+        assert_eq!(compact.node_count(), osm.nodes.len());
+        assert_eq!(compact.way_count(), osm.ways.len());
+        assert_eq!(compact.relation_count(), osm.relations.len());
+
+        for element in osm.elements() {
+            let found = compact.get(element.element_id()).unwrap();
+            let mut expected = element.to_element();
+            match expected {
+                osm::Element::Node(ref mut node) => node.meta = Default::default(),
+                osm::Element::Way(ref mut way) => way.meta = Default::default(),
+                osm::Element::Relation(ref mut rel) => rel.meta = Default::default(),
+            }
+            assert_eq!(found.to_element(), expected);
+        }
+    }
+}
+
+#[test]
+fn exact_coordinates() {
+    let (osm, compact) = parse_both("./tests/test_data/way.osm");
+
+    for node in compact.nodes() {
+        let original = &osm.nodes[&node.id()];
+        assert_eq!(node.lat(), original.lat);
+        assert_eq!(node.lon(), original.lon);
+    }
+
+    assert_eq!(
+        compact.node(1375815878).unwrap().location(),
+        Location { lat: 60.1757315, lon: 24.9595858 }
+    );
+}
+
+#[test]
+fn lookups_and_order() {
+    let (_, compact) = parse_both("./tests/test_data/relations.osm");
+
+    let ids: Vec<_> = compact.ways().map(|way| way.id()).collect();
+    assert_eq!(ids, vec![22147620, 123365172]);
+    assert!(compact.node(1).is_none());
+
+    let way = compact.way(22147620).unwrap();
+    assert_eq!(way.nodes().len(), 7);
+    assert!(way.tags().is_empty());
+
+    let relation = compact.relation(375952).unwrap();
+    assert_eq!(relation.tags().get("name:sv"), Some("Salutorget"));
+    assert_eq!(relation.tags().len(), 6);
+    let roles: Vec<_> = relation.members().map(|(_, role)| role).collect();
+    assert_eq!(roles, vec!["passengers", "vehicle"]);
+
+    match compact.get(ElementId::Relation(1688359)) {
+        Some(CompactElement::Relation(rel)) => {
+            assert_eq!(rel.members().next(), Some((ElementId::Way(123365172), "outer")))
+        }
+        other => panic!("Unexpected element {:?}", other),
+    }
+}
+
+#[test]
+fn convert_from_osm() {
+    let (osm, compact) = parse_both("./tests/test_data/relations.osm");
+    let converted = CompactOSM::from(&osm);
+    assert_eq!(
+        CompactOSM::from_osm(&osm).unwrap().node_count(),
+        converted.node_count()
+    );
+
+    let ids: Vec<_> = converted.elements().map(|el| el.element_id()).collect();
+    let expected: Vec<_> = compact.elements().map(|el| el.element_id()).collect();
+    assert_eq!(ids, expected);
+
+    let back = converted.to_osm();
+    assert_eq!(back.nodes.len(), osm.nodes.len());
+    assert_eq!(back.relations[&375952].tags, osm.relations[&375952].tags);
+}
+
+#[test]
+fn store_api() {
+    let (osm, mut compact) = parse_both("./tests/test_data/relations.osm");
+
+    let ids = vec![ElementId::Relation(1688359)];
+    assert_eq!(
+        store::descendants(&compact, ids.clone()).unwrap(),
+        osm.descendants(ids.clone())
+    );
+    assert_eq!(store::parents(&compact, ids.clone()).unwrap(), osm.parents(ids));
+
+    let reference = UnresolvedReference::Way(123365172);
+    match store::resolve_reference(&compact, &reference).unwrap() {
+        store::StoredReference::Way(way) => assert_eq!(way.nodes, osm.ways[&123365172].nodes),
+        other => panic!("Unexpected reference {:?}", other),
+    }
+    assert_eq!(compact.ids(osm::ElementKind::Way).count(), osm.ways.len());
+
+    let way = compact.element(ElementId::Way(123365172)).unwrap().unwrap();
+    assert!(compact.insert(way).is_err());
+    assert!(compact.remove(ElementId::Way(123365172)).is_err());
+    assert!(ElementStore::way(&compact, 123365172).unwrap().is_some());
+}
@@ -0,0 +1,186 @@
+extern crate osm_xml as osm;
+
+use std::fs::File;
+
+use osm::error::Error;
+use osm::{DeleteMode, Element, ElementId, Member, OSMElement, Role, Tag, Tags,
+          UnresolvedReference, OSM};
+
+fn tags(key: &str, val: &str) -> Tags {
+    vec![Tag::new(key, val)].into()
+}
+
+#[test]
+fn build_new_document() {
+    let mut osm = OSM::new();
+    let a = osm.add_node(60.0, 25.0, Tags::new());
+    let b = osm.add_node(60.1, 25.1, tags("amenity", "bench"));
+    let way = osm.add_way(vec![a, b], tags("highway", "path")).unwrap();
+    let relation = osm.add_relation(
+        vec![
+            Member::Way(UnresolvedReference::Way(way), Role::from("")),
+            Member::Node(UnresolvedReference::Node(b), Role::from("stop")),
+        ],
+        tags("type", "route"),
+    ).unwrap();
+
+    assert_eq!((a, b, way, relation), (-1, -2, -3, -4));
+    assert_eq!(
+        osm.ways[&way].nodes,
+        vec![UnresolvedReference::Node(a), UnresolvedReference::Node(b)]
+    );
+    assert_eq!(osm.nodes[&b].tags.get("amenity"), Some("bench"));
+
+    match osm.add_way(vec![a, 5], Tags::new()) {
+        Err(Error::ElementNotFound(ElementId::Node(5))) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    let member = Member::Relation(UnresolvedReference::Relation(7), Role::from(""));
+    match osm.add_relation(vec![member], Tags::new()) {
+        Err(Error::ElementNotFound(ElementId::Relation(7))) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(osm.ways.len(), 1);
+    assert_eq!(osm.relations.len(), 1);
+}
+
+#[test]
+fn ways_need_two_distinct_nodes() {
+    let mut osm = OSM::new();
+    let a = osm.add_node(60.0, 25.0, Tags::new());
+    let b = osm.add_node(60.1, 25.1, Tags::new());
+
+    for nodes in [vec![], vec![a], vec![a, a], vec![a, a, a]].iter() {
+        match osm.add_way(nodes.clone(), Tags::new()) {
+            Err(Error::InvalidEdit(_)) => (),
+            other => panic!("unexpected {:?} for {:?}", other, nodes),
+        }
+    }
+    assert!(osm.ways.is_empty());
+
+    // Closed ways repeat the first node
+    assert_eq!(osm.add_way(vec![a, b, a], Tags::new()).unwrap(), -3);
+}
+
+#[test]
+fn placeholders_skip_existing_ids() {
+    let mut osm = OSM::new();
+    osm.insert(Element::Node(osm::Node {
+        id: -1,
+        lat: 0.0,
+        lon: 0.0,
+        tags: Tags::new(),
+        meta: Default::default(),
+    }));
+    assert_eq!(osm.add_node(1.0, 1.0, Tags::new()), -2);
+}
+
+#[test]
+fn tags_and_moves() {
+    let mut osm = OSM::parse(File::open("./tests/test_data/way.osm").unwrap()).unwrap();
+    let id = *osm.ways.keys().next().unwrap();
+
+    assert_eq!(osm.set_tag(ElementId::Way(id), "smoothness", "good").unwrap(), None);
+    assert_eq!(
+        osm.set_tag(ElementId::Way(id), "smoothness", "bad").unwrap(),
+        Some("good".to_string())
+    );
+    assert_eq!(osm.ways[&id].tags.get("smoothness"), Some("bad"));
+    assert_eq!(
+        osm.remove_tag(ElementId::Way(id), "smoothness").unwrap(),
+        Some("bad".to_string())
+    );
+    assert!(osm.set_tag(ElementId::Way(-99), "a", "b").is_err());
+
+    let node = *osm.nodes.keys().next().unwrap();
+    osm.move_node(node, 1.5, 2.5).unwrap();
+    assert_eq!((osm.nodes[&node].lat, osm.nodes[&node].lon), (1.5, 2.5));
+    assert!(osm.move_node(-99, 0.0, 0.0).is_err());
+}
+
+#[test]
+fn delete_refused_when_in_use() {
+    let mut osm = OSM::new();
+    let a = osm.add_node(0.0, 0.0, Tags::new());
+    let b = osm.add_node(0.0, 1.0, Tags::new());
+    let way = osm.add_way(vec![a, b], Tags::new()).unwrap();
+    let member = Member::Node(UnresolvedReference::Node(a), Role::from(""));
+    let relation = osm.add_relation(vec![member], Tags::new()).unwrap();
+
+    match osm.delete_node(a, DeleteMode::Refuse) {
+        Err(Error::ElementInUse(ElementId::Node(id), parents)) => {
+            assert_eq!(id, a);
+            assert_eq!(
+                parents,
+                vec![ElementId::Way(way), ElementId::Relation(relation)]
+            );
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+    assert!(osm.nodes.contains_key(&a));
+
+    let deleted = osm.delete_relation(relation, DeleteMode::Refuse).unwrap();
+    assert_eq!(deleted.len(), 1);
+    assert!(osm.delete_way(way, DeleteMode::Refuse).is_ok());
+    assert!(osm.delete_node(a, DeleteMode::Refuse).is_ok());
+    assert!(osm.delete_node(a, DeleteMode::Refuse).is_err());
+}
+
+#[test]
+fn delete_cascades_to_references() {
+    let mut osm = OSM::new();
+    let a = osm.add_node(0.0, 0.0, Tags::new());
+    let b = osm.add_node(0.0, 1.0, Tags::new());
+    let c = osm.add_node(1.0, 1.0, Tags::new());
+    let short = osm.add_way(vec![a, b], Tags::new()).unwrap();
+    let long = osm.add_way(vec![a, b, c], Tags::new()).unwrap();
+    let relation = osm.add_relation(
+        vec![
+            Member::Way(UnresolvedReference::Way(short), Role::from("")),
+            Member::Way(UnresolvedReference::Way(long), Role::from("")),
+            Member::Node(UnresolvedReference::Node(a), Role::from("label")),
+        ],
+        Tags::new(),
+    ).unwrap();
+
+    let deleted = osm.delete_node(a, DeleteMode::Cascade).unwrap();
+    let ids: Vec<ElementId> = deleted.iter().map(OSMElement::element_id).collect();
+    assert_eq!(ids, vec![ElementId::Node(a), ElementId::Way(short)]);
+
+    assert_eq!(
+        osm.ways[&long].nodes,
+        vec![UnresolvedReference::Node(b), UnresolvedReference::Node(c)]
+    );
+    assert_eq!(
+        osm.relations[&relation].members,
+        vec![Member::Way(UnresolvedReference::Way(long), Role::from(""))]
+    );
+}
+
+#[test]
+fn delete_cascade_keeps_way_shape() {
+    let mut osm = OSM::new();
+    let n: Vec<_> = (0..4)
+        .map(|i| osm.add_node(0.0, f64::from(i), Tags::new()))
+        .collect();
+    let (a, b, c, d) = (n[0], n[1], n[2], n[3]);
+    let refs = |ids: &[osm::Id]| -> Vec<UnresolvedReference> {
+        ids.iter().map(|&id| UnresolvedReference::Node(id)).collect()
+    };
+    let ring = osm.add_way(vec![a, b, c, d, a], tags("building", "yes")).unwrap();
+    let spur = osm.add_way(vec![b, a, b, c], Tags::new()).unwrap();
+    let triangle = osm.add_way(vec![b, c, d, b], tags("area", "yes")).unwrap();
+
+    // Rings stay closed and repeated nodes next to each other are merged
+    osm.delete_node(a, DeleteMode::Cascade).unwrap();
+    assert_eq!(osm.ways[&ring].nodes, refs(&[b, c, d, b]));
+    assert_eq!(osm.ways[&spur].nodes, refs(&[b, c]));
+
+    // Closed ways need three different nodes
+    let deleted = osm.delete_node(d, DeleteMode::Cascade).unwrap();
+    let ids: Vec<ElementId> = deleted.iter().map(OSMElement::element_id).collect();
+    assert_eq!(ids.len(), 3);
+    assert!(ids.contains(&ElementId::Way(ring)));
+    assert!(ids.contains(&ElementId::Way(triangle)));
+    assert_eq!(osm.ways.keys().collect::<Vec<_>>(), vec![&spur]);
+}
@@ -0,0 +1,87 @@
+extern crate osm_xml as osm;
+
+use std::fs::File;
+use osm::{Element, ElementId, ElementKind, ElementRef, OSMElement, OSM};
+
+#[test]
+fn node_metadata() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let meta = &osm.nodes[&345579225].meta;
+    assert_eq!(meta.version, Some(6));
+    assert_eq!(meta.changeset, Some(14224603));
+    assert_eq!(meta.timestamp, Some("2012-12-10T12:46:34Z".to_string()));
+    assert_eq!(meta.user, Some("woodpeck_repair".to_string()));
+    assert_eq!(meta.uid, Some(145231));
+    assert_eq!(meta.visible, Some(true));
+}
+
+#[test]
+fn way_metadata() {
+    let f = File::open("./tests/test_data/way.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let meta = &osm.ways[&4253174].meta;
+    assert_eq!(meta.version, Some(19));
+    assert_eq!(meta.user, Some("ij_".to_string()));
+}
+
+#[test]
+fn missing_metadata() {
+    let doc = r#"<osm version="0.6"><node id="1" lat="1.0" lon="2.0" version="x"/></osm>"#;
+    let osm = OSM::parse(doc.as_bytes()).unwrap();
+
+    assert_eq!(osm.nodes[&1].meta, osm::Metadata::default());
+}
+
+#[test]
+fn elements_iterates_all_kinds() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let total = osm.nodes.len() + osm.ways.len() + osm.relations.len();
+    assert_eq!(osm.elements().count(), total);
+
+    let relation_count = osm.elements()
+        .filter(|el| el.kind() == ElementKind::Relation)
+        .count();
+    assert_eq!(relation_count, osm.relations.len());
+}
+
+#[test]
+fn get_by_element_id() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    match osm.get(ElementId::Relation(77994)) {
+        Some(ElementRef::Relation(rel)) => assert_eq!(rel.id, 77994),
+        _ => panic!("Relation was not found!"),
+    }
+
+    let node = osm.get(ElementId::Node(345579224)).unwrap();
+    assert_eq!(node.id(), 345579224);
+    assert_eq!(node.element_id(), ElementId::Node(345579224));
+    assert_eq!(node.tags()[0].key, "man_made".to_string());
+
+    assert_eq!(osm.get(ElementId::Way(345579224)), None);
+}
+
+#[test]
+fn insert_and_remove_element() {
+    let f = File::open("./tests/test_data/two_nodes.osm").unwrap();
+    let mut osm = OSM::parse(f).unwrap();
+
+    let mut node = osm.nodes[&25496583].clone();
+    node.lat = 1.0;
+
+    match osm.insert(Element::Node(node)) {
+        Some(Element::Node(old)) => assert_eq!(old.lat, 51.5173639),
+        _ => panic!("Replaced node was not returned!"),
+    }
+    assert_eq!(osm.nodes[&25496583].lat, 1.0);
+
+    let removed = osm.remove(ElementId::Node(25496583)).unwrap();
+    assert_eq!(removed.element_id(), ElementId::Node(25496583));
+    assert!(osm.get(ElementId::Node(25496583)).is_none());
+}
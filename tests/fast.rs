@@ -0,0 +1,100 @@
+#![cfg(feature = "fast")]
+
+extern crate osm_xml as osm;
+
+use std::fs::File;
+use osm::{ParseOptions, ReaderBuilder, Symbols, OSM};
+
+fn assert_same(xml: OSM, fast: OSM) {
+    assert_eq!(xml.bounds, fast.bounds);
+    assert_eq!(xml.nodes, fast.nodes);
+    assert_eq!(xml.ways, fast.ways);
+    assert_eq!(xml.relations, fast.relations);
+    assert_eq!(xml.areas, fast.areas);
+    assert_eq!(xml.counts, fast.counts);
+}
+
+#[test]
+fn same_results_as_xml_rs() {
+    let files = [
+        "bounds.osm",
+        "bounds_invalid_coord.osm",
+        "bounds_missing_coord.osm",
+        "invalid_nodes.osm",
+        "overpass_geom.osm",
+        "relation_cycle.osm",
+        "relations.osm",
+        "two_nodes.osm",
+        "way.osm",
+    ];
+
+    for file in files.iter() {
+        let path = format!("./tests/test_data/{}", file);
+        let xml = OSM::parse(File::open(&path).unwrap()).unwrap();
+        let fast = OSM::parse_fast(File::open(&path).unwrap()).unwrap();
+        assert_same(xml, fast);
+    }
+}
+
+#[test]
+fn escapes_case_and_whitespace() {
+    let doc = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <!-- comment -->
+        <osm version="0.6">
+          <NODE id="1" lat="1.5" lon="-2.5" user="a &amp; b" visible="true"/>
+          <node id="2" lat = "1.0" lon="2.0">
+            <tag k="name" v="&lt;&#228;&gt;"/>
+            <tag k="note" v='single "quoted"'/>
+          </node>
+          <way id="3"><nd ref="1"/><nd ref="2"/><unknown/></way>
+          <relation id="4">
+            <member type="Node" ref="1" role=""/>
+          </relation>
+          <remark><![CDATA[runtime remark: ok]]></remark>
+        </osm>"#;
+
+    let xml = OSM::parse(doc.as_bytes()).unwrap();
+    let fast = OSM::parse_fast(doc.as_bytes()).unwrap();
+    assert_eq!(fast.nodes[&1].meta.user, Some("a & b".to_string()));
+    assert_eq!(fast.nodes[&2].tags.get("name"), Some("<ä>"));
+    assert!(fast.ways.is_empty());
+    assert_eq!(fast.relations.len(), 1);
+    assert_same(xml, fast);
+}
+
+#[test]
+fn runtime_error_and_broken_xml() {
+    let doc = r#"<osm><node id="1" lat="1.0" lon="2.0"/>
+        <remark> runtime error: Query timed out </remark></osm>"#;
+    match OSM::parse_fast(doc.as_bytes()) {
+        Err(osm::error::Error::RuntimeError(message)) => {
+            assert_eq!(message, "runtime error: Query timed out")
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+
+    let doc = r#"<osm><node id="1" lat="1.0" lon="2.0"></way></osm>"#;
+    assert!(OSM::parse_fast(doc.as_bytes()).is_err());
+}
+
+#[test]
+fn options_and_interning_with_fast_backend() {
+    let options = ParseOptions {
+        allow_keys: vec!["highway".to_string()],
+        untagged_nodes: false,
+        metadata: false,
+        ..ParseOptions::default()
+    };
+    let path = "./tests/test_data/way.osm";
+    let xml = OSM::parse_with_options(File::open(path).unwrap(), options.clone()).unwrap();
+    let fast = ReaderBuilder::new()
+        .fast()
+        .options(options)
+        .symbols(Symbols::new())
+        .parse(File::open(path).unwrap())
+        .unwrap();
+
+    assert_eq!(fast.nodes.len(), 2);
+    assert!(fast.symbols.get("highway").is_some());
+    assert_same(xml, fast);
+}
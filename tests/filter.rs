@@ -0,0 +1,83 @@
+extern crate osm_xml as osm;
+
+use std::fs::File;
+use osm::filter::Filter;
+use osm::{Element, ElementReader, OSM};
+
+#[test]
+fn apply_to_document() {
+    let f = File::open("./tests/test_data/way.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let filter: Filter = "n/highway=crossing".parse().unwrap();
+    let filtered = filter.apply(&osm);
+
+    assert_eq!(filtered.nodes.len(), 2);
+    assert!(filtered.nodes.contains_key(&391448656));
+    assert!(filtered.nodes.contains_key(&471408613));
+    assert!(filtered.ways.is_empty());
+}
+
+#[test]
+fn apply_with_references_completes_ways() {
+    let f = File::open("./tests/test_data/way.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let filter: Filter = "w/name=Maurinkatu".parse().unwrap();
+
+    let filtered = filter.apply(&osm);
+    assert_eq!(filtered.ways.len(), 1);
+    assert!(filtered.nodes.is_empty());
+
+    let completed = filter.apply_with_references(&osm);
+    assert_eq!(completed.ways.len(), 1);
+    assert_eq!(completed.nodes.len(), 7);
+}
+
+#[test]
+fn apply_with_references_completes_relations() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let filter: Filter = "r/type=multipolygon".parse().unwrap();
+    let completed = filter.apply_with_references(&osm);
+
+    assert_eq!(completed.relations.len(), 1);
+    assert!(completed.relations.contains_key(&1688359));
+    assert_eq!(completed.ways.len(), 2);
+    assert!(!completed.nodes.is_empty());
+}
+
+#[test]
+fn filter_element_stream() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let filter: Filter = "nw/ref=B".parse().unwrap();
+
+    let ids: Vec<_> = filter
+        .filter_stream(ElementReader::new(f))
+        .map(|element| match element.unwrap() {
+            Element::Node(node) => node.id,
+            _ => panic!("Only nodes have ref=B!"),
+        })
+        .collect();
+
+    assert_eq!(ids, vec![345579225, 1758939075]);
+}
+
+#[test]
+fn element_reader_streams_in_document_order() {
+    let f = File::open("./tests/test_data/way.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+    let f = File::open("./tests/test_data/way.osm").unwrap();
+    let mut reader = ElementReader::new(f);
+
+    let first = reader.next().unwrap().unwrap();
+    assert_eq!(first, Element::Node(osm.nodes[&1375815878].clone()));
+    assert_eq!(reader.bounds(), osm.bounds);
+
+    let rest: Vec<_> = reader.map(|element| element.unwrap()).collect();
+    match rest.last() {
+        Some(&Element::Way(ref way)) => assert_eq!(way.id, 4253123),
+        _ => panic!("Last element should have been a way!"),
+    }
+}
@@ -0,0 +1,158 @@
+extern crate osm_xml as osm;
+
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::PathBuf;
+
+use osm::index::{ElementIndex, IndexedFile};
+use osm::{ElementId, OSMElement, Reference, OSM};
+
+fn read(path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+/// Copy of the test file in the temp directory, so that the sidecar isn't written next to it.
+fn temp_copy(file: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("osm-xml-index-{}-{}", std::process::id(), file));
+    fs::copy(format!("./tests/test_data/{}", file), &path).unwrap();
+    path
+}
+
+#[test]
+fn index_has_offsets_of_all_elements() {
+    let data = read("./tests/test_data/relations.osm");
+    let osm = OSM::parse(&data[..]).unwrap();
+    let index = ElementIndex::build(&data[..]).unwrap();
+
+    assert_eq!(index.len(), osm.elements().count());
+    assert_eq!(index.source_len(), data.len() as u64);
+
+    for element in osm.elements() {
+        let offset = index.offset(element.element_id()).unwrap() as usize;
+        let tag = match element.element_id() {
+            ElementId::Node(_) => "<node",
+            ElementId::Way(_) => "<way",
+            ElementId::Relation(_) => "<relation",
+        };
+        assert!(data[offset..].starts_with(tag.as_bytes()));
+    }
+    assert_eq!(index.offset(ElementId::Node(1234567)), None);
+}
+
+#[test]
+fn index_written_and_read_back() {
+    let data = read("./tests/test_data/way.osm");
+    let index = ElementIndex::build(&data[..]).unwrap();
+
+    let mut written = Vec::new();
+    index.write_to(&mut written).unwrap();
+    assert_eq!(ElementIndex::read_from(&written[..]).unwrap(), index);
+
+    assert!(ElementIndex::read_from(&b"not an index"[..]).is_err());
+    assert!(ElementIndex::read_from(&written[..written.len() - 1]).is_err());
+}
+
+#[test]
+fn load_single_elements() {
+    let path = temp_copy("relations.osm");
+    let osm = OSM::parse(File::open(&path).unwrap()).unwrap();
+    let file = IndexedFile::open(&path).unwrap();
+
+    for (id, node) in &osm.nodes {
+        assert_eq!(file.node(*id).unwrap().as_ref(), Some(node));
+    }
+    for (id, way) in &osm.ways {
+        assert_eq!(file.way(*id).unwrap().as_ref(), Some(way));
+    }
+    for (id, relation) in &osm.relations {
+        assert_eq!(file.relation(*id).unwrap().as_ref(), Some(relation));
+    }
+    assert_eq!(file.node(1234567).unwrap(), None);
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(format!("{}.idx", path.display())).unwrap();
+}
+
+#[test]
+fn load_way_with_nodes() {
+    let path = temp_copy("way.osm");
+    let file = IndexedFile::open(&path).unwrap();
+
+    let osm = file.way_with_nodes(4253174).unwrap().unwrap();
+    let way = &osm.ways[&4253174];
+    assert_eq!(osm.ways.len(), 1);
+    assert!(!way.nodes.is_empty());
+    for reference in &way.nodes {
+        match osm.resolve_reference(reference) {
+            Reference::Node(_) => {}
+            _ => panic!("unresolved node"),
+        }
+    }
+    assert!(file.way_with_nodes(1).unwrap().is_none());
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(format!("{}.idx", path.display())).unwrap();
+}
+
+#[test]
+fn sidecar_is_reused() {
+    let path = temp_copy("way.osm");
+    let sidecar = PathBuf::from(format!("{}.idx", path.display()));
+
+    let index = IndexedFile::open(&path).unwrap().index().clone();
+    assert!(sidecar.exists());
+    assert_eq!(ElementIndex::load(&sidecar).unwrap(), index);
+    assert_eq!(IndexedFile::open(&path).unwrap().index(), &index);
+
+    // Unreadable sidecar is rebuilt
+    fs::write(&sidecar, b"garbage").unwrap();
+    assert_eq!(IndexedFile::open(&path).unwrap().index(), &index);
+    assert_eq!(ElementIndex::load(&sidecar).unwrap(), index);
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&sidecar).unwrap();
+}
+
+#[test]
+fn unwritable_sidecar_is_skipped() {
+    let path = temp_copy("way.osm");
+    // A directory in place of the sidecar can't be written, even by root
+    let sidecar = PathBuf::from(format!("{}.idx", path.display()));
+    fs::create_dir(&sidecar).unwrap();
+
+    let file = IndexedFile::open(&path).unwrap();
+    assert!(file.way(4253174).unwrap().is_some());
+    assert!(sidecar.is_dir());
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_dir(&sidecar).unwrap();
+}
+
+#[test]
+fn malformed_indexed_element_is_an_error() {
+    let path = env::temp_dir().join(format!("osm-xml-index-{}-malformed.osm", std::process::id()));
+    fs::write(
+        &path,
+        r#"<osm>
+          <node id="1" lat="north" lon="1.0"/>
+          <node id="2" lat="1.0" lon="1.0"/>
+          <way id="3"><nd ref="2"/><nd ref="x"/></way>
+          <way id="4"><nd ref="2"/></way>
+        </osm>"#,
+    ).unwrap();
+    let index = ElementIndex::build(File::open(&path).unwrap()).unwrap();
+    assert_eq!(index.len(), 4);
+    let file = IndexedFile::with_index(File::open(&path).unwrap(), index);
+
+    // The reader must not skip ahead to the next element
+    assert!(file.element(ElementId::Node(1)).is_err());
+    assert!(file.element(ElementId::Way(3)).is_err());
+    assert_eq!(file.node(2).unwrap().unwrap().id, 2);
+    assert_eq!(file.way(4).unwrap().unwrap().id, 4);
+    assert_eq!(file.node(5).unwrap(), None);
+
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,56 @@
+extern crate osm_xml as osm;
+
+use std::fs::File;
+use osm::locations::{DenseLocations, LocationAdder, LocationStore, SparseLocations};
+use osm::{Element, ElementReader, Location};
+
+#[test]
+fn add_locations_to_ways() {
+    let f = File::open("./tests/test_data/way.osm").unwrap();
+    let mut adder = LocationAdder::new(ElementReader::new(f), SparseLocations::new());
+
+    let ways: Vec<_> = adder
+        .by_ref()
+        .filter_map(|element| match element.unwrap() {
+            Element::Way(way) => Some(way),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(ways.len(), 2);
+
+    let maurinkatu = ways.iter().find(|way| way.id == 4253174).unwrap();
+    assert_eq!(maurinkatu.geometry.nodes.len(), maurinkatu.nodes.len());
+    assert!(maurinkatu.geometry.nodes.iter().all(|loc| loc.is_some()));
+    assert_eq!(
+        maurinkatu.geometry.nodes[0],
+        Some(Location { lat: 60.1757315, lon: 24.9595858 })
+    );
+
+    // Node 123456 is not in the document
+    let partial = ways.iter().find(|way| way.id == 4253123).unwrap();
+    assert_eq!(
+        partial.geometry.nodes,
+        vec![Some(Location { lat: 60.1757315, lon: 24.9595858 }), None]
+    );
+
+    assert_eq!(adder.into_store().len(), 7);
+}
+
+#[test]
+fn stores_are_interchangeable() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let mut store = DenseLocations::new().unwrap();
+
+    let count = LocationAdder::new(ElementReader::new(f), &mut store)
+        .filter(|element| match *element {
+            Ok(Element::Way(ref way)) => !way.geometry.nodes.is_empty(),
+            _ => false,
+        })
+        .count();
+    assert_eq!(count, 2);
+
+    assert_eq!(
+        store.get(345579224),
+        Some(Location { lat: 60.1674604, lon: 24.9626315 })
+    );
+}
@@ -0,0 +1,139 @@
+extern crate osm_xml as osm;
+
+use std::fs::File;
+use osm::locations::SparseLocations;
+use osm::multipolygon::{Multipolygon, MultipolygonCollector};
+use osm::ElementReader;
+
+fn assemble_bytes(doc: &str) -> Vec<Multipolygon> {
+    let mut collector = MultipolygonCollector::new();
+    collector
+        .read_relations(ElementReader::new(doc.as_bytes()))
+        .unwrap();
+
+    collector
+        .assemble(ElementReader::new(doc.as_bytes()), SparseLocations::new())
+        .map(|multipolygon| multipolygon.unwrap())
+        .collect()
+}
+
+#[test]
+fn assemble_from_file() {
+    let mut collector = MultipolygonCollector::new();
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    collector.read_relations(ElementReader::new(f)).unwrap();
+    assert_eq!(collector.len(), 1);
+
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let multipolygons: Vec<_> = collector
+        .assemble(ElementReader::new(f), SparseLocations::new())
+        .map(|multipolygon| multipolygon.unwrap())
+        .collect();
+
+    assert_eq!(multipolygons.len(), 1);
+    let multipolygon = &multipolygons[0];
+    assert_eq!(multipolygon.id, 1688359);
+    assert_eq!(multipolygon.tags.get("type"), Some("multipolygon"));
+    assert!(multipolygon.missing_ways.is_empty());
+    assert_eq!(multipolygon.polygons.len(), 1);
+    assert_eq!(multipolygon.polygons[0].outer.len(), 9);
+    assert_eq!(multipolygon.polygons[0].inners.len(), 1);
+    assert_eq!(multipolygon.polygons[0].inners[0].len(), 7);
+}
+
+const SHARED_BOUNDARY: &str = r#"<osm version="0.6">
+    <node id="1" lat="0.0" lon="0.0"/>
+    <node id="2" lat="0.0" lon="1.0"/>
+    <node id="3" lat="1.0" lon="1.0"/>
+    <node id="4" lat="1.0" lon="0.0"/>
+    <node id="5" lat="0.0" lon="2.0"/>
+    <node id="6" lat="1.0" lon="2.0"/>
+    <way id="10"><nd ref="2"/><nd ref="3"/></way>
+    <way id="11"><nd ref="3"/><nd ref="4"/><nd ref="1"/><nd ref="2"/></way>
+    <way id="12"><nd ref="2"/><nd ref="5"/><nd ref="6"/><nd ref="3"/></way>
+    <way id="13"><nd ref="1"/><nd ref="7"/></way>
+    <relation id="100">
+      <member type="way" ref="10" role="outer"/>
+      <member type="way" ref="11" role="outer"/>
+      <tag k="type" v="boundary"/>
+      <tag k="name" v="West"/>
+    </relation>
+    <relation id="101">
+      <member type="way" ref="10" role="outer"/>
+      <member type="way" ref="12" role="outer"/>
+      <tag k="type" v="multipolygon"/>
+      <tag k="name" v="East"/>
+    </relation>
+    <relation id="102">
+      <member type="way" ref="11" role="outer"/>
+      <member type="way" ref="14" role="outer"/>
+      <tag k="type" v="multipolygon"/>
+    </relation>
+    <relation id="103">
+      <member type="way" ref="13" role="outer"/>
+      <tag k="type" v="multipolygon"/>
+    </relation>
+    <relation id="104">
+      <member type="way" ref="10" role="outer"/>
+      <tag k="type" v="route"/>
+    </relation>
+  </osm>"#;
+
+#[test]
+fn emit_when_complete() {
+    let multipolygons = assemble_bytes(SHARED_BOUNDARY);
+    let ids: Vec<_> = multipolygons.iter().map(|m| m.id).collect();
+
+    // 100 completes at way 11, 101 at way 12 and 103 at way 13 even though its node 7 has no
+    // location. 102 refers to a missing way so it is emitted at the end.
+    assert_eq!(ids, vec![100, 101, 103, 102]);
+
+    assert_eq!(multipolygons[0].polygons.len(), 1);
+    assert_eq!(multipolygons[0].polygons[0].outer.len(), 5);
+    assert_eq!(multipolygons[1].polygons.len(), 1);
+    assert_eq!(multipolygons[1].polygons[0].outer.len(), 5);
+
+    assert_eq!(multipolygons[2].missing_ways, vec![13]);
+    assert_eq!(multipolygons[3].missing_ways, vec![14]);
+    assert!(multipolygons[3].polygons.is_empty());
+}
+
+#[test]
+fn ways_are_released_after_use() {
+    let mut collector = MultipolygonCollector::new();
+    collector
+        .read_relations(ElementReader::new(SHARED_BOUNDARY.as_bytes()))
+        .unwrap();
+
+    let mut assembled =
+        collector.assemble(ElementReader::new(SHARED_BOUNDARY.as_bytes()), SparseLocations::new());
+    assert_eq!(assembled.next().unwrap().unwrap().id, 100);
+    // Way 11 is still needed by relation 102, way 10 by relation 101
+    assert_eq!(assembled.pending_ways(), 2);
+    assert_eq!(assembled.next().unwrap().unwrap().id, 101);
+    assert_eq!(assembled.pending_ways(), 1);
+    assert_eq!(assembled.count(), 2);
+}
+
+#[test]
+fn duplicate_way_counts_once() {
+    let doc = r#"<osm version="0.6">
+        <node id="1" lat="0.0" lon="0.0"/>
+        <node id="2" lat="0.0" lon="1.0"/>
+        <node id="3" lat="1.0" lon="1.0"/>
+        <way id="10"><nd ref="1"/><nd ref="2"/></way>
+        <way id="10"><nd ref="1"/><nd ref="2"/></way>
+        <way id="11"><nd ref="2"/><nd ref="3"/><nd ref="1"/></way>
+        <relation id="100">
+          <member type="way" ref="10" role="outer"/>
+          <member type="way" ref="11" role="outer"/>
+          <tag k="type" v="multipolygon"/>
+        </relation>
+      </osm>"#;
+
+    let multipolygons = assemble_bytes(doc);
+    assert_eq!(multipolygons.len(), 1);
+    assert!(multipolygons[0].missing_ways.is_empty());
+    assert_eq!(multipolygons[0].polygons.len(), 1);
+    assert_eq!(multipolygons[0].polygons[0].outer.len(), 4);
+}
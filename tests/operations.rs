@@ -0,0 +1,177 @@
+extern crate osm_xml as osm;
+
+use osm::error::Error;
+use osm::{Id, Member, Role, Tag, Tags, UnresolvedReference, OSM};
+
+fn tags(list: &[(&str, &str)]) -> Tags {
+    list.iter()
+        .map(|&(key, val)| Tag::new(key, val))
+        .collect::<Vec<_>>()
+        .into()
+}
+
+fn node_refs(ids: &[Id]) -> Vec<UnresolvedReference> {
+    ids.iter().map(|&id| UnresolvedReference::Node(id)).collect()
+}
+
+fn way_member(id: Id, role: &str) -> Member {
+    Member::Way(UnresolvedReference::Way(id), Role::from(role))
+}
+
+/// Four nodes in a row and the ids of them.
+fn line() -> (OSM, Vec<Id>) {
+    let mut osm = OSM::new();
+    let nodes = (0..4)
+        .map(|i| osm.add_node(60.0, 25.0 + f64::from(i) * 0.01, Tags::new()))
+        .collect();
+    (osm, nodes)
+}
+
+#[test]
+fn split_way_updates_relations() {
+    let (mut osm, n) = line();
+    let extra = osm.add_node(61.0, 25.0, Tags::new());
+    let way = osm.add_way(n.clone(), tags(&[("highway", "primary")])).unwrap();
+    let before = osm.add_way(vec![extra, n[0]], Tags::new()).unwrap();
+    let after = osm.add_way(vec![n[3], extra], Tags::new()).unwrap();
+    let forward = osm.add_relation(
+        vec![way_member(before, ""), way_member(way, "forward")],
+        tags(&[("type", "route")]),
+    ).unwrap();
+    let backward = osm.add_relation(
+        vec![way_member(after, ""), way_member(way, "")],
+        tags(&[("type", "route")]),
+    ).unwrap();
+
+    let new_way = osm.split_way(way, n[1]).unwrap();
+
+    assert_eq!(osm.ways[&way].nodes, node_refs(&n[..2]));
+    assert_eq!(osm.ways[&new_way].nodes, node_refs(&n[1..]));
+    assert_eq!(osm.ways[&new_way].tags, osm.ways[&way].tags);
+    assert_eq!(
+        osm.relations[&forward].members,
+        vec![
+            way_member(before, ""),
+            way_member(way, "forward"),
+            way_member(new_way, "forward"),
+        ]
+    );
+    assert_eq!(
+        osm.relations[&backward].members,
+        vec![
+            way_member(after, ""),
+            way_member(new_way, ""),
+            way_member(way, ""),
+        ]
+    );
+
+    match osm.split_way(way, n[0]) {
+        Err(Error::InvalidEdit(_)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+}
+
+#[test]
+fn merge_ways_sharing_an_end() {
+    let (mut osm, n) = line();
+    let first = osm.add_way(vec![n[0], n[1]], tags(&[("highway", "residential")])).unwrap();
+    let second = osm.add_way(
+        vec![n[3], n[2], n[1]],
+        tags(&[("highway", "residential"), ("oneway", "-1")]),
+    ).unwrap();
+    let both = osm.add_relation(
+        vec![way_member(first, ""), way_member(second, "")],
+        Tags::new(),
+    ).unwrap();
+    let only_second = osm.add_relation(vec![way_member(second, "outer")], Tags::new()).unwrap();
+
+    osm.merge_ways(first, second).unwrap();
+
+    assert!(!osm.ways.contains_key(&second));
+    assert_eq!(osm.ways[&first].nodes, node_refs(&n));
+    assert_eq!(osm.ways[&first].tags.get("oneway"), Some("yes"));
+    assert_eq!(osm.relations[&both].members, vec![way_member(first, "")]);
+    assert_eq!(
+        osm.relations[&only_second].members,
+        vec![way_member(first, "outer")]
+    );
+
+    let other = osm.add_way(vec![n[0], n[2]], tags(&[("highway", "track")])).unwrap();
+    match osm.merge_ways(first, other) {
+        Err(Error::InvalidEdit(_)) => (),
+        other => panic!("unexpected {:?}", other),
+    }
+    let apart = osm.add_way(vec![n[1], n[3]], Tags::new()).unwrap();
+    assert!(osm.merge_ways(other, apart).is_err());
+}
+
+#[test]
+fn reverse_way_flips_direction() {
+    let (mut osm, n) = line();
+    let way = osm.add_way(
+        n.clone(),
+        tags(&[("oneway", "yes"), ("sidewalk:left", "yes"), ("incline", "up")]),
+    ).unwrap();
+    let route = osm.add_relation(vec![way_member(way, "backward")], Tags::new()).unwrap();
+
+    osm.reverse_way(way).unwrap();
+
+    let mut reversed = n.clone();
+    reversed.reverse();
+    assert_eq!(osm.ways[&way].nodes, node_refs(&reversed));
+    assert_eq!(
+        osm.ways[&way].tags,
+        tags(&[("oneway", "-1"), ("sidewalk:right", "yes"), ("incline", "down")])
+    );
+    assert_eq!(
+        osm.relations[&route].members,
+        vec![way_member(way, "forward")]
+    );
+}
+
+#[test]
+fn merge_duplicate_nodes() {
+    let (mut osm, n) = line();
+    let duplicate = osm.add_node(60.0, 25.0, tags(&[("barrier", "gate")]));
+    let way = osm.add_way(vec![n[0], duplicate, n[1]], Tags::new()).unwrap();
+    let short = osm.add_way(vec![n[0], duplicate], Tags::new()).unwrap();
+    let relation = osm.add_relation(
+        vec![
+            Member::Node(UnresolvedReference::Node(duplicate), Role::from("label")),
+            way_member(short, ""),
+        ],
+        Tags::new(),
+    ).unwrap();
+
+    osm.merge_nodes(n[0], vec![duplicate]).unwrap();
+
+    assert!(!osm.nodes.contains_key(&duplicate));
+    assert_eq!(osm.nodes[&n[0]].tags.get("barrier"), Some("gate"));
+    assert_eq!(osm.ways[&way].nodes, node_refs(&[n[0], n[1]]));
+    assert!(!osm.ways.contains_key(&short));
+    assert_eq!(
+        osm.relations[&relation].members,
+        vec![Member::Node(UnresolvedReference::Node(n[0]), Role::from("label"))]
+    );
+
+    osm.set_tag(osm::ElementId::Node(n[2]), "barrier", "bollard").unwrap();
+    assert!(osm.merge_nodes(n[0], vec![n[2]]).is_err());
+    assert!(osm.nodes.contains_key(&n[2]));
+}
+
+#[test]
+fn merge_nodes_removes_collapsed_rings() {
+    let (mut osm, n) = line();
+    let ring = osm
+        .add_way(vec![n[0], n[1], n[2], n[0]], tags(&[("building", "yes")]))
+        .unwrap();
+    let closing = osm.add_way(vec![n[0], n[1], n[2]], Tags::new()).unwrap();
+    let larger = osm.add_way(vec![n[0], n[1], n[3], n[2], n[0]], Tags::new()).unwrap();
+
+    // The open way becomes a ring, the rings are left with two and three nodes
+    osm.merge_nodes(n[0], vec![n[2]]).unwrap();
+    assert!(!osm.ways.contains_key(&ring));
+    assert!(!osm.ways.contains_key(&closing));
+    assert_eq!(osm.ways[&larger].nodes, node_refs(&[n[0], n[1], n[3], n[0]]));
+    assert!(!osm.nodes.contains_key(&n[2]));
+}
@@ -0,0 +1,118 @@
+extern crate osm_xml as osm;
+
+use std::fs::File;
+use osm::overpass::Query;
+use osm::{ElementId, OSMElement, OSM};
+
+fn parse(path: &str) -> OSM {
+    let f = File::open(path).unwrap();
+    OSM::parse(f).unwrap()
+}
+
+#[test]
+fn tag_filters_and_out() {
+    let osm = parse("./tests/test_data/way.osm");
+
+    let result = osm.query("node[highway=crossing]; out;").unwrap();
+    assert_eq!(result.nodes.len(), 2);
+    assert!(result.nodes.contains_key(&391448656));
+    assert!(result.nodes.contains_key(&471408613));
+
+    let result = osm.query("way[\"name:sv\"~\"^mauritz\",i]; out;").unwrap();
+    assert_eq!(result.ways.len(), 1);
+    assert!(result.ways.contains_key(&4253174));
+
+    let result = osm.query("way[!highway]; out;").unwrap();
+    assert_eq!(result.ways.len(), 1);
+    assert!(result.ways.contains_key(&4253123));
+}
+
+#[test]
+fn nothing_is_returned_without_out() {
+    let osm = parse("./tests/test_data/way.osm");
+
+    let result = osm.query("node[highway=crossing];").unwrap();
+    assert!(result.nodes.is_empty());
+}
+
+#[test]
+fn bbox_and_around() {
+    let osm = parse("./tests/test_data/way.osm");
+
+    let result = osm.query("node(60.1751,24.95,60.1760,24.97); out;").unwrap();
+    assert_eq!(result.nodes.len(), 5);
+
+    let result = osm.query("[bbox:60.1751,24.95,60.1760,24.97]; way(bbox); out;")
+        .unwrap();
+    assert_eq!(result.ways.len(), 2);
+
+    // Global bbox restricts queries without `(bbox)` too
+    let result = osm.query("[bbox:60.1751,24.95,60.1760,24.97]; node; out;").unwrap();
+    assert_eq!(result.nodes.len(), 5);
+    assert!(osm.query("way(bbox); out;").is_err());
+
+    // Crossing at 391448656 is about 7 meters from 1375815878
+    let result = osm.query("node(around:10,60.1757315,24.9595858); out;").unwrap();
+    assert_eq!(result.nodes.len(), 2);
+
+    let result = osm.query(
+        "node(id:1376857625)->.end; node(around.end:20)[highway]; out;",
+    ).unwrap();
+    assert_eq!(result.nodes.len(), 1);
+    assert!(result.nodes.contains_key(&471408613));
+}
+
+#[test]
+fn union_difference_and_recursion() {
+    let osm = parse("./tests/test_data/relations.osm");
+
+    let result = osm.query("relation[type=multipolygon]; >; out;").unwrap();
+    assert!(result.relations.is_empty());
+    assert_eq!(result.ways.len(), 2);
+
+    let result = osm.query("relation[type=multipolygon]; >>; out;").unwrap();
+    assert_eq!(result.ways.len(), 2);
+    assert_eq!(result.nodes.len(), 14);
+
+    let result = osm.query(
+        "relation[type=multipolygon]->.mp; \
+         (way(r.mp); - way[building];); out;",
+    ).unwrap();
+    assert_eq!(result.ways.len(), 1);
+    assert!(result.ways.contains_key(&22147620));
+
+    let result = osm.query("node[entrance]; way(bn); <; out;").unwrap();
+    assert_eq!(result.relations.len(), 1);
+    assert!(result.relations.contains_key(&1688359));
+}
+
+#[test]
+fn execute_elements_in_output_order() {
+    let osm = parse("./tests/test_data/relations.osm");
+
+    let query: Query = "rel[type=multipolygon]->.mp; way(r.mp); out; .mp out;"
+        .parse()
+        .unwrap();
+    let ids: Vec<ElementId> = query
+        .execute_elements(&osm)
+        .iter()
+        .map(|element| element.element_id())
+        .collect();
+
+    assert_eq!(
+        ids,
+        vec![
+            ElementId::Way(22147620),
+            ElementId::Way(123365172),
+            ElementId::Relation(1688359),
+        ]
+    );
+}
+
+#[test]
+fn malformed_query() {
+    let osm = parse("./tests/test_data/way.osm");
+    assert!(osm.query("node[highway=crossing]").is_err());
+    assert!(osm.query("node(around:abc); out;").is_err());
+    assert!(osm.query("node[name~\"(\"]; out;").is_err());
+}
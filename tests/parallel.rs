@@ -0,0 +1,132 @@
+extern crate osm_xml as osm;
+
+use std::fs::File;
+use std::io::Read;
+use osm::{ParseOptions, ReaderBuilder, Symbols, OSM};
+
+fn assert_same(sequential: &OSM, parallel: &OSM) {
+    assert_eq!(sequential.bounds, parallel.bounds);
+    assert_eq!(sequential.nodes, parallel.nodes);
+    assert_eq!(sequential.ways, parallel.ways);
+    assert_eq!(sequential.relations, parallel.relations);
+    assert_eq!(sequential.areas, parallel.areas);
+    assert_eq!(sequential.counts, parallel.counts);
+}
+
+#[test]
+fn same_results_as_sequential_parse() {
+    let files = [
+        "bounds.osm",
+        "bounds_invalid_coord.osm",
+        "invalid_nodes.osm",
+        "overpass_geom.osm",
+        "relations.osm",
+        "way.osm",
+    ];
+
+    for file in files.iter() {
+        let mut data = Vec::new();
+        File::open(format!("./tests/test_data/{}", file))
+            .unwrap()
+            .read_to_end(&mut data)
+            .unwrap();
+        let sequential = OSM::parse(&data[..]).unwrap();
+
+        for threads in 1..9 {
+            let parallel = OSM::parse_parallel(&data, threads).unwrap();
+            assert_same(&sequential, &parallel);
+        }
+    }
+
+    let sequential = OSM::parse(File::open("./tests/test_data/way.osm").unwrap()).unwrap();
+    let parallel = OSM::parse_file_parallel("./tests/test_data/way.osm", 0).unwrap();
+    assert_same(&sequential, &parallel);
+}
+
+#[test]
+fn later_duplicates_and_bounds_win() {
+    let doc = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <osm version="0.6">
+          <bounds minlat="1.0" minlon="1.0" maxlat="2.0" maxlon="2.0"/>
+          <node id="1" lat="1.0" lon="1.0"><tag k="name" v="first"/></node>
+          <node id="2" lat="1.0" lon="1.0"/>
+          <node id="3" lat="1.0" lon="1.0"/>
+          <node id="1" lat="1.5" lon="1.5"><tag k="name" v="second"/></node>
+          <bounds minlat="1.0"/>
+          <node id="4" lat="1.0" lon="1.0"/>
+        </osm>"#;
+
+    let sequential = OSM::parse(doc.as_bytes()).unwrap();
+    let parallel = OSM::parse_parallel(doc.as_bytes(), 8).unwrap();
+    assert_eq!(parallel.nodes[&1].tags.get("name"), Some("second"));
+    assert_eq!(parallel.bounds, None);
+    assert_same(&sequential, &parallel);
+}
+
+#[test]
+fn errors_are_reported() {
+    let doc = r#"<osm>
+        <node id="1" lat="1.0" lon="1.0"/>
+        <node id="2" lat="1.0" lon="1.0"/>
+        <node id="3" lat="1.0" lon="1.0"></way>
+        <node id="4" lat="1.0" lon="1.0"/>
+      </osm>"#;
+    assert!(OSM::parse_parallel(doc.as_bytes(), 4).is_err());
+}
+
+#[test]
+fn configuration_applies_to_chunks() {
+    let mut data = Vec::new();
+    File::open("./tests/test_data/way.osm")
+        .unwrap()
+        .read_to_end(&mut data)
+        .unwrap();
+    let builder = ReaderBuilder::new()
+        .options(ParseOptions {
+            untagged_nodes: false,
+            ..ParseOptions::default()
+        })
+        .symbols(Symbols::new());
+
+    let sequential = builder.parse(&data[..]).unwrap();
+    let parallel = builder.parse_parallel(&data, 4).unwrap();
+    assert_same(&sequential, &parallel);
+    assert_eq!(parallel.nodes.len(), 2);
+
+    // Strings of all chunks are interned to the same table
+    let highway = parallel.symbols.get("highway").unwrap();
+    let keys: Vec<_> = parallel
+        .nodes
+        .values()
+        .map(|node| &node.tags)
+        .chain(parallel.ways.values().map(|way| &way.tags))
+        .filter_map(|tags| tags.iter().find(|tag| tag.key == "highway"))
+        .map(|tag| tag.key.clone())
+        .collect();
+    assert!(keys.len() > 1);
+    assert!(keys.iter().all(|key| osm::Str::ptr_eq(key, &highway)));
+}
+
+#[test]
+fn duplicate_tags_are_interned() {
+    let doc = r#"<osm>
+        <node id="1" lat="1.0" lon="1.0"><tag k="name" v="a"/><tag k="name" v="b"/></node>
+        <node id="2" lat="1.0" lon="1.0"><tag k="name" v="c"/></node>
+        <node id="3" lat="1.0" lon="1.0"><tag k="name" v="d"/><tag k="name" v="b"/></node>
+      </osm>"#;
+    let builder = ReaderBuilder::new().symbols(Symbols::new());
+
+    let sequential = builder.parse(doc.as_bytes()).unwrap();
+    let parallel = builder.parse_parallel(doc.as_bytes(), 4).unwrap();
+    assert_eq!(parallel.duplicate_tags, sequential.duplicate_tags);
+    assert_eq!(parallel.duplicate_tags.len(), 2);
+
+    let name = parallel.symbols.get("name").unwrap();
+    let b = parallel.symbols.get("b").unwrap();
+    for duplicate in &parallel.duplicate_tags {
+        let tag = &duplicate.1;
+        assert!(osm::Str::ptr_eq(&tag.key, &name));
+        assert!(osm::Str::ptr_eq(&tag.val, &b));
+    }
+    assert!(osm::Str::ptr_eq(&parallel.nodes[&2].tags.iter().next().unwrap().key, &name));
+}
@@ -1,7 +1,7 @@
 extern crate osm_xml as osm;
 
 use std::fs::File;
-use osm::OSM;
+use osm::{ElementKind, Location, Metadata, ParseOptions, OSM};
 
 #[test]
 fn bounds_parsing() {
@@ -401,3 +401,168 @@ fn relation_with_unresolvable_node() {
     }
 }
 
+
+#[test]
+fn duplicate_tag_keys_keep_first_value() {
+    let doc = r#"<osm version="0.6">
+        <node id="1" lat="1.0" lon="2.0">
+          <tag k="name" v="first"/>
+          <tag k="name" v="second"/>
+          <tag k="amenity" v="bench"/>
+        </node>
+      </osm>"#;
+    let osm = OSM::parse(doc.as_bytes()).unwrap();
+
+    let tags = &osm.nodes[&1].tags;
+    assert_eq!(tags.len(), 2);
+    assert_eq!(tags.get("name"), Some("first"));
+    assert_eq!(tags.get("amenity"), Some("bench"));
+    assert_eq!(
+        osm.duplicate_tags,
+        vec![(osm::ElementId::Node(1), osm::Tag::new("name", "second"))]
+    );
+}
+
+#[test]
+fn overpass_inline_geometry() {
+    let f = File::open("./tests/test_data/overpass_geom.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+    assert_eq!(osm.ways.len(), 2);
+    assert_eq!(osm.relations.len(), 1);
+
+    let way = &osm.ways[&4253174];
+    assert_eq!(way.nodes.len(), 3);
+    assert_eq!(way.geometry.bounds.unwrap().maxlat, 60.1757315);
+    assert_eq!(
+        way.geometry.nodes,
+        vec![
+            Some(Location { lat: 60.1757315, lon: 24.9595858 }),
+            Some(Location { lat: 60.1756727, lon: 24.9596021 }),
+            None,
+        ]
+    );
+
+    let way = &osm.ways[&4253123];
+    assert_eq!(way.geometry.center, Some(Location { lat: 60.175, lon: 24.959 }));
+    assert!(way.geometry.nodes.is_empty());
+
+    let relation = &osm.relations[&1688359];
+    assert_eq!(relation.members.len(), 3);
+    assert_eq!(relation.geometry.members.len(), 3);
+    assert_eq!(relation.geometry.members[0].len(), 3);
+    assert_eq!(
+        relation.geometry.members[1],
+        vec![Some(Location { lat: 60.1674604, lon: 24.9626315 })]
+    );
+    assert!(relation.geometry.members[2].is_empty());
+}
+
+#[test]
+fn plain_osm_has_no_geometry() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+    assert!(osm.ways.values().all(|way| way.geometry.is_empty()));
+    assert!(osm.relations.values().all(|rel| rel.geometry.is_empty()));
+}
+
+#[test]
+fn overpass_areas_and_counts() {
+    let f = File::open("./tests/test_data/overpass_geom.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    assert_eq!(osm.areas.len(), 1);
+    assert_eq!(osm.areas[&3600034914].tags.get("name"), Some("Helsinki"));
+
+    assert_eq!(osm.counts.len(), 1);
+    assert_eq!(osm.counts[0].ways, 2);
+    assert_eq!(osm.counts[0].total, 4);
+}
+
+#[test]
+fn overpass_runtime_error() {
+    let doc = r#"<osm version="0.6" generator="Overpass API">
+        <node id="1" lat="1.0" lon="2.0"/>
+        <remark> runtime error: Query timed out in "query" at line 3 after 26 seconds. </remark>
+      </osm>"#;
+
+    match OSM::parse(doc.as_bytes()) {
+        Err(osm::error::Error::RuntimeError(message)) => {
+            assert!(message.starts_with("runtime error: Query timed out"))
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+
+    let doc = r#"<osm version="0.6" generator="Overpass API">
+        <remark> runtime remark: Timeout is 10 and maxsize is 536870912. </remark>
+        <node id="1" lat="1.0" lon="2.0"/>
+      </osm>"#;
+    assert_eq!(OSM::parse(doc.as_bytes()).unwrap().nodes.len(), 1);
+}
+
+#[test]
+fn interned_tags_share_symbols() {
+    let f = File::open("./tests/test_data/way.osm").unwrap();
+    let osm = OSM::parse_interned(f).unwrap();
+
+    let highway = osm.symbols.get("highway").unwrap();
+    let keys: Vec<&osm::Str> = osm.nodes
+        .values()
+        .flat_map(|node| node.tags.iter())
+        .chain(osm.ways.values().flat_map(|way| way.tags.iter()))
+        .map(|tag| &tag.key)
+        .filter(|key| *key == "highway")
+        .collect();
+    assert!(keys.len() > 1);
+    assert!(keys.iter().all(|key| osm::Str::ptr_eq(key, &highway)));
+    assert_eq!(osm.ways[&4253174].tags.get_symbol(&highway), Some(&"residential".into()));
+
+    let f = File::open("./tests/test_data/way.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+    assert!(osm.symbols.is_empty());
+}
+
+#[test]
+fn parse_options_element_kinds() {
+    let options = ParseOptions {
+        kinds: vec![ElementKind::Relation],
+        ..ParseOptions::default()
+    };
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let osm = OSM::parse_with_options(f, options).unwrap();
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let full = OSM::parse(f).unwrap();
+
+    assert!(osm.nodes.is_empty());
+    assert!(osm.ways.is_empty());
+    assert_eq!(osm.relations, full.relations);
+}
+
+#[test]
+fn parse_options_tags_and_metadata() {
+    let options = ParseOptions {
+        allow_keys: vec!["highway".to_string(), "name*".to_string()],
+        deny_keys: vec!["name:*".to_string()],
+        untagged_nodes: false,
+        metadata: false,
+        ..ParseOptions::default()
+    };
+    let f = File::open("./tests/test_data/way.osm").unwrap();
+    let osm = OSM::parse_with_options(f, options).unwrap();
+
+    assert_eq!(osm.nodes.len(), 2);
+    assert!(osm.nodes.values().all(|node| node.tags.len() == 1));
+    assert!(osm.nodes.values().all(|node| node.meta == Metadata::default()));
+
+    let keys: Vec<&str> = osm.ways[&4253174].tags.iter().map(|tag| tag.key.as_str()).collect();
+    assert_eq!(keys, vec!["highway", "name"]);
+    assert_eq!(osm.ways[&4253174].nodes.len(), 7);
+
+    let options = ParseOptions {
+        tags: false,
+        ..ParseOptions::default()
+    };
+    let f = File::open("./tests/test_data/way.osm").unwrap();
+    let osm = OSM::parse_with_options(f, options).unwrap();
+    assert!(osm.ways.values().all(|way| way.tags.is_empty()));
+    assert!(osm.nodes.values().all(|node| node.meta.version.is_some()));
+}
@@ -0,0 +1,144 @@
+extern crate osm_xml as osm;
+
+use std::fs::File;
+use osm::{ElementId, IdSet, Member, OSM};
+
+fn sorted<I: IntoIterator<Item = ElementId>>(ids: I) -> Vec<ElementId> {
+    let mut ids: Vec<_> = ids.into_iter().collect();
+    ids.sort();
+    ids
+}
+
+#[test]
+fn children_of_way() {
+    let f = File::open("./tests/test_data/relation_cycle.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let children = osm.children(vec![ElementId::Way(10)]);
+    assert_eq!(
+        sorted(children),
+        vec![ElementId::Node(1), ElementId::Node(2), ElementId::Node(3)]
+    );
+}
+
+#[test]
+fn children_of_relation_skip_sub_relations() {
+    let f = File::open("./tests/test_data/relation_cycle.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let children = osm.children(vec![ElementId::Relation(100)]);
+    assert_eq!(
+        sorted(children),
+        vec![
+            ElementId::Node(1),
+            ElementId::Node(2),
+            ElementId::Node(3),
+            ElementId::Way(10),
+        ]
+    );
+}
+
+#[test]
+fn descendants_with_cycle() {
+    let f = File::open("./tests/test_data/relation_cycle.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let descendants = osm.descendants(vec![ElementId::Relation(101)]);
+    assert_eq!(
+        sorted(descendants),
+        vec![
+            ElementId::Node(1),
+            ElementId::Node(2),
+            ElementId::Node(3),
+            ElementId::Way(10),
+            ElementId::Relation(100),
+            ElementId::Relation(101),
+            ElementId::Relation(102),
+        ]
+    );
+}
+
+#[test]
+fn descendants_skip_missing_elements() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    assert!(osm.descendants(vec![ElementId::Relation(987654)]).is_empty());
+}
+
+#[test]
+fn parents_of_node() {
+    let f = File::open("./tests/test_data/relation_cycle.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let parents = osm.parents(vec![ElementId::Node(3)]);
+    assert_eq!(sorted(parents), vec![ElementId::Way(10), ElementId::Relation(101)]);
+
+    assert!(osm.parents(vec![ElementId::Relation(102)]).is_empty());
+}
+
+#[test]
+fn ancestors_of_node() {
+    let f = File::open("./tests/test_data/relation_cycle.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let ancestors = osm.ancestors(vec![ElementId::Node(1)]);
+    assert_eq!(
+        sorted(ancestors),
+        vec![
+            ElementId::Way(10),
+            ElementId::Relation(100),
+            ElementId::Relation(101),
+            ElementId::Relation(102),
+        ]
+    );
+}
+
+#[test]
+fn subset_of_descendants() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let mut ids = osm.descendants(vec![ElementId::Relation(1688359)]);
+    ids.insert(ElementId::Relation(1688359));
+    let subset = osm.subset(&ids);
+
+    assert_eq!(subset.relations.len(), 1);
+    assert_eq!(subset.ways.len(), 2);
+    assert_eq!(subset.nodes.len(), ids.len() - 3);
+    assert_eq!(subset.bounds, osm.bounds);
+    assert_eq!(subset.ways[&22147620], osm.ways[&22147620]);
+}
+
+#[test]
+fn multi_pass_with_id_sets() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+
+    let mut way_ids = IdSet::new();
+    let mut node_ids = IdSet::new();
+    for relation in osm.relations.values() {
+        for member in &relation.members {
+            match *member {
+                Member::Node(reference, _) => node_ids.insert(ElementId::from(reference).id()),
+                Member::Way(reference, _) => way_ids.insert(ElementId::from(reference).id()),
+                Member::Relation(..) => false,
+            };
+        }
+    }
+
+    for id in &way_ids {
+        if let Some(way) = osm.ways.get(&id) {
+            node_ids.extend(way.nodes.iter().map(|node| ElementId::from(*node).id()));
+        }
+    }
+
+    let descendants = osm.descendants(osm.relations.keys().map(|&id| ElementId::Relation(id)));
+    assert_eq!(descendants.ways, way_ids.intersection(&descendants.ways));
+    assert_eq!(
+        descendants.nodes,
+        node_ids.intersection(&osm.nodes.keys().cloned().collect())
+    );
+    assert!(!descendants.ways.is_empty());
+    assert!(descendants.iter().all(|id| osm.get(id).is_some()));
+}
@@ -0,0 +1,108 @@
+extern crate osm_xml as osm;
+
+use std::fs::File;
+use osm::store::StoredReference;
+use osm::{ElementId, ElementRef, UnresolvedReference, OSM};
+
+#[test]
+fn way_nodes_are_resolved() {
+    let f = File::open("./tests/test_data/way.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+    let resolved = osm.resolve();
+
+    let way = resolved.way(4253174).unwrap();
+    assert_eq!(way.way.id, 4253174);
+    assert_eq!(way.nodes.len(), 7);
+    assert_eq!(*way.nodes[0], osm.nodes[&1375815878]);
+    assert_eq!(*way.nodes[6], osm.nodes[&1376857625]);
+}
+
+#[test]
+fn relation_members_are_resolved() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+    let resolved = osm.resolve();
+
+    let relation = resolved.relation(1688359).unwrap();
+    assert_eq!(relation.members.len(), 2);
+    assert_eq!(relation.members[0].role, "outer");
+    match relation.members[0].reference {
+        StoredReference::Way(ref way) => assert_eq!(way.id, 123365172),
+        _ => panic!("Member should have been resolved to Way!"),
+    }
+}
+
+#[test]
+fn missing_references_are_reported() {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+    let resolved = osm.resolve();
+
+    let missing: Vec<_> = resolved
+        .missing()
+        .iter()
+        .filter(|missing| missing.parent == ElementId::Relation(987654))
+        .map(|missing| missing.reference)
+        .collect();
+
+    assert_eq!(
+        missing,
+        vec![
+            UnresolvedReference::Node(12345),
+            UnresolvedReference::Way(23456),
+            UnresolvedReference::Relation(345678),
+        ]
+    );
+}
+
+#[test]
+fn missing_way_nodes_are_skipped() {
+    let f = File::open("./tests/test_data/relation_cycle.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+    let resolved = osm.resolve();
+
+    let ids: Vec<_> = resolved.way(10).unwrap().nodes.iter().map(|n| n.id).collect();
+    assert_eq!(ids, vec![1, 2, 3]);
+    assert_eq!(resolved.missing().len(), 1);
+    assert_eq!(resolved.missing()[0].parent, ElementId::Way(10));
+    assert_eq!(resolved.missing()[0].reference, UnresolvedReference::Node(4));
+}
+
+#[test]
+fn recursive_members_terminate_on_cycles() {
+    let f = File::open("./tests/test_data/relation_cycle.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+    let resolved = osm.resolve();
+
+    let members: Vec<_> = resolved
+        .recursive_members(100)
+        .map(|member| match member {
+            ElementRef::Node(node) => ElementId::Node(node.id),
+            ElementRef::Way(way) => ElementId::Way(way.id),
+            ElementRef::Relation(rel) => ElementId::Relation(rel.id),
+        })
+        .collect();
+
+    assert_eq!(
+        members,
+        vec![
+            ElementId::Way(10),
+            ElementId::Relation(101),
+            ElementId::Node(3),
+            ElementId::Relation(100),
+            ElementId::Relation(102),
+            ElementId::Node(1),
+        ]
+    );
+}
+
+#[test]
+fn cycle_detection() {
+    let f = File::open("./tests/test_data/relation_cycle.osm").unwrap();
+    let osm = OSM::parse(f).unwrap();
+    let resolved = osm.resolve();
+
+    assert!(resolved.is_cyclic(100));
+    assert!(resolved.is_cyclic(101));
+    assert!(!resolved.is_cyclic(102));
+}
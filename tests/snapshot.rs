@@ -0,0 +1,110 @@
+extern crate osm_xml as osm;
+
+use std::env;
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+use osm::error::{Error, ErrorReason};
+use osm::snapshot::Snapshot;
+use osm::{ElementId, OSM};
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("osm-xml-snapshot-{}-{}", std::process::id(), name))
+}
+
+fn parse(file: &str) -> OSM {
+    OSM::parse(File::open(format!("./tests/test_data/{}", file)).unwrap()).unwrap()
+}
+
+#[test]
+fn snapshot_round_trip() {
+    for file in ["bounds.osm", "relations.osm", "two_nodes.osm", "way.osm"].iter() {
+        let osm = parse(file);
+        let path = temp_path(file);
+        osm.save_snapshot(&path).unwrap();
+
+        let loaded = OSM::load_snapshot(&path).unwrap();
+        assert_eq!(loaded.bounds, osm.bounds);
+        assert_eq!(loaded.nodes, osm.nodes);
+        assert_eq!(loaded.ways, osm.ways);
+        assert_eq!(loaded.relations, osm.relations);
+
+        fs::remove_file(&path).unwrap();
+    }
+}
+
+#[test]
+fn query_snapshot_without_loading() {
+    let osm = parse("relations.osm");
+    let path = temp_path("query");
+    osm.save_snapshot(&path).unwrap();
+    let snapshot = Snapshot::open(&path).unwrap();
+
+    assert_eq!(snapshot.node_count(), osm.nodes.len());
+    assert_eq!(snapshot.way_count(), osm.ways.len());
+    assert_eq!(snapshot.relation_count(), osm.relations.len());
+    assert_eq!(snapshot.elements().count(), osm.elements().count());
+
+    for (id, node) in &osm.nodes {
+        let view = snapshot.node(*id).unwrap();
+        assert_eq!(view.lat(), node.lat);
+        assert_eq!(view.lon(), node.lon);
+        assert_eq!(&view.to_node(), node);
+    }
+    for (id, way) in &osm.ways {
+        let view = snapshot.way(*id).unwrap();
+        assert_eq!(view.nodes().count(), way.nodes.len());
+        assert_eq!(view.tags().len(), way.tags.len());
+        for tag in way.tags.iter() {
+            assert_eq!(view.tags().get(&tag.key), Some(tag.val.as_str()));
+        }
+        assert_eq!(view.meta(), way.meta);
+    }
+    for (id, relation) in &osm.relations {
+        let view = snapshot.get(ElementId::Relation(*id)).unwrap();
+        assert_eq!(view.to_element(), osm::Element::Relation(relation.clone()));
+    }
+    assert!(snapshot.node(1234567).is_none());
+
+    let ids: Vec<_> = snapshot.ways().map(|way| way.id()).collect();
+    let mut sorted = ids.clone();
+    sorted.sort();
+    assert_eq!(ids, sorted);
+
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn corrupt_snapshots_are_rejected() {
+    let osm = parse("way.osm");
+    let path = temp_path("corrupt");
+    osm.save_snapshot(&path).unwrap();
+    let data = fs::read(&path).unwrap();
+
+    let mut flipped = data.clone();
+    let last = flipped.len() - 1;
+    flipped[last] ^= 1;
+    fs::write(&path, &flipped).unwrap();
+    let snapshot = Snapshot::open(&path).unwrap();
+    match snapshot.verify() {
+        Err(Error::MalformedSnapshot(ErrorReason::ChecksumMismatch)) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+    drop(snapshot);
+    assert!(OSM::load_snapshot(&path).is_err());
+
+    let mut version = data.clone();
+    version[8] = 99;
+    fs::write(&path, &version).unwrap();
+    match Snapshot::open(&path) {
+        Err(Error::MalformedSnapshot(ErrorReason::UnsupportedVersion(99))) => {}
+        other => panic!("Unexpected result {:?}", other),
+    }
+
+    fs::write(&path, &data[..data.len() - 1]).unwrap();
+    assert!(Snapshot::open(&path).is_err());
+    fs::write(&path, b"<osm></osm>").unwrap();
+    assert!(Snapshot::open(&path).is_err());
+
+    fs::remove_file(&path).unwrap();
+}
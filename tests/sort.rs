@@ -0,0 +1,160 @@
+extern crate osm_xml as osm;
+
+use std::env;
+use std::fs::{self, File};
+
+use osm::sort::{self, ExternalSort, SortViolation};
+use osm::{Element, ElementId, ElementReader, OSMElement, OSM};
+
+fn run_files(dir: &std::path::Path) -> usize {
+    fs::read_dir(dir)
+        .map(|entries| entries.filter(|entry| entry.is_ok()).count())
+        .unwrap_or(0)
+}
+
+fn unsorted_elements() -> Vec<Element> {
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let mut elements: Vec<Element> = ElementReader::new(f).map(Result::unwrap).collect();
+    elements.reverse();
+    elements
+}
+
+#[test]
+fn sort_with_spilled_runs() {
+    let elements = unsorted_elements();
+    let mut osm = OSM::parse(File::open("./tests/test_data/relations.osm").unwrap()).unwrap();
+    osm.bounds = None;
+    let mut expected: Vec<Element> = osm.elements().map(|element| element.to_element()).collect();
+    expected.sort_by_key(|element| element.element_id());
+
+    let dir = env::temp_dir().join(format!("osm-xml-sort-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    for run_size in [1, 2, 5, 1000].iter() {
+        let sorter = ExternalSort {
+            run_size: *run_size,
+            temp_dir: dir.clone(),
+            ..ExternalSort::default()
+        };
+        let sorted = sorter.sort(elements.clone().into_iter().map(Ok)).unwrap();
+        if *run_size < elements.len() {
+            assert!(run_files(&dir) > 0);
+        }
+
+        let sorted: Vec<Element> = sorted.map(Result::unwrap).collect();
+        assert_eq!(sorted, expected);
+        assert_eq!(run_files(&dir), 0);
+    }
+
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn duplicates_keep_the_last_element() {
+    let mut elements = unsorted_elements();
+    let mut changed = match elements[0].clone() {
+        Element::Relation(relation) => relation,
+        _ => panic!("expected relation"),
+    };
+    changed.members.clear();
+    elements.push(Element::Relation(changed.clone()));
+
+    let sorter = ExternalSort {
+        run_size: 3,
+        ..ExternalSort::default()
+    };
+    let sorted: Vec<Element> = sorter
+        .sort(elements.into_iter().map(Ok))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+
+    let ids: Vec<ElementId> = sorted.iter().map(|element| element.element_id()).collect();
+    assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+    assert!(sorted.contains(&Element::Relation(changed)));
+}
+
+#[test]
+fn merge_in_passes() {
+    let mut elements = unsorted_elements();
+    let mut changed = match elements[0].clone() {
+        Element::Relation(relation) => relation,
+        _ => panic!("expected relation"),
+    };
+    changed.members.clear();
+    elements.push(Element::Relation(changed.clone()));
+
+    let mut expected = elements.clone();
+    expected.reverse();
+    expected.sort_by_key(|element| element.element_id());
+    expected.dedup_by_key(|element| element.element_id());
+
+    let dir = env::temp_dir().join(format!("osm-xml-sort-passes-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+
+    for fan_in in [2, 3, 64].iter() {
+        let sorter = ExternalSort {
+            run_size: 1,
+            temp_dir: dir.clone(),
+            fan_in: *fan_in,
+        };
+        let sorted = sorter.sort(elements.clone().into_iter().map(Ok)).unwrap();
+        assert!(run_files(&dir) <= *fan_in);
+
+        let sorted: Vec<Element> = sorted.map(Result::unwrap).collect();
+        assert_eq!(sorted, expected);
+        assert!(sorted.contains(&Element::Relation(changed.clone())));
+        assert_eq!(run_files(&dir), 0);
+    }
+
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[test]
+fn input_errors_end_sorting() {
+    let doc = r#"<osm>
+        <node id="2" lat="1.0" lon="1.0"/>
+        <node id="1" lat="1.0" lon="1.0"></way>
+      </osm>"#;
+
+    let sorter = ExternalSort {
+        run_size: 1,
+        ..ExternalSort::default()
+    };
+    assert!(sorter.sort(ElementReader::new(doc.as_bytes())).is_err());
+}
+
+#[test]
+fn check_sorted_files() {
+    for file in ["bounds.osm", "relation_cycle.osm", "two_nodes.osm"].iter() {
+        let f = File::open(format!("./tests/test_data/{}", file)).unwrap();
+        assert_eq!(sort::check_sorted(f).unwrap(), None);
+    }
+
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let violation = sort::check_sorted(f).unwrap().unwrap();
+    assert_eq!(violation.id, ElementId::Node(391457958));
+    assert_eq!(violation.previous, ElementId::Node(1375985764));
+    assert_eq!(violation.index, 3);
+
+    let doc = r#"<osm>
+        <bounds minlat="1" minlon="1" maxlat="2" maxlon="2"/>
+        <node id="1" lat="1.0" lon="1.0"/>
+        <node id="3" lat="1.0" lon="1.0"/>
+        <way id="1"><nd ref="3"/><nd ref="1"/></way>
+        <node id="2" lat="1.0" lon="1.0"/>
+      </osm>"#;
+    let violation = sort::check_sorted(doc.as_bytes()).unwrap().unwrap();
+    assert_eq!(
+        violation,
+        SortViolation {
+            id: ElementId::Node(2),
+            previous: ElementId::Way(1),
+            offset: doc.find("<node id=\"2\"").unwrap() as u64,
+            index: 3,
+        }
+    );
+
+    let duplicate = r#"<osm><node id="1" lat="1" lon="1"/><node id="1" lat="1" lon="1"/></osm>"#;
+    assert_eq!(sort::check_sorted(duplicate.as_bytes()).unwrap().unwrap().offset, 35);
+}
@@ -0,0 +1,384 @@
+extern crate osm_xml as osm;
+
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use osm::filter::Filter;
+use osm::locations::{LocationAdder, LocationStore};
+use osm::multipolygon::MultipolygonCollector;
+use osm::store::{self, ElementStore, FileStore, StoreLocations};
+use osm::{Element, ElementId, ElementKind, ElementReader, Id, Location, Node, OSMElement,
+          ResolvedOSM, UnresolvedReference, OSM};
+
+fn temp_path(name: &str) -> PathBuf {
+    env::temp_dir().join(format!("osm-xml-store-{}-{}", std::process::id(), name))
+}
+
+fn parse(file: &str) -> OSM {
+    OSM::parse(File::open(format!("./tests/test_data/{}", file)).unwrap()).unwrap()
+}
+
+fn fill(store: &mut FileStore, osm: &OSM) {
+    for element in osm.elements() {
+        assert_eq!(store.insert(element.to_element()).unwrap(), None);
+    }
+}
+
+#[test]
+fn file_store_matches_document() {
+    let osm = parse("relations.osm");
+    let path = temp_path("matches");
+    let mut store = FileStore::create(&path).unwrap();
+    fill(&mut store, &osm);
+    assert_eq!(store.len(), osm.elements().count());
+
+    for element in osm.elements() {
+        let id = element.element_id();
+        assert_eq!(store.element(id).unwrap(), Some(element.to_element()));
+        assert!(store.contains(id).unwrap());
+    }
+    assert_eq!(store.element(ElementId::Node(1234567)).unwrap(), None);
+    assert_eq!(store.nodes().count(), osm.nodes.len());
+
+    let way = osm.ways.values().next().unwrap();
+    let node_ref = way.nodes[0];
+    assert_eq!(
+        store.referenced(&node_ref).unwrap(),
+        ElementStore::referenced(&osm, &node_ref).unwrap()
+    );
+    assert_eq!(
+        store::way_locations(&store, way).unwrap(),
+        store::way_locations(&osm, way).unwrap()
+    );
+
+    let relation = osm.relations.values().next().unwrap();
+    assert_eq!(
+        store::member_locations(&store, relation).unwrap(),
+        store::member_locations(&osm, relation).unwrap()
+    );
+
+    drop(store);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn file_store_reopen_and_compact() {
+    let osm = parse("way.osm");
+    let path = temp_path("reopen");
+    let mut store = FileStore::create(&path).unwrap();
+    fill(&mut store, &osm);
+
+    let id = *osm.nodes.keys().next().unwrap();
+    let moved = Node {
+        lat: 1.0,
+        lon: 2.0,
+        ..osm.nodes[&id].clone()
+    };
+    let previous = store.insert(Element::Node(moved.clone())).unwrap();
+    assert_eq!(previous, Some(Element::Node(osm.nodes[&id].clone())));
+    let way_id = *osm.ways.keys().next().unwrap();
+    assert!(store.remove(ElementId::Way(way_id)).unwrap().is_some());
+    assert_eq!(store.remove(ElementId::Way(way_id)).unwrap(), None);
+    assert!(store.stale_bytes() > 0);
+    drop(store);
+
+    let mut store = FileStore::open(&path).unwrap();
+    assert_eq!(store.len(), osm.elements().count() - 1);
+    assert_eq!(store.node(id).unwrap().unwrap().into_owned(), moved);
+    assert_eq!(store.way(way_id).unwrap(), None);
+    let stale = store.stale_bytes();
+    assert!(stale > 0);
+
+    let len = fs::metadata(&path).unwrap().len();
+    store.compact().unwrap();
+    assert_eq!(store.stale_bytes(), 0);
+    assert_eq!(fs::metadata(&path).unwrap().len(), len - stale);
+    assert_eq!(store.node(id).unwrap().unwrap().into_owned(), moved);
+    drop(store);
+
+    let store = FileStore::open(&path).unwrap();
+    assert_eq!(store.len(), osm.elements().count() - 1);
+    assert_eq!(store.ids(ElementKind::Way).count(), osm.ways.len() - 1);
+
+    // Partial record header is cut off
+    let len = fs::metadata(&path).unwrap().len();
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[1, 0, 5]).unwrap();
+    assert_eq!(FileStore::open(&path).unwrap().len(), osm.elements().count() - 1);
+    assert_eq!(fs::metadata(&path).unwrap().len(), len);
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(format!("{}.idx", path.display())).unwrap();
+}
+
+#[test]
+fn file_store_index() {
+    let osm = parse("relations.osm");
+    let path = temp_path("index");
+    let index = PathBuf::from(format!("{}.idx", path.display()));
+    let mut store = FileStore::create(&path).unwrap();
+    fill(&mut store, &osm);
+    store.flush().unwrap();
+    assert!(index.exists());
+
+    // Changes after the index was written are read from the end of the file
+    let node = osm.nodes.values().next().unwrap();
+    store.remove(ElementId::Node(node.id)).unwrap();
+    let added = Node {
+        id: -1,
+        ..node.clone()
+    };
+    store.insert(Element::Node(added.clone())).unwrap();
+    let stale = store.stale_bytes();
+    drop(store);
+
+    let store = FileStore::open(&path).unwrap();
+    assert_eq!(store.len(), osm.elements().count());
+    assert_eq!(store.stale_bytes(), stale);
+    assert!(!store.contains(ElementId::Node(node.id)).unwrap());
+    assert_eq!(store.node(-1).unwrap().unwrap().into_owned(), added);
+    for element in osm.elements() {
+        let id = element.element_id();
+        if id != ElementId::Node(node.id) {
+            assert_eq!(store.element(id).unwrap(), Some(element.to_element()));
+        }
+    }
+
+    let mut ids: Vec<Id> = store.ids(ElementKind::Node).collect();
+    let mut expected: Vec<Id> = osm.nodes.keys().cloned().filter(|&id| id != node.id).collect();
+    expected.push(-1);
+    ids.sort();
+    expected.sort();
+    assert_eq!(ids, expected);
+    drop(store);
+
+    // Index of an earlier store in the same place is not used
+    drop(FileStore::create(&path).unwrap());
+    let store = FileStore::open(&path).unwrap();
+    assert!(store.is_empty());
+    assert_eq!(store.ids(ElementKind::Node).count(), 0);
+    drop(store);
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&index).unwrap();
+}
+
+#[test]
+fn extraction_from_any_store() {
+    let osm = parse("relations.osm");
+    let path = temp_path("extract");
+    let mut store = FileStore::create(&path).unwrap();
+    fill(&mut store, &osm);
+
+    let ids: Vec<ElementId> = osm.relations.keys().map(|&id| ElementId::Relation(id)).collect();
+    assert_eq!(
+        store::descendants(&store, ids.iter().cloned()).unwrap(),
+        osm.descendants(ids.iter().cloned())
+    );
+    assert_eq!(
+        store::children(&store, ids.iter().cloned()).unwrap(),
+        osm.children(ids.iter().cloned())
+    );
+
+    let nodes: Vec<ElementId> = osm.nodes.keys().map(|&id| ElementId::Node(id)).collect();
+    assert_eq!(
+        store::parents(&store, nodes.iter().cloned()).unwrap(),
+        osm.parents(nodes.iter().cloned())
+    );
+    assert_eq!(
+        store::ancestors(&store, nodes.iter().cloned()).unwrap(),
+        osm.ancestors(nodes.iter().cloned())
+    );
+
+    let extract = store::extract(&store, &ids).unwrap();
+    assert_eq!(extract.relations, osm.subset(&ids).relations);
+
+    let filter: Filter = "r/type".parse().unwrap();
+    let from_store = filter.extract_with_references(&store).unwrap();
+    let applied = filter.apply_with_references(&osm);
+    assert_eq!(from_store.nodes, applied.nodes);
+    assert_eq!(from_store.ways, applied.ways);
+    assert_eq!(from_store.relations, applied.relations);
+    assert_eq!(filter.extract(&osm).unwrap().relations, filter.apply(&osm).relations);
+
+    drop(store);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn locations_of_missing_nodes() {
+    let mut osm = OSM::parse(&b"<osm></osm>"[..]).unwrap();
+    ElementStore::insert(
+        &mut osm,
+        Element::Node(Node {
+            id: 1,
+            lat: 60.0,
+            lon: 25.0,
+            tags: Default::default(),
+            meta: Default::default(),
+        }),
+    ).unwrap();
+
+    let way = osm::Way {
+        id: 1,
+        tags: Default::default(),
+        nodes: vec![UnresolvedReference::Node(1), UnresolvedReference::Node(2)],
+        meta: Default::default(),
+        geometry: Default::default(),
+    };
+    assert_eq!(
+        store::way_locations(&osm, &way).unwrap(),
+        vec![Some(Location { lat: 60.0, lon: 25.0 }), None]
+    );
+}
+
+#[test]
+fn resolve_from_file_store() {
+    let osm = parse("relations.osm");
+    let path = temp_path("resolve");
+    let mut store = FileStore::create(&path).unwrap();
+    fill(&mut store, &osm);
+
+    let from_store = ResolvedOSM::new(&store).unwrap();
+    let resolved = osm.resolve();
+    let mut missing = from_store.missing().to_vec();
+    let mut expected = resolved.missing().to_vec();
+    missing.sort_by_key(|missing| missing.parent);
+    expected.sort_by_key(|missing| missing.parent);
+    assert_eq!(missing, expected);
+
+    for way in resolved.ways() {
+        assert_eq!(from_store.way(way.way.id).unwrap().nodes, way.nodes);
+        for node_ref in &way.way.nodes {
+            assert_eq!(
+                store::resolve_reference(&store, node_ref).unwrap(),
+                store::StoredReference::from(osm.resolve_reference(node_ref))
+            );
+        }
+    }
+    for relation in resolved.relations() {
+        let id = relation.relation.id;
+        assert_eq!(from_store.relation(id).unwrap().members.len(), relation.members.len());
+        assert_eq!(from_store.is_cyclic(id), resolved.is_cyclic(id));
+    }
+
+    drop(from_store);
+    drop(store);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn node_locations_from_store() {
+    let osm = parse("relations.osm");
+    let path = temp_path("locations");
+    let mut store = FileStore::create(&path).unwrap();
+    fill(&mut store, &osm);
+
+    let mut collector = MultipolygonCollector::new();
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    collector.read_relations(ElementReader::new(f)).unwrap();
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let multipolygons: Vec<_> = collector
+        .assemble(ElementReader::new(f), StoreLocations(&mut store))
+        .map(|multipolygon| multipolygon.unwrap())
+        .collect();
+    assert_eq!(multipolygons.len(), 1);
+    assert_eq!(multipolygons[0].polygons[0].outer.len(), 9);
+    // Nodes already in the store are not rewritten
+    assert_eq!(store.stale_bytes(), 0);
+
+    let f = File::open("./tests/test_data/relations.osm").unwrap();
+    let mut adder = LocationAdder::new(ElementReader::new(f), StoreLocations(OSM::new()));
+    assert!(adder.by_ref().all(|element| element.is_ok()));
+    let locations = adder.into_store();
+    assert_eq!(locations.0.nodes.len(), osm.nodes.len());
+    let node = osm.nodes.values().next().unwrap();
+    assert_eq!(
+        locations.get(node.id),
+        Some(Location { lat: node.lat, lon: node.lon })
+    );
+    assert!(locations.0.nodes[&node.id].tags.is_empty());
+
+    drop(store);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn many_changes_write_the_index() {
+    let path = temp_path("many");
+    let index = PathBuf::from(format!("{}.idx", path.display()));
+    let mut store = FileStore::create(&path).unwrap();
+    for id in 0..70_000 {
+        let node = Node {
+            id: id,
+            lat: 60.0,
+            lon: id as f64 / 1000.0,
+            tags: Default::default(),
+            meta: Default::default(),
+        };
+        store.insert(Element::Node(node)).unwrap();
+    }
+    assert!(index.exists());
+    drop(store);
+
+    let store = FileStore::open(&path).unwrap();
+    assert_eq!(store.len(), 70_000);
+    for &id in [0, 1, 12_345, 65_535, 65_536, 69_999].iter() {
+        assert_eq!(store.node(id).unwrap().unwrap().lon, id as f64 / 1000.0);
+    }
+    assert_eq!(store.node(70_000).unwrap(), None);
+    assert_eq!(store.node(-1).unwrap(), None);
+    drop(store);
+
+    fs::remove_file(&path).unwrap();
+    fs::remove_file(&index).unwrap();
+}
+
+#[test]
+fn torn_record_is_truncated() {
+    let osm = parse("way.osm");
+    let path = temp_path("torn");
+    let mut store = FileStore::create(&path).unwrap();
+    fill(&mut store, &osm);
+    drop(store);
+
+    let mut last = None;
+    let full = fs::metadata(&path).unwrap().len();
+    for cut in 1..4 {
+        let file = OpenOptions::new().write(true).open(&path).unwrap();
+        file.set_len(full - cut).unwrap();
+        drop(file);
+
+        // The last element is lost, the rest of the store stays usable
+        let store = FileStore::open(&path).unwrap();
+        assert_eq!(store.len(), osm.elements().count() - 1);
+        let len = fs::metadata(&path).unwrap().len();
+        assert!(len < full - cut);
+        if let Some(last) = last {
+            assert_eq!(len, last);
+        }
+        last = Some(len);
+        drop(store);
+
+        // Restore the record for the next cut
+        let mut store = FileStore::open(&path).unwrap();
+        for element in osm.elements() {
+            if !store.contains(element.element_id()).unwrap() {
+                store.insert(element.to_element()).unwrap();
+            }
+        }
+        assert_eq!(fs::metadata(&path).unwrap().len(), full);
+    }
+
+    let mut store = FileStore::open(&path).unwrap();
+    assert_eq!(store.len(), osm.elements().count());
+    for element in osm.elements() {
+        assert_eq!(store.element(element.element_id()).unwrap(), Some(element.to_element()));
+    }
+    assert!(store.insert(osm.elements().next().unwrap().to_element()).is_ok());
+    drop(store);
+
+    fs::remove_file(&path).unwrap();
+}
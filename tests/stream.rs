@@ -0,0 +1,150 @@
+#![cfg(feature = "tokio")]
+
+extern crate futures_core;
+extern crate osm_xml as osm;
+extern crate tokio;
+
+use std::fs::File;
+use std::future::{self, Future};
+use std::io::{self, Read};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_core::Stream;
+use osm::{Element, ElementStream, ParseOptions, ReaderBuilder, Symbols, OSM};
+use tokio::io::{AsyncRead, ReadBuf};
+
+/// Returns a few bytes at a time, every other read is not ready yet.
+struct Trickle {
+    data: Vec<u8>,
+    pos: usize,
+    step: usize,
+    ready: bool,
+}
+
+impl AsyncRead for Trickle {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        self.ready = !self.ready;
+        if !self.ready {
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+
+        let end = (self.pos + self.step).min(self.data.len());
+        buf.put_slice(&self.data[self.pos..end]);
+        self.pos = end;
+        Poll::Ready(Ok(()))
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+fn collect<R: AsyncRead + Unpin>(source: R) -> Vec<Result<Element, osm::error::Error>> {
+    let mut stream = ElementStream::new(source);
+    block_on(async_collect(&mut stream))
+}
+
+fn async_collect<'a, R: AsyncRead + Unpin>(
+    stream: &'a mut ElementStream<R>,
+) -> impl Future<Output = Vec<Result<Element, osm::error::Error>>> + 'a {
+    let mut elements = Vec::new();
+    future::poll_fn(move |cx| loop {
+        match Pin::new(&mut *stream).poll_next(cx) {
+            Poll::Ready(Some(element)) => elements.push(element),
+            Poll::Ready(None) => return Poll::Ready(::std::mem::take(&mut elements)),
+            Poll::Pending => return Poll::Pending,
+        }
+    })
+}
+
+fn read(path: &str) -> Vec<u8> {
+    let mut data = Vec::new();
+    File::open(path).unwrap().read_to_end(&mut data).unwrap();
+    data
+}
+
+#[test]
+fn stream_elements_in_document_order() {
+    let data = read("./tests/test_data/way.osm");
+    let expected: Vec<Element> = osm::ElementReader::new(&data[..])
+        .map(Result::unwrap)
+        .collect();
+
+    for step in [1, 7, 100, 100_000].iter() {
+        let elements: Vec<Element> = collect(Trickle {
+            data: data.clone(),
+            pos: 0,
+            step: *step,
+            ready: false,
+        }).into_iter()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(elements, expected);
+    }
+}
+
+#[test]
+fn parse_async_same_as_parse() {
+    let files = ["bounds.osm", "overpass_geom.osm", "relations.osm", "way.osm"];
+
+    for file in files.iter() {
+        let data = read(&format!("./tests/test_data/{}", file));
+        let expected = OSM::parse(&data[..]).unwrap();
+        let osm = block_on(OSM::parse_async(&data[..])).unwrap();
+
+        assert_eq!(osm.bounds, expected.bounds);
+        assert_eq!(osm.nodes, expected.nodes);
+        assert_eq!(osm.ways, expected.ways);
+        assert_eq!(osm.relations, expected.relations);
+        assert_eq!(osm.areas, expected.areas);
+        assert_eq!(osm.counts, expected.counts);
+    }
+}
+
+#[test]
+fn errors_end_the_stream() {
+    let doc = r#"<osm>
+        <node id="1" lat="1.0" lon="1.0"/>
+        <node id="2" lat="1.0" lon="1.0"></way>
+        <node id="3" lat="1.0" lon="1.0"/>
+      </osm>"#;
+
+    let elements = collect(doc.as_bytes());
+    assert_eq!(elements.len(), 2);
+    assert!(elements[0].is_ok());
+    assert!(elements[1].is_err());
+
+    assert!(block_on(OSM::parse_async(doc.as_bytes())).is_err());
+}
+
+#[test]
+fn stream_with_options() {
+    let data = read("./tests/test_data/way.osm");
+    let builder = ReaderBuilder::new()
+        .options(ParseOptions {
+            untagged_nodes: false,
+            ..ParseOptions::default()
+        })
+        .symbols(Symbols::new());
+
+    let expected = builder.parse(&data[..]).unwrap();
+    let osm = block_on(builder.parse_async(Trickle {
+        data: data,
+        pos: 0,
+        step: 100,
+        ready: false,
+    })).unwrap();
+
+    assert_eq!(osm.nodes, expected.nodes);
+    assert_eq!(osm.ways, expected.ways);
+    assert_eq!(osm.symbols.len(), expected.symbols.len());
+}